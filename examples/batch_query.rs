@@ -40,8 +40,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     let object_type = object_type.unwrap_or("SalesReceipt".to_string());
 
-    let client = ureq::Agent::new_with_defaults();
-    let mut qb = QBContext::new_from_env(quick_oxibooks::Environment::PRODUCTION, &client)?;
+    let mut qb = QBContext::new_from_env(quick_oxibooks::Environment::PRODUCTION)?;
 
     if let Some(token) = access_token {
         qb = qb.with_access_token(token);
@@ -53,7 +52,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             r"select * from {object_type} where DocNumber = '{num}'"
         ))));
     }
-    let batch_resp = batch_items.batch(&qb, &client)?;
+    let batch_resp = batch_items.batch(&qb)?;
     for (op, item) in batch_resp {
         match item {
             quick_oxibooks::batch::QBBatchResponseData::QueryResponse(qr) => {
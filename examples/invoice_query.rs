@@ -18,10 +18,9 @@ fn main() -> Result<(), APIError> {
         _ => panic!("Invalid environment"),
     };
 
-    let client = ureq::Agent::new_with_defaults();
-    let qb = QBContext::new(environment, company_id, access_token, &client)?;
+    let qb = QBContext::new(environment, company_id, access_token)?;
 
-    let inv = Invoice::query_single(&format!(r"where DocNumber = '{doc_number}'"), &qb, &client)?;
+    let inv = Invoice::query_single(&format!(r"where DocNumber = '{doc_number}'"), &qb)?;
 
     println!("{inv:?}");
 
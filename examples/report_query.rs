@@ -2,11 +2,9 @@ use quick_oxibooks::functions::reports::QBReport;
 use quick_oxibooks::{Environment, QBContext};
 use quickbooks_types::reports::types::ProfitAndLoss;
 use quickbooks_types::reports::Report;
-use ureq::Agent;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Example usage of the QBReport trait
-    let client = Agent::new_with_defaults();
 
     let args = std::env::args().collect::<Vec<_>>();
 
@@ -18,11 +16,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.get(2)
             .cloned()
             .ok_or_else(|| "Missing access token".to_string())?,
-        &client,
     )?;
 
     // Fetch Profit and Loss report
-    match Report::get(&qb_context, &client, &ProfitAndLoss, None) {
+    match Report::get(&qb_context, &ProfitAndLoss, None) {
         Ok(report) => println!(
             "Successfully fetched report: {}",
             serde_json::to_string_pretty(&report)?
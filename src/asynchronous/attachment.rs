@@ -0,0 +1,109 @@
+//! Uploading attachments through the async client.
+//!
+//! See [`crate::functions::attachment`] for the blocking counterpart and the upload
+//! requirements.
+
+use std::future::Future;
+
+use quickbooks_types::{Attachable, QBAttachable};
+use reqwest::Client;
+use ureq::http::StatusCode;
+
+use crate::{
+    error::APIErrorInner,
+    functions::{
+        attachment::{make_upload_request, uploaded_attachable, AttachableResponseExt},
+        retry::retry_after,
+    },
+    APIResult, QBContext,
+};
+
+/// Trait for uploading file attachments through the async client.
+///
+/// The async counterpart of [`crate::functions::attachment::QBUpload`], implemented
+/// for [`Attachable`]. The file is read without blocking the runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::attachment::QBUpload;
+/// use quickbooks_types::Attachable;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let mut attachment = Attachable::default();
+/// attachment.file_name = Some("receipt_123.pdf".to_string());
+/// attachment.note = Some("Restaurant receipt for business lunch".to_string());
+/// let uploaded = attachment.upload(&qb_context, &client).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `AttachableUploadMissingItems`: Missing required fields (`file_name` or note)
+/// - `IoError`: File reading errors
+/// - `ReqwestError`: Network or HTTP errors during upload
+/// - `BadRequest`: `QuickBooks` rejected the attachment (size, type, etc.)
+/// - `NoAttachableObjects`: Upload succeeded but no attachment in response
+pub trait QBUpload {
+    /// Uploads the attachment
+    /// returns an error if the attachment is not suitable for upload
+    /// or if the request itself fails
+    fn upload(
+        &self,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Self>> + Send
+    where
+        Self: Sized;
+}
+
+impl QBUpload for Attachable {
+    async fn upload(&self, qb: &QBContext, client: &Client) -> APIResult<Self> {
+        self.can_upload()?;
+        let file_content = tokio::fs::read(self.file_path().unwrap()).await?;
+
+        qb.refresh_if_expiring_async(client).await?;
+        let send = |access_token: &str| {
+            let request = make_upload_request(self, &file_content, qb, access_token);
+            async move {
+                let slot = qb.acquire_permission(false).await?;
+                let response =
+                    super::send(&qb.transport, client, request?.map(String::into_bytes)).await;
+                drop(slot);
+                response
+            }
+        };
+        let access_token = qb.access_token();
+        let mut response = send(&access_token).await?;
+        if response.status() == StatusCode::UNAUTHORIZED
+            && qb.refresh_rejected_async(&access_token, client).await?
+        {
+            // Retry once with the refreshed token
+            response = send(&qb.access_token()).await?;
+        }
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            // Handle rate limiting by QuickBooks
+            return Err(APIErrorInner::ThrottleLimitReached {
+                retry_after: retry_after(response.headers()),
+            }
+            .into());
+        }
+        if !response.status().is_success() {
+            return Err(APIErrorInner::BadRequest(serde_json::from_slice(response.body())?).into());
+        }
+        let qb_response: AttachableResponseExt = serde_json::from_slice(response.body())?;
+        uploaded_attachable(qb_response)
+    }
+}
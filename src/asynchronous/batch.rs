@@ -0,0 +1,114 @@
+//! Batch requests through the async client.
+//!
+//! See [`crate::batch`] for the blocking counterpart and the batch operations.
+
+use std::future::Future;
+
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{
+    asynchronous::execute_request,
+    batch::{
+        batch_request, batch_results, BatchResponseExt, QBBatchOperation, QBBatchResponseData,
+    },
+    functions::request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+    APIResult, QBContext,
+};
+
+/// `BatchIterator` trait for executing batch operations through the async client.
+///
+/// The async counterpart of [`crate::batch::BatchIterator`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::batch::BatchIterator;
+/// use quick_oxibooks::batch::QBBatchOperation;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let results = vec![QBBatchOperation::query("SELECT * FROM Invoice MAXRESULTS 10")]
+///     .batch(&qb_context, &client)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the request fails or some items are missing in the response
+pub trait BatchIterator {
+    /// Executes a batch request, see [`crate::batch::BatchIterator::batch`]
+    fn batch<'a>(
+        self,
+        qb: &'a QBContext,
+        client: &'a Client,
+    ) -> impl Future<Output = APIResult<Vec<(QBBatchOperation, QBBatchResponseData)>>> + Send + 'a;
+}
+
+impl<I> BatchIterator for I
+where
+    I: IntoIterator<Item = QBBatchOperation>,
+{
+    fn batch<'a>(
+        self,
+        qb: &'a QBContext,
+        client: &'a Client,
+    ) -> impl Future<Output = APIResult<Vec<(QBBatchOperation, QBBatchResponseData)>>> + Send + 'a
+    {
+        // Collected right away, so the future doesn't have to hold the iterator
+        let batch = qb_batch_with_request_id(self, None, qb, client);
+        async move { batch.await.map(WithRequestId::into_value) }
+    }
+}
+
+/// Executes a batch request with the given request ID, or a new one if `None`
+///
+/// The async counterpart of [`crate::batch::qb_batch_with_request_id`].
+///
+/// # Errors
+///
+/// Returns an error if the request fails or some items are missing in the response
+pub fn qb_batch_with_request_id<'a, I>(
+    items: I,
+    request_id: Option<RequestId>,
+    qb: &'a QBContext,
+    client: &'a Client,
+) -> impl Future<Output = APIResult<WithRequestId<Vec<(QBBatchOperation, QBBatchResponseData)>>>>
+       + Send
+       + 'a
+where
+    I: IntoIterator<Item = QBBatchOperation>,
+{
+    let request_id = request_id.map_or_else(RequestId::new, Ok);
+    let batch = batch_request(items);
+    async move {
+        let request_id = request_id?;
+        let resp = execute_request(
+            qb,
+            client,
+            Method::POST,
+            &format!("company/{}/batch", qb.company_id),
+            Some(&batch),
+            None,
+            Some([(REQUEST_ID_PARAM, request_id.as_str())]),
+            true,
+        )
+        .await?;
+        let batch_resp: BatchResponseExt = serde_json::from_slice(resp.body())?;
+        Ok(WithRequestId {
+            value: batch_results(batch, batch_resp)?,
+            request_id,
+        })
+    }
+}
@@ -0,0 +1,133 @@
+//! Creating `QuickBooks` entities through the async client.
+//!
+//! See [`crate::functions::create`] for the blocking counterpart.
+
+use std::future::Future;
+
+use quickbooks_types::{QBCreatable, QBItem};
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{
+    asynchronous::qb_request,
+    error::APIErrorInner,
+    functions::{
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
+    APIResult, QBContext,
+};
+
+/// Trait for creating `QuickBooks` entities through the async client.
+///
+/// The async counterpart of [`crate::functions::create::QBCreate`], automatically
+/// implemented for all types that implement both [`QBItem`] and [`QBCreatable`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::create::QBCreate;
+/// use quickbooks_types::Customer;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let mut customer = Customer::default();
+/// customer.display_name = Some("John Doe".to_string());
+/// let created_customer = customer.create(&qb_context, &client).await?;
+/// println!("Created customer with ID: {:?}", created_customer.id);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `CreateMissingItems`: Entity is missing required fields
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: `QuickBooks` rejected the entity
+pub trait QBCreate {
+    /// Creates the object in `QuickBooks`, see [`crate::functions::create::QBCreate::create`]
+    fn create(
+        &self,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Self>> + Send
+    where
+        Self: Sized;
+
+    /// Creates the object with the given request ID, or a new one if `None`,
+    /// see [`crate::functions::create::QBCreate::create_with_request_id`]
+    fn create_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<WithRequestId<Self>>> + Send
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem + QBCreatable + Sync> QBCreate for T {
+    async fn create(&self, qb: &QBContext, client: &Client) -> APIResult<Self> {
+        qb_create(self, RequestId::new()?, qb, client)
+            .await
+            .map(WithRequestId::into_value)
+    }
+
+    async fn create_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<WithRequestId<Self>> {
+        qb_create(
+            self,
+            request_id.map_or_else(RequestId::new, Ok)?,
+            qb,
+            client,
+        )
+        .await
+    }
+}
+
+async fn qb_create<T: QBItem + QBCreatable + Sync>(
+    item: &T,
+    request_id: RequestId,
+    qb: &QBContext,
+    client: &Client,
+) -> APIResult<WithRequestId<T>> {
+    if !item.can_create() {
+        return Err(APIErrorInner::CreateMissingItems.into());
+    }
+
+    let response: QBResponse<T> = qb_request(
+        qb,
+        client,
+        Method::POST,
+        &format!("company/{}/{}", qb.company_id, T::qb_id()),
+        Some(item),
+        None,
+        Some([(REQUEST_ID_PARAM, request_id.as_str())]),
+    )
+    .await?;
+
+    #[cfg(feature = "logging")]
+    log::info!(
+        "Successfully created {} with ID of '{:?}'",
+        T::name(),
+        response.object.id().into_iter().next()
+    );
+
+    Ok(WithRequestId {
+        value: response.object,
+        request_id,
+    })
+}
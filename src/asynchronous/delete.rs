@@ -0,0 +1,156 @@
+//! Deleting `QuickBooks` entities through the async client.
+//!
+//! See [`crate::functions::delete`] for the blocking counterpart.
+
+use std::future::Future;
+
+use quickbooks_types::{QBDeletable, QBItem};
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{
+    asynchronous::{qb_request, read::qb_get_single},
+    error::APIErrorInner,
+    functions::{
+        conflict::count_conflict_retry,
+        delete::{QBDeleted, QBToDelete, QBToDeleteTrait},
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
+    APIResult, QBContext,
+};
+
+/// Trait for deleting `QuickBooks` entities through the async client.
+///
+/// The async counterpart of [`crate::functions::delete::QBDelete`], automatically
+/// implemented for all types that implement both [`QBItem`] and [`QBDeletable`].
+/// Stale `SyncToken` conflicts are recovered from according to the context's
+/// [`ConflictPolicy`](crate::functions::conflict::ConflictPolicy).
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::{delete::QBDelete, read::qb_get_single};
+/// use quickbooks_types::Invoice;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let invoice: Invoice = qb_get_single("123", &qb_context, &client).await?;
+/// let deleted = invoice.delete(&qb_context, &client).await?;
+/// println!("Deleted invoice {} with status {}", deleted.id, deleted.status);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `DeleteMissingItems`: Entity has no ID or sync token
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: `QuickBooks` rejected the deletion
+/// - `ConflictRetriesExhausted`: Stale `SyncToken` conflict persisted after retrying
+pub trait QBDelete {
+    /// Deletes the object from `QuickBooks`
+    fn delete(
+        &self,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<QBDeleted>> + Send
+    where
+        Self: Sized;
+
+    /// Deletes the object with the given request ID, or a new one if `None`,
+    /// see [`crate::functions::delete::QBDelete::delete_with_request_id`]
+    fn delete_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<WithRequestId<QBDeleted>>> + Send
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem + QBDeletable + Sync> QBDelete for T {
+    async fn delete(&self, qb: &QBContext, client: &Client) -> APIResult<QBDeleted> {
+        qb_delete(self, RequestId::new()?, qb, client)
+            .await
+            .map(WithRequestId::into_value)
+    }
+
+    async fn delete_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<WithRequestId<QBDeleted>> {
+        qb_delete(
+            self,
+            request_id.map_or_else(RequestId::new, Ok)?,
+            qb,
+            client,
+        )
+        .await
+    }
+}
+
+async fn qb_delete<T: QBItem + QBDeletable + Sync>(
+    item: &T,
+    mut request_id: RequestId,
+    qb: &QBContext,
+    client: &Client,
+) -> APIResult<WithRequestId<QBDeleted>> {
+    let (Some(_), Some(id)) = (item.sync_token(), item.id()) else {
+        return Err(APIErrorInner::DeleteMissingItems.into());
+    };
+
+    let mut latest: Option<T> = None;
+    let mut retries = 0;
+    loop {
+        let delete_object: QBToDelete = latest.as_ref().unwrap_or(item).to_delete();
+        let response: APIResult<QBResponse<QBDeleted>> = qb_request(
+            qb,
+            client,
+            Method::POST,
+            &format!("company/{}/{}", qb.company_id, T::qb_id()),
+            Some(&delete_object),
+            None,
+            Some([
+                ("operation", "delete"),
+                (REQUEST_ID_PARAM, request_id.as_str()),
+            ]),
+        )
+        .await;
+
+        match response {
+            Ok(response) => {
+                #[cfg(feature = "logging")]
+                log::info!(
+                    "Successfully deleted {} with ID of {}",
+                    T::name(),
+                    delete_object.id
+                );
+
+                return Ok(WithRequestId {
+                    value: response.object,
+                    request_id,
+                });
+            }
+            Err(e) if e.is_stale_object() => {
+                count_conflict_retry::<T>(id, qb, &mut retries, e)?;
+                latest = Some(qb_get_single(id, qb, client).await?);
+                // A different request, which QuickBooks must not deduplicate
+                request_id = RequestId::new()?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Async API on `reqwest`, enabled with the `async` feature.
+//!
+//! The traits in this module mirror the blocking ones in [`crate::functions`] and
+//! [`crate::batch`], taking a [`reqwest::Client`] instead of a [`ureq::Agent`] and
+//! returning futures. They use the same [`QBContext`]: auto-refresh, the retry and
+//! conflict policies and the limit timeout work the same way.
+//!
+//! Requests share the rate and concurrency limits of the company with blocking
+//! requests, but wait for them by yielding to the runtime instead of blocking the
+//! thread. They have to run on a Tokio runtime.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use quick_oxibooks::{QBContext, Environment};
+//! use quick_oxibooks::asynchronous::{create::QBCreate, query::QBQuery};
+//! use quickbooks_types::Customer;
+//!
+//! # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+//! let client = reqwest::Client::new();
+//! let qb_context = QBContext::new_async(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//!     &client,
+//! )
+//! .await?;
+//!
+//! let mut customer = Customer::default();
+//! customer.display_name = Some("John Doe".to_string());
+//! let created = customer.create(&qb_context, &client).await?;
+//!
+//! let customers = Customer::query("WHERE Active = true", Some(10), &qb_context, &client).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{ops::ControlFlow, sync::Arc};
+
+use reqwest::Client;
+use serde::Serialize;
+use ureq::http::{Method, Request, Response};
+
+use crate::{
+    client::Transport,
+    functions::{check_attempt, is_unauthorized, request_id::REQUEST_ID_PARAM},
+    APIResult, QBContext,
+};
+
+#[cfg(feature = "attachments")]
+pub mod attachment;
+pub mod batch;
+pub mod create;
+pub mod delete;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod query;
+pub mod read;
+pub mod reports;
+
+/// Sends a request to the `QuickBooks` API endpoint with the given parameters,
+/// accounts for rate limiting
+///
+/// The async counterpart of [`crate::functions::qb_request`].
+pub(crate) async fn qb_request<S, SS, T, U>(
+    qb: &QBContext,
+    client: &Client,
+    method: Method,
+    path: &str,
+    body: Option<&T>,
+    content_type: Option<&str>,
+    query: Option<impl IntoIterator<Item = (S, SS)> + Send>,
+) -> APIResult<U>
+where
+    T: Serialize + Sync,
+    U: serde::de::DeserializeOwned,
+    S: AsRef<str> + Send,
+    SS: AsRef<str> + Send,
+{
+    let response =
+        execute_request(qb, client, method, path, body, content_type, query, false).await?;
+    Ok(serde_json::from_slice(response.body())?)
+}
+
+/// Sends a request to the `QuickBooks` API endpoint, returning the response with
+/// its body read
+///
+/// The async counterpart of [`crate::functions::execute_request`], acquiring
+/// permits, refreshing and retrying the same way.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn execute_request<S, SS, T>(
+    qb: &QBContext,
+    client: &Client,
+    method: Method,
+    path: &str,
+    body: Option<&T>,
+    content_type: Option<&str>,
+    query: Option<impl IntoIterator<Item = (S, SS)> + Send>,
+    batch: bool,
+) -> APIResult<Response<Vec<u8>>>
+where
+    T: Serialize + Sync,
+    S: AsRef<str> + Send,
+    SS: AsRef<str> + Send,
+{
+    let query: Option<Vec<(S, SS)>> = query.map(|q| q.into_iter().collect());
+    qb.refresh_if_expiring_async(client).await?;
+
+    // Retrying a POST could apply it twice, unless QuickBooks can deduplicate it
+    let idempotent = method != Method::POST
+        || query
+            .iter()
+            .flatten()
+            .any(|(k, _)| k.as_ref() == REQUEST_ID_PARAM);
+    let policy = qb.retry_policy.as_ref().filter(|_| idempotent);
+
+    let mut attempt = 1;
+    let mut refreshed = false;
+    loop {
+        let access_token = qb.access_token();
+        let request = crate::client::build_request(
+            &method,
+            path,
+            body,
+            query
+                .as_ref()
+                .map(|q| q.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))),
+            content_type.unwrap_or("application/json"),
+            &qb.endpoints,
+            &access_token,
+        )?;
+        let can_retry = policy.filter(|p| attempt < p.max_attempts);
+
+        let slot = qb.acquire_permission(batch).await?;
+        let outcome = send(&qb.transport, client, request).await;
+        drop(slot);
+        if is_unauthorized(&outcome)
+            && !refreshed
+            && qb.refresh_rejected_async(&access_token, client).await?
+        {
+            refreshed = true;
+            continue;
+        }
+        let (policy, retry_after) = match check_attempt(outcome, can_retry)? {
+            ControlFlow::Break(response) => return Ok(response),
+            ControlFlow::Continue(retry) => retry,
+        };
+
+        let delay = policy.delay(attempt, retry_after);
+
+        #[cfg(feature = "logging")]
+        log::warn!(
+            "Request to {path} failed, retrying in {delay:?} ({attempt}/{})",
+            policy.max_attempts
+        );
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Sends a request through the async client, or through the transport if it
+/// [sends async requests](Transport::sends_async_requests), returning the response
+/// with its body read
+pub(crate) async fn send(
+    transport: &Arc<dyn Transport>,
+    client: &Client,
+    request: Request<Vec<u8>>,
+) -> APIResult<Response<Vec<u8>>> {
+    if transport.sends_async_requests() {
+        let transport = Arc::clone(transport);
+        return tokio::task::spawn_blocking(move || transport.send(request))
+            .await
+            .map_err(std::io::Error::from)?;
+    }
+    read_response(client.execute(request.try_into()?).await?).await
+}
+
+/// Reads the body of a response into the response type of the blocking client
+async fn read_response(response: reqwest::Response) -> APIResult<Response<Vec<u8>>> {
+    let mut builder = Response::builder().status(response.status());
+    if let Some(headers) = builder.headers_mut() {
+        headers.clone_from(response.headers());
+    }
+    let body = response.bytes().await?;
+    Ok(builder.body(body.to_vec())?)
+}
@@ -0,0 +1,112 @@
+//! Fetching PDFs of `QuickBooks` entities through the async client.
+//!
+//! See [`crate::functions::pdf`] for the blocking counterpart.
+
+use std::future::Future;
+
+use quickbooks_types::{QBItem, QBPDFable};
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{asynchronous::execute_request, error::APIErrorInner, APIResult, QBContext};
+
+/// Trait for fetching PDFs of `QuickBooks` entities through the async client.
+///
+/// The async counterpart of [`crate::functions::pdf::QBGetPDF`], automatically
+/// implemented for all types that implement both [`QBItem`] and [`QBPDFable`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::{pdf::QBGetPDF, query::QBQuery};
+/// use quickbooks_types::Invoice;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let invoice = Invoice::query_single("WHERE DocNumber = 'INV-001'", &qb_context, &client)
+///     .await?
+///     .unwrap();
+/// invoice.save_pdf_to_file("invoice_001.pdf", &qb_context, &client).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `NoIdOnGetPDF`: Entity has no ID
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: `QuickBooks` can't generate a PDF of the entity
+/// - `IoError`: Writing the file failed
+pub trait QBGetPDF {
+    /// Fetches the PDF of the object as bytes
+    fn get_pdf_bytes(
+        &self,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Vec<u8>>> + Send
+    where
+        Self: Sized;
+
+    /// Fetches the PDF of the object and saves it to the given file
+    fn save_pdf_to_file(
+        &self,
+        file_name: &str,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<()>> + Send
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem + QBPDFable + Sync> QBGetPDF for T {
+    async fn get_pdf_bytes(&self, qb: &QBContext, client: &Client) -> APIResult<Vec<u8>> {
+        let Some(id) = self.id() else {
+            return Err(APIErrorInner::NoIdOnGetPDF.into());
+        };
+
+        let response = execute_request(
+            qb,
+            client,
+            Method::GET,
+            &format!("company/{}/{}/{}/pdf", qb.company_id, T::qb_id(), id),
+            None::<&()>,
+            None,
+            None::<std::iter::Empty<(&str, &str)>>,
+            false,
+        )
+        .await?;
+
+        #[cfg(feature = "logging")]
+        log::info!("Successfully got PDF of {} with ID : {}", T::name(), id);
+
+        Ok(response.into_body())
+    }
+
+    async fn save_pdf_to_file(
+        &self,
+        file_name: &str,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<()> {
+        let bytes = self.get_pdf_bytes(qb, client).await?;
+        tokio::fs::write(file_name, bytes).await?;
+
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Successfully saved PDF of {} #{} to {}",
+            T::name(),
+            self.id().ok_or(APIErrorInner::NoIdOnGetPDF)?,
+            file_name
+        );
+        Ok(())
+    }
+}
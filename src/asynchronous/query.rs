@@ -0,0 +1,267 @@
+//! Querying `QuickBooks` entities through the async client.
+//!
+//! See [`crate::functions::query`] for the blocking counterpart and the shape of the
+//! query strings.
+
+use std::future::Future;
+
+use quickbooks_types::QBItem;
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{
+    asynchronous::qb_request,
+    functions::{
+        query::{
+            count_query, select_query, PageCursor, QueryResponse, QueryResponseExt, MAX_PAGE_SIZE,
+        },
+        query_builder::QueryBuilder,
+    },
+    APIResult, QBContext,
+};
+
+/// Trait for querying `QuickBooks` entities through the async client.
+///
+/// The async counterpart of [`crate::functions::query::QBQuery`], automatically
+/// implemented for all types that implement [`QBItem`]. The lazy queries return
+/// [`QueryPages`] and [`QueryAll`], which fetch the next page when asked for it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::query::QBQuery;
+/// use quickbooks_types::Invoice;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let unpaid = Invoice::query("WHERE Balance > '0'", Some(10), &qb_context, &client).await?;
+///
+/// let mut invoices = Invoice::query_all("", &qb_context, &client);
+/// while let Some(invoice) = invoices.next_item().await {
+///     println!("{:?}", invoice?.id);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: `QuickBooks` rejected the query
+/// - `InvalidQuery`: A [`QueryBuilder`] contains an invalid field name
+pub trait QBQuery {
+    /// Queries the `QuickBooks` API for objects of type T,
+    /// see [`crate::functions::query::QBQuery::query`]
+    fn query(
+        query_str: &str,
+        max_results: Option<usize>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Vec<Self>>> + Send
+    where
+        Self: Sized;
+
+    /// Queries the `QuickBooks` API for a single object of type T,
+    /// see [`crate::functions::query::QBQuery::query_single`]
+    fn query_single(
+        query_str: &str,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Option<Self>>> + Send
+    where
+        Self: Sized;
+
+    /// Lazily queries the `QuickBooks` API for all objects of type T,
+    /// one page of `page_size` objects per request,
+    /// see [`crate::functions::query::QBQuery::query_pages`]
+    fn query_pages<'a>(
+        query_str: &str,
+        page_size: usize,
+        qb: &'a QBContext,
+        client: &'a Client,
+    ) -> QueryPages<'a, Self>
+    where
+        Self: Sized;
+
+    /// Lazily queries the `QuickBooks` API for all objects of type T,
+    /// one at a time, see [`crate::functions::query::QBQuery::query_all`]
+    fn query_all<'a>(query_str: &str, qb: &'a QBContext, client: &'a Client) -> QueryAll<'a, Self>
+    where
+        Self: Sized;
+
+    /// Counts the objects of type T matching the where clause
+    /// without fetching them
+    fn count(
+        where_clause: &str,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<usize>> + Send
+    where
+        Self: Sized;
+
+    /// Queries the `QuickBooks` API for objects of type T
+    /// using a [`QueryBuilder`] instead of a raw query string
+    fn query_with(
+        query: &QueryBuilder<Self>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<Vec<Self>>> + Send
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem + Sync> QBQuery for T {
+    async fn query(
+        query_str: &str,
+        max_results: Option<usize>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<Vec<Self>> {
+        let query = select_query::<T>(query_str, max_results);
+        Ok(qb_query_response(query, qb, client).await?.items)
+    }
+
+    async fn query_single(
+        query_str: &str,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<Option<Self>> {
+        let mut results = Self::query(query_str, Some(1), qb, client).await?;
+        if results.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(results.swap_remove(0)))
+    }
+
+    fn query_pages<'a>(
+        query_str: &str,
+        page_size: usize,
+        qb: &'a QBContext,
+        client: &'a Client,
+    ) -> QueryPages<'a, Self> {
+        QueryPages {
+            cursor: PageCursor::new(query_str, page_size),
+            qb,
+            client,
+        }
+    }
+
+    fn query_all<'a>(query_str: &str, qb: &'a QBContext, client: &'a Client) -> QueryAll<'a, Self> {
+        QueryAll {
+            pages: Self::query_pages(query_str, MAX_PAGE_SIZE, qb, client),
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    async fn count(where_clause: &str, qb: &QBContext, client: &Client) -> APIResult<usize> {
+        let query = count_query::<T>(where_clause);
+        let response = qb_query_response::<T>(query, qb, client).await?;
+        Ok(response.total_count.unwrap_or_default())
+    }
+
+    async fn query_with(
+        query: &QueryBuilder<Self>,
+        qb: &QBContext,
+        client: &Client,
+    ) -> APIResult<Vec<Self>> {
+        let query = query.build()?;
+        Ok(qb_query_response(query, qb, client).await?.items)
+    }
+}
+
+/// Lazy pages of a `QuickBooks` query, fetched through the async client.
+///
+/// Created by [`QBQuery::query_pages`]. Each call to [`QueryPages::next_page`] sends
+/// one request, like [`crate::functions::query::QueryPages`] does for every item.
+pub struct QueryPages<'a, T> {
+    cursor: PageCursor<T>,
+    qb: &'a QBContext,
+    client: &'a Client,
+}
+
+impl<T: QBItem> QueryPages<'_, T> {
+    /// Fetches the next page, `None` after the last page or the first error
+    pub async fn next_page(&mut self) -> Option<APIResult<Vec<T>>> {
+        let query = self.cursor.next_query()?;
+        let response = qb_query_response::<T>(query, self.qb, self.client).await;
+        self.cursor.advance(response)
+    }
+
+    /// The `totalCount` reported by `QuickBooks` for the most recent page
+    #[must_use]
+    pub fn total_count(&self) -> Option<usize> {
+        self.cursor.total_count
+    }
+
+    /// The 1-based `startPosition` of the most recent page
+    #[must_use]
+    pub fn start_position(&self) -> Option<usize> {
+        self.cursor.start_position
+    }
+}
+
+/// Every entity matching a `QuickBooks` query, fetched through the async client.
+///
+/// Created by [`QBQuery::query_all`]. Fetches pages of up to 1000 entities as the
+/// previous page is used up.
+pub struct QueryAll<'a, T> {
+    pages: QueryPages<'a, T>,
+    current: std::vec::IntoIter<T>,
+}
+
+impl<T: QBItem> QueryAll<'_, T> {
+    /// Returns the next entity, fetching the next page if needed,
+    /// `None` after the last entity or the first error
+    pub async fn next_item(&mut self) -> Option<APIResult<T>> {
+        loop {
+            if let Some(item) = self.current.next() {
+                return Some(Ok(item));
+            }
+            match self.pages.next_page().await? {
+                Ok(page) => self.current = page.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// The `totalCount` reported by `QuickBooks` for the most recent page
+    #[must_use]
+    pub fn total_count(&self) -> Option<usize> {
+        self.pages.total_count()
+    }
+
+    /// The 1-based `startPosition` of the most recent page
+    #[must_use]
+    pub fn start_position(&self) -> Option<usize> {
+        self.pages.start_position()
+    }
+}
+
+async fn qb_query_response<T: QBItem>(
+    query: String,
+    qb: &QBContext,
+    client: &Client,
+) -> APIResult<QueryResponse<T>> {
+    let response: QueryResponseExt<T> = qb_request(
+        qb,
+        client,
+        Method::GET,
+        &format!("company/{}/query", qb.company_id),
+        None::<&()>,
+        None,
+        Some([("query", query.as_str())]),
+    )
+    .await?;
+    #[cfg(feature = "logging")]
+    log::info!("Successfully Queried Raw Data for query string : {query}");
+    Ok(response.query_response)
+}
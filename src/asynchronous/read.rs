@@ -0,0 +1,96 @@
+//! Reading `QuickBooks` entities through the async client.
+//!
+//! See [`crate::functions::read`] for the blocking counterpart.
+
+use std::future::Future;
+
+use quickbooks_types::QBItem;
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{
+    asynchronous::qb_request, error::APIErrorInner, functions::QBResponse, APIResult, QBContext,
+};
+
+/// Trait for reading `QuickBooks` entities through the async client.
+///
+/// The async counterpart of [`crate::functions::read::QBRead`], automatically
+/// implemented for all types that implement [`QBItem`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::read::QBRead;
+/// use quickbooks_types::Customer;
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let mut customer = Customer::default();
+/// customer.id = Some("123".to_string());
+/// customer.read(&qb_context, &client).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `NoIdOnRead`: Entity has no ID
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: The entity doesn't exist or can't be read
+pub trait QBRead {
+    /// Reads the object from `QuickBooks` by its ID, replacing its fields
+    fn read(
+        &mut self,
+        qb: &QBContext,
+        client: &Client,
+    ) -> impl Future<Output = APIResult<()>> + Send;
+}
+
+impl<T: QBItem + Sync> QBRead for T {
+    async fn read(&mut self, qb: &QBContext, client: &Client) -> APIResult<()> {
+        let Some(id) = self.id() else {
+            return Err(APIErrorInner::NoIdOnRead.into());
+        };
+        let object: T = qb_get_single(id, qb, client).await?;
+
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Successfully Read {} object with ID : {}",
+            T::name(),
+            object.id().expect("ID should be present in the response")
+        );
+
+        *self = object;
+        Ok(())
+    }
+}
+
+/// Reads the object of type T with the given ID
+///
+/// The async counterpart of [`crate::functions::read::qb_get_single`].
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the object doesn't exist
+pub async fn qb_get_single<T: QBItem>(id: &str, qb: &QBContext, client: &Client) -> APIResult<T> {
+    let response: QBResponse<T> = qb_request(
+        qb,
+        client,
+        Method::GET,
+        &format!("company/{}/{}/{}", qb.company_id, T::qb_id(), id),
+        None::<&()>,
+        None,
+        None::<std::iter::Empty<(&str, &str)>>,
+    )
+    .await?;
+    Ok(response.object)
+}
@@ -0,0 +1,83 @@
+//! Fetching `QuickBooks` financial reports through the async client.
+//!
+//! See [`crate::functions::reports`] for the blocking counterpart.
+
+use std::future::Future;
+
+use quickbooks_types::reports::{
+    types::{QBReportParams, QBReportType},
+    Report,
+};
+use reqwest::Client;
+use ureq::http::Method;
+
+use crate::{asynchronous::qb_request, functions::reports::report_path, APIResult, QBContext};
+
+/// Trait for retrieving `QuickBooks` financial reports through the async client.
+///
+/// The async counterpart of [`crate::functions::reports::QBReport`], implemented
+/// for [`Report`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::asynchronous::reports::QBReport;
+/// use quickbooks_types::reports::{Report, types::ProfitAndLoss};
+///
+/// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+/// let client = reqwest::Client::new();
+/// let qb_context = QBContext::new_async(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+///     &client,
+/// )
+/// .await?;
+///
+/// let report = Report::get(&qb_context, &client, &ProfitAndLoss, None).await?;
+/// println!("Report name: {:?}", report.header.as_ref().unwrap().report_name);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// - `ReqwestError`: Network or HTTP errors
+/// - `BadRequest`: `QuickBooks` rejected the report parameters
+pub trait QBReport {
+    /// Fetches the report of the given type, with optional parameters
+    fn get<T: QBReportType + Sync>(
+        qb: &QBContext,
+        client: &Client,
+        report_type: &T,
+        params: Option<T::QueryParams>,
+    ) -> impl Future<Output = APIResult<Self>> + Send
+    where
+        T::QueryParams: Send,
+        Self: Sized;
+}
+
+impl QBReport for Report {
+    async fn get<T: QBReportType + Sync>(
+        qb: &QBContext,
+        client: &Client,
+        report_type: &T,
+        params: Option<T::QueryParams>,
+    ) -> APIResult<Self>
+    where
+        T::QueryParams: Send,
+    {
+        qb_request(
+            qb,
+            client,
+            Method::GET,
+            &report_path(qb, report_type),
+            None::<&()>,
+            Some("application/json"),
+            // The params iterator isn't `Send`, so it can't be held across an await
+            params.as_ref().map(|p| p.params().collect::<Vec<_>>()),
+        )
+        .await
+    }
+}
@@ -7,7 +7,7 @@
 //! ## Key Features
 //!
 //! - Execute multiple `QuickBooks` operations in a single API call
-//! - Support for create, update, delete operations on various resource types
+//! - Support for create, update, delete and void operations on various resource types
 //! - Support for query operations to fetch multiple resources at once
 //! - Type-safe API with enum-based resource handling
 //!
@@ -20,7 +20,7 @@
 //! };
 //! use quickbooks_types::{Invoice, Vendor};
 //!
-//! fn batch_example(qb: &QBContext, client: &ureq::Agent) -> Result<(), Box<dyn std::error::Error>> {
+//! fn batch_example(qb: &QBContext) -> Result<(), Box<dyn std::error::Error>> {
 //!     // Create a collection of operations
 //!     let operations = vec![
 //!         // Query for invoices
@@ -41,7 +41,7 @@
 //!     ];
 //!
 //!     // Execute the batch request
-//!     let results = operations.batch(qb, client)?;
+//!     let results = operations.batch(qb)?;
 //!
 //!     // Process the results
 //!     for (operation, response) in results {
@@ -59,11 +59,14 @@ use std::collections::HashMap;
 
 use quickbooks_types::{Invoice, SalesReceipt, Vendor};
 use serde::{Deserialize, Serialize};
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{
     error::{APIError, APIErrorInner, BatchMissingItemsError, Fault},
-    functions::execute_request,
+    functions::{
+        execute_request,
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+    },
     QBContext,
 };
 
@@ -71,7 +74,7 @@ use crate::{
 ///
 /// Internal use only, not meant to be used directly.
 #[derive(Serialize, Deserialize, Debug)]
-struct QBBatchRequest {
+pub(crate) struct QBBatchRequest {
     #[serde(rename = "BatchItemRequest")]
     items: Vec<QBBatchItem<QBBatchOperation>>,
 }
@@ -91,6 +94,7 @@ pub enum QBOperationType {
     Create,
     Update,
     Delete,
+    Void,
 }
 
 /// Represents a batch item in a batch request,
@@ -139,6 +143,14 @@ impl QBBatchOperation {
             operation: QBOperationType::Delete,
         })
     }
+
+    #[must_use]
+    pub fn void(resource: impl Into<QBResource>) -> Self {
+        QBBatchOperation::Operation(QBResourceOperation {
+            resource: resource.into(),
+            operation: QBOperationType::Void,
+        })
+    }
 }
 
 /// Represents a resource in a batch request,
@@ -177,16 +189,18 @@ pub struct QBQueryResult {
 /// Represents the response data for a batch request item.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum QBBatchResponseData {
-    Item(QBResource),
     Fault(Fault),
     QueryResponse(QBQueryResult),
+    /// Entities come back under their type's name, e.g. `"Vendor": { ... }`
+    #[serde(untagged)]
+    Item(QBResource),
 }
 
 /// Represents the response structure for a batch request.
 ///
 /// Internal use only, not meant to be used directly.
 #[derive(Serialize, Deserialize, Debug)]
-struct BatchResponseExt {
+pub(crate) struct BatchResponseExt {
     time: String,
     #[serde(rename = "BatchItemResponse")]
     items: Vec<QBBatchItem<QBBatchResponseData>>,
@@ -204,7 +218,6 @@ pub trait BatchIterator {
     fn batch(
         self,
         qb: &QBContext,
-        client: &Agent,
     ) -> Result<Vec<(QBBatchOperation, QBBatchResponseData)>, APIError>;
 }
 
@@ -215,9 +228,8 @@ where
     fn batch(
         self,
         qb: &QBContext,
-        client: &Agent,
     ) -> Result<Vec<(QBBatchOperation, QBBatchResponseData)>, APIError> {
-        qb_batch(self, qb, client)
+        qb_batch(self, qb)
     }
 }
 
@@ -233,13 +245,55 @@ where
 pub fn qb_batch<I>(
     items: I,
     qb: &QBContext,
-    client: &ureq::Agent,
     // ) -> Result<Vec<QBBatchItem<QBBatchResponseData>>, APIError>
 ) -> Result<Vec<(QBBatchOperation, QBBatchResponseData)>, APIError>
 where
     I: IntoIterator<Item = QBBatchOperation>,
 {
-    let batch = QBBatchRequest {
+    qb_batch_with_request_id(items, None, qb).map(WithRequestId::into_value)
+}
+
+/// Executes a batch request with the given request ID, or a new one if `None`,
+/// so `QuickBooks` runs it only once however many times it is sent.
+///
+/// See [`qb_batch`], returns its results along with the request ID.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or some items are missing in the response
+pub fn qb_batch_with_request_id<I>(
+    items: I,
+    request_id: Option<RequestId>,
+    qb: &QBContext,
+) -> Result<WithRequestId<Vec<(QBBatchOperation, QBBatchResponseData)>>, APIError>
+where
+    I: IntoIterator<Item = QBBatchOperation>,
+{
+    let request_id = request_id.map_or_else(RequestId::new, Ok)?;
+    let batch = batch_request(items);
+    let url = format!("company/{}/batch", qb.company_id);
+    let resp = execute_request(
+        qb,
+        Method::POST,
+        &url,
+        Some(&batch),
+        None,
+        Some([(REQUEST_ID_PARAM, request_id.as_str())]),
+        true,
+    )?;
+    let batch_resp: BatchResponseExt = serde_json::from_slice(resp.body())?;
+    Ok(WithRequestId {
+        value: batch_results(batch, batch_resp)?,
+        request_id,
+    })
+}
+
+/// Numbers the operations of a batch request
+pub(crate) fn batch_request<I>(items: I) -> QBBatchRequest
+where
+    I: IntoIterator<Item = QBBatchOperation>,
+{
+    QBBatchRequest {
         items: items
             .into_iter()
             .enumerate()
@@ -248,20 +302,14 @@ where
                 QBBatchItem { b_id, item }
             })
             .collect(),
-    };
-    let url = format!("company/{}/batch", qb.company_id);
-    let resp = qb.with_batch_permission(|qb| {
-        execute_request(
-            qb,
-            client,
-            Method::POST,
-            &url,
-            Some(&batch),
-            None,
-            None::<std::iter::Empty<(&str, &str)>>,
-        )
-    })?;
-    let batch_resp: BatchResponseExt = resp.into_body().read_json()?;
+    }
+}
+
+/// Pairs the operations of a batch request with their responses
+pub(crate) fn batch_results(
+    batch: QBBatchRequest,
+    batch_resp: BatchResponseExt,
+) -> Result<Vec<(QBBatchOperation, QBBatchResponseData)>, APIError> {
     let mut items = batch
         .items
         .into_iter()
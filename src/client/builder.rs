@@ -0,0 +1,159 @@
+//! Building a [`QBContext`] with custom endpoints, transport or discovery document.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+
+use super::{transport::default_transport, QBContext, Transport};
+use crate::{APIResult, DiscoveryDoc, Endpoints, Environment};
+
+/// Builds a [`QBContext`] whose endpoints, transport or discovery document differ
+/// from those of its [`Environment`].
+///
+/// Created with [`QBContext::builder`]. By default the context sends its requests
+/// to the endpoints of the environment through a [`ureq::Agent`] with the default
+/// configuration, and fetches the discovery document when it is built. Pointing the
+/// endpoints at a local stub server and supplying the discovery document lets a
+/// context run without reaching `QuickBooks` at all.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{DiscoveryDoc, Endpoints, Environment, QBContext};
+///
+/// let discovery_doc: DiscoveryDoc =
+///     serde_json::from_str(&std::fs::read_to_string("discovery.json").unwrap()).unwrap();
+///
+/// let context = QBContext::builder(
+///     Environment::SANDBOX,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+/// )
+/// .endpoints(Endpoints::from_base_url("http://localhost:8080"))
+/// .minor_version(70)
+/// .discovery_doc(discovery_doc)
+/// .build()
+/// .unwrap();
+/// ```
+#[must_use]
+pub struct QBContextBuilder {
+    company_id: String,
+    access_token: String,
+    endpoints: Endpoints,
+    discovery_doc: Option<DiscoveryDoc>,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl QBContextBuilder {
+    pub(crate) fn new(environment: Environment, company_id: String, access_token: String) -> Self {
+        Self {
+            company_id,
+            access_token,
+            endpoints: environment.endpoints(),
+            discovery_doc: None,
+            transport: None,
+        }
+    }
+
+    /// Replaces all the endpoints, including the minor version
+    pub fn endpoints(self, endpoints: Endpoints) -> Self {
+        Self { endpoints, ..self }
+    }
+
+    /// Replaces the base URL of the accounting API, which should end with `/v3/`
+    pub fn api_url(mut self, api_url: impl Into<String>) -> Self {
+        self.endpoints.api_url = api_url.into();
+        self
+    }
+
+    /// Replaces the URL the discovery document is fetched from
+    pub fn discovery_url(mut self, discovery_url: impl Into<String>) -> Self {
+        self.endpoints.discovery_url = discovery_url.into();
+        self
+    }
+
+    /// Replaces the URL of the userinfo endpoint used by [`QBContext::check_authorized`]
+    pub fn user_info_url(mut self, user_info_url: impl Into<String>) -> Self {
+        self.endpoints.user_info_url = user_info_url.into();
+        self
+    }
+
+    /// Replaces the `minorversion` sent with API requests,
+    /// [`DEFAULT_MINOR_VERSION`](crate::DEFAULT_MINOR_VERSION) by default
+    pub fn minor_version(mut self, minor_version: u32) -> Self {
+        self.endpoints.minor_version = minor_version;
+        self
+    }
+
+    /// Uses the given discovery document instead of fetching it when building
+    pub fn discovery_doc(self, discovery_doc: DiscoveryDoc) -> Self {
+        Self {
+            discovery_doc: Some(discovery_doc),
+            ..self
+        }
+    }
+
+    /// Sends the requests of the context through the given [`Transport`]
+    pub fn transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport: Some(transport),
+            ..self
+        }
+    }
+
+    /// Creates the context, fetching the discovery document through its transport
+    /// if none was given
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if discovery response is malformed
+    /// - HTTP errors if discovery endpoint is unavailable
+    pub fn build(self) -> APIResult<QBContext> {
+        let transport = self.transport.unwrap_or_else(default_transport);
+        let discovery_doc = match self.discovery_doc {
+            Some(discovery_doc) => discovery_doc,
+            None => DiscoveryDoc::get_from_url(&self.endpoints.discovery_url, transport.as_ref())?,
+        };
+        Ok(QBContext::from_parts(
+            self.endpoints,
+            self.company_id,
+            self.access_token,
+            Utc::now() + chrono::Duration::hours(999),
+            discovery_doc,
+            transport,
+        ))
+    }
+
+    /// Creates the context, fetching the discovery document through the async
+    /// client if none was given
+    ///
+    /// The discovery document is fetched through the transport instead if it
+    /// [sends async requests](Transport::sends_async_requests).
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if discovery response is malformed
+    /// - HTTP errors if discovery endpoint is unavailable
+    #[cfg(feature = "async")]
+    pub async fn build_async(self, client: &reqwest::Client) -> APIResult<QBContext> {
+        let transport = self.transport.unwrap_or_else(default_transport);
+        let discovery_doc = if let Some(discovery_doc) = self.discovery_doc {
+            discovery_doc
+        } else {
+            let request =
+                ureq::http::Request::get(self.endpoints.discovery_url.as_str()).body(Vec::new())?;
+            let response = crate::asynchronous::send(&transport, client, request).await?;
+            DiscoveryDoc::from_response(&response)?
+        };
+        Ok(QBContext::from_parts(
+            self.endpoints,
+            self.company_id,
+            self.access_token,
+            Utc::now() + chrono::Duration::hours(999),
+            discovery_doc,
+            transport,
+        ))
+    }
+}
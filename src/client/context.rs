@@ -1,14 +1,23 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::Duration,
+};
 
 use chrono::{DateTime, Utc};
-use ureq::Agent;
+use ureq::http::{Request, Response};
 
-use super::refresh::RefreshableQBContext;
-use crate::{limiter::RateLimiter, APIResult, DiscoveryDoc, Environment};
-
-const RATE_LIMIT: usize = 500;
-const BATCH_RATE_LIMIT: usize = 40;
-const RESET_DURATION: Duration = Duration::from_secs(60);
+use super::{
+    builder::QBContextBuilder,
+    refresh::{RefreshState, RefreshableQBContext},
+    token_store::TokenStore,
+    transport::Transport,
+};
+use crate::{
+    error::OAuthError,
+    functions::{conflict::ConflictPolicy, retry::RetryPolicy},
+    limiter::{ConcurrencyGuard, LimitConfig, LimitStatus, LimiterRegistry, RealmLimiter},
+    APIResult, DiscoveryDoc, Endpoints, Environment,
+};
 
 /// The core context for interacting with the `QuickBooks` Online API.
 ///
@@ -20,8 +29,11 @@ const RESET_DURATION: Duration = Duration::from_secs(60);
 ///
 /// - **Regular API**: 500 requests per minute
 /// - **Batch API**: 40 batches per minute, 30 requests per batch
+/// - **Concurrency**: 10 requests in flight at once
 /// - **Throttle Recovery**: 60-second wait period after hitting limits
 ///
+/// These are the defaults, see [`QBContext::set_realm_limits`] to change them.
+///
 /// # Fields
 ///
 /// - `environment`: The `QuickBooks` environment (sandbox or production)
@@ -29,7 +41,17 @@ const RESET_DURATION: Duration = Duration::from_secs(60);
 /// - `access_token`: OAuth 2.0 access token for authentication
 /// - `expires_in`: Token expiration time (defaults to far future)
 /// - `discovery_doc`: OAuth discovery document with endpoint URLs
-/// - Rate limiters for regular and batch operations
+/// - `endpoints`: The [`Endpoints`] requests are sent to, those of the environment by default
+/// - `transport`: The [`Transport`] requests are sent through
+/// - Rate limiters for regular and batch operations, and a concurrency limiter for both,
+///   shared with every other context for the same company
+/// - `conflict_policy`: Optional recovery policy for stale `SyncToken` conflicts
+/// - `retry_policy`: Optional policy for retrying throttled and failed requests
+/// - Refresh token state, for contexts created through [`QBContext::with_refresh`]
+///
+/// The access token and refresh state are kept behind locks, so a context with
+/// auto-refresh enabled can renew its token through the `&QBContext` every
+/// operation takes.
 ///
 /// # Examples
 ///
@@ -37,20 +59,16 @@ const RESET_DURATION: Duration = Duration::from_secs(60);
 ///
 /// ```no_run
 /// use quick_oxibooks::{QBContext, Environment};
-/// use ureq::Agent;
-///
-/// let client = Agent::new_with_defaults();
 ///
 /// // Create from explicit parameters
 /// let context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_123".to_string(),
 ///     "access_token_xyz".to_string(),
-///     &client
 /// ).unwrap();
 ///
 /// // Create from environment variables QB_COMPANY_ID and QB_ACCESS_TOKEN
-/// let context = QBContext::new_from_env(Environment::SANDBOX, &client).unwrap();
+/// let context = QBContext::new_from_env(Environment::SANDBOX).unwrap();
 /// ```
 ///
 /// ## Using with Operations
@@ -58,37 +76,31 @@ const RESET_DURATION: Duration = Duration::from_secs(60);
 /// ```no_run
 /// use quick_oxibooks::functions::{create::QBCreate, query::QBQuery};
 /// use quickbooks_types::Customer;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let context = quick_oxibooks::QBContext::new(
 ///     quick_oxibooks::Environment::SANDBOX,
 ///     "company".to_string(),
 ///     "token".to_string(),
-///     &client
 /// ).unwrap();
 ///
 /// // Create a customer
 /// let mut customer = Customer::default();
 /// customer.display_name = Some("John Doe".to_string());
-/// let created = customer.create(&context, &client).unwrap();
+/// let created = customer.create(&context).unwrap();
 ///
 /// // Query customers
-/// let customers = Customer::query("WHERE Active = true", Some(10), &context, &client).unwrap();
+/// let customers = Customer::query("WHERE Active = true", Some(10), &context).unwrap();
 /// ```
 ///
 /// ## Refresh Token Support
 ///
 /// ```no_run
 /// use quick_oxibooks::{QBContext, Environment};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_123".to_string(),
 ///     "access_token_xyz".to_string(),
-///     &client
 /// ).unwrap();
 ///
 /// // Create a refreshable context for automatic token renewal
@@ -96,27 +108,40 @@ const RESET_DURATION: Duration = Duration::from_secs(60);
 /// let _ = refreshable;
 /// ```
 pub struct QBContext {
-    pub(crate) environment: Environment,
+    pub(crate) endpoints: Endpoints,
     pub(crate) company_id: String,
-    pub(crate) access_token: String,
-    pub(crate) expires_in: DateTime<Utc>,
+    pub(crate) token: RwLock<AccessToken>,
     pub(crate) discovery_doc: DiscoveryDoc,
-    pub(crate) qbo_limiter: RateLimiter,
-    pub(crate) batch_limiter: RateLimiter, // Batch endpoints have a different rate limit
+    pub(crate) transport: Arc<dyn Transport>,
+    pub(crate) limiter: RealmLimiter,
+    pub(crate) limit_timeout: Option<Duration>,
+    pub(crate) conflict_policy: Option<ConflictPolicy>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) refresh: Option<Mutex<RefreshState>>,
+    /// Lets one refresh through the async client run at a time
+    #[cfg(feature = "async")]
+    pub(crate) refresh_gate: tokio::sync::Mutex<()>,
+}
+
+/// The access token of a [`QBContext`] and when it expires
+pub(crate) struct AccessToken {
+    pub(crate) access_token: String,
+    pub(crate) expires_at: DateTime<Utc>,
 }
 
 impl QBContext {
     /// Creates a new `QuickBooks` context with the specified parameters.
     ///
     /// This constructor initializes the context with authentication details,
-    /// fetches the OAuth discovery document, and sets up rate limiters.
+    /// fetches the OAuth discovery document, and sets up rate limiters. Requests
+    /// are sent through a [`ureq::Agent`] with the default configuration, see
+    /// [`QBContext::new_with_transport`] to send them through another [`Transport`].
     ///
     /// # Parameters
     ///
     /// - `environment`: `QuickBooks` environment (sandbox or production)
     /// - `company_id`: The `QuickBooks` company ID for your application
     /// - `access_token`: Valid OAuth 2.0 access token
-    /// - `client`: HTTP client for making API requests
     ///
     /// # Returns
     ///
@@ -132,31 +157,140 @@ impl QBContext {
     ///
     /// ```no_run
     /// use quick_oxibooks::{QBContext, Environment};
-    /// use ureq::Agent;
     ///
-    /// let client = Agent::new_with_defaults();
     /// let context = QBContext::new(
     ///     Environment::SANDBOX,
     ///     "company_123".to_string(),
     ///     "Bearer_token_xyz".to_string(),
-    ///     &client
     /// ).unwrap();
     /// ```
     pub fn new(
         environment: Environment,
         company_id: String,
         access_token: String,
-        client: &Agent,
     ) -> APIResult<Self> {
-        Ok(Self {
-            environment,
+        Self::builder(environment, company_id, access_token).build()
+    }
+
+    /// Creates a new `QuickBooks` context like [`QBContext::new`], sending its
+    /// requests through the given [`Transport`].
+    ///
+    /// The discovery document is fetched through the transport too.
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if discovery response is malformed
+    /// - HTTP errors if discovery endpoint is unavailable
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use ureq::Agent;
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .proxy(ureq::Proxy::new("http://proxy.example.com:8080").ok())
+    ///     .build()
+    ///     .into();
+    /// let context = QBContext::new_with_transport(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "Bearer_token_xyz".to_string(),
+    ///     Arc::new(agent),
+    /// ).unwrap();
+    /// ```
+    pub fn new_with_transport(
+        environment: Environment,
+        company_id: String,
+        access_token: String,
+        transport: Arc<dyn Transport>,
+    ) -> APIResult<Self> {
+        Self::builder(environment, company_id, access_token)
+            .transport(transport)
+            .build()
+    }
+
+    /// Creates a new `QuickBooks` context like [`QBContext::new`], fetching the
+    /// discovery document through the async client.
+    ///
+    /// The context can be used with both the blocking and the async API, see
+    /// [`crate::asynchronous`]. Blocking requests are sent through a [`ureq::Agent`]
+    /// with the default configuration.
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if discovery response is malformed
+    /// - HTTP errors if discovery endpoint is unavailable
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    ///
+    /// # async fn example() -> Result<(), quick_oxibooks::error::APIError> {
+    /// let client = reqwest::Client::new();
+    /// let context = QBContext::new_async(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "Bearer_token_xyz".to_string(),
+    ///     &client
+    /// ).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn new_async(
+        environment: Environment,
+        company_id: String,
+        access_token: String,
+        client: &reqwest::Client,
+    ) -> APIResult<Self> {
+        Self::builder(environment, company_id, access_token)
+            .build_async(client)
+            .await
+    }
+
+    /// Starts building a context for the environment, whose endpoints, transport
+    /// and discovery document can be replaced before it is created.
+    ///
+    /// See [`QBContextBuilder`].
+    pub fn builder(
+        environment: Environment,
+        company_id: String,
+        access_token: String,
+    ) -> QBContextBuilder {
+        QBContextBuilder::new(environment, company_id, access_token)
+    }
+
+    /// Creates a context from already known parts, without any requests
+    pub(crate) fn from_parts(
+        endpoints: Endpoints,
+        company_id: String,
+        access_token: String,
+        expires_in: DateTime<Utc>,
+        discovery_doc: DiscoveryDoc,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        Self {
+            limiter: LimiterRegistry::global().realm(&company_id),
+            endpoints,
             company_id,
-            access_token,
-            expires_in: Utc::now() + chrono::Duration::hours(999),
-            discovery_doc: DiscoveryDoc::get(environment, client)?,
-            qbo_limiter: RateLimiter::new(RATE_LIMIT, RESET_DURATION),
-            batch_limiter: RateLimiter::new(BATCH_RATE_LIMIT, RESET_DURATION),
-        })
+            token: RwLock::new(AccessToken {
+                access_token,
+                expires_at: expires_in,
+            }),
+            discovery_doc,
+            transport,
+            limit_timeout: None,
+            conflict_policy: None,
+            retry_policy: None,
+            refresh: None,
+            #[cfg(feature = "async")]
+            refresh_gate: tokio::sync::Mutex::new(()),
+        }
     }
 
     /// Creates a new `QuickBooks` context from environment variables.
@@ -173,7 +307,6 @@ impl QBContext {
     /// # Parameters
     ///
     /// - `environment`: `QuickBooks` environment (sandbox or production)
-    /// - `client`: HTTP client for making API requests
     ///
     /// # Returns
     ///
@@ -193,15 +326,13 @@ impl QBContext {
     ///
     /// ```no_run
     /// use quick_oxibooks::{QBContext, Environment};
-    /// use ureq::Agent;
     ///
-    /// let client = Agent::new_with_defaults();
-    /// let context = QBContext::new_from_env(Environment::SANDBOX, &client).unwrap();
+    /// let context = QBContext::new_from_env(Environment::SANDBOX).unwrap();
     /// ```
-    pub fn new_from_env(environment: Environment, client: &Agent) -> APIResult<Self> {
+    pub fn new_from_env(environment: Environment) -> APIResult<Self> {
         let company_id = std::env::var("QB_COMPANY_ID")?;
         let access_token = std::env::var("QB_ACCESS_TOKEN")?;
-        let context = Self::new(environment, company_id, access_token, client)?;
+        let context = Self::new(environment, company_id, access_token)?;
         Ok(context)
     }
 
@@ -222,14 +353,11 @@ impl QBContext {
     ///
     /// ```no_run
     /// use quick_oxibooks::{QBContext, Environment};
-    /// use ureq::Agent;
     ///
-    /// let client = Agent::new_with_defaults();
     /// let context = QBContext::new(
     ///     Environment::SANDBOX,
     ///     "company_123".to_string(),
     ///     "access_token_xyz".to_string(),
-    ///     &client
     /// ).unwrap();
     ///
     /// // Enable automatic token refresh
@@ -238,11 +366,58 @@ impl QBContext {
     #[must_use]
     pub fn with_refresh(self, refresh_token: String) -> RefreshableQBContext {
         RefreshableQBContext {
-            context: self,
-            refresh_token,
+            context: Self {
+                refresh: Some(Mutex::new(RefreshState::new(refresh_token))),
+                ..self
+            },
         }
     }
 
+    /// Creates a refreshable context from the tokens stored for this company.
+    ///
+    /// The access token of the context is replaced with the stored one, and the
+    /// store stays attached to the returned context so refreshed tokens are saved
+    /// back to it. See [`TokenStore`].
+    ///
+    /// # Errors
+    ///
+    /// - `OAuth(NoStoredTokens)`: The store holds no tokens for the company
+    /// - Errors from the token store
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use quick_oxibooks::client::FileTokenStore;
+    ///
+    /// let store = Arc::new(FileTokenStore::new("tokens.json"));
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     String::new(),
+    /// ).unwrap()
+    /// .with_stored_refresh(store)
+    /// .unwrap();
+    /// ```
+    pub fn with_stored_refresh(
+        self,
+        store: Arc<dyn TokenStore>,
+    ) -> APIResult<RefreshableQBContext> {
+        let Some(stored) = store.load(&self.company_id)? else {
+            return Err(OAuthError::NoStoredTokens(self.company_id).into());
+        };
+        let mut state = RefreshState::new(String::new());
+        self.set_stored_tokens(&mut state, stored);
+        state.token_store = Some(store);
+        Ok(RefreshableQBContext {
+            context: Self {
+                refresh: Some(Mutex::new(state)),
+                ..self
+            },
+        })
+    }
+
     /// Updates the access token and returns a new context.
     ///
     /// This method is useful when you need to update the access token after
@@ -260,75 +435,481 @@ impl QBContext {
     ///
     /// ```no_run
     /// use quick_oxibooks::{QBContext, Environment};
-    /// use ureq::Agent;
     ///
-    /// let client = Agent::new_with_defaults();
     /// let context = QBContext::new(
     ///     Environment::SANDBOX,
     ///     "company_123".to_string(),
     ///     "access_token_xyz".to_string(),
-    ///     &client
     /// ).unwrap();
     /// // Update the access token after manual refresh
     /// let new_context = context.with_access_token("new_access_token_xyz".to_string());
     /// let _ = new_context; // suppress unused variable warning
     /// ```
     #[must_use]
-    pub fn with_access_token(self, access_token: String) -> Self {
+    pub fn with_access_token(mut self, access_token: String) -> Self {
+        self.token
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .access_token = access_token;
+        self
+    }
+
+    /// Replaces the [`Transport`] requests are sent through.
+    ///
+    /// The discovery document of the context was already fetched when it was created,
+    /// use [`QBContext::new_with_transport`] to fetch it through the transport too.
+    ///
+    /// # Parameters
+    ///
+    /// - `transport`: The transport to send requests through
+    ///
+    /// # Returns
+    ///
+    /// A new `QBContext` sending requests through the transport.
+    #[must_use]
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self { transport, ..self }
+    }
+
+    /// Enables automatic recovery from stale `SyncToken` conflicts.
+    ///
+    /// With a [`ConflictPolicy`] set, updates and deletes that fail because the
+    /// entity was changed in the meantime re-read it and retry, up to the policy's
+    /// `max_retries`. See [`ConflictPolicy`] for how each operation recovers.
+    ///
+    /// # Parameters
+    ///
+    /// - `policy`: The conflict recovery policy to use
+    ///
+    /// # Returns
+    ///
+    /// A new `QBContext` with the conflict policy set.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use quick_oxibooks::functions::conflict::ConflictPolicy;
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap()
+    /// .with_conflict_policy(ConflictPolicy::new(3));
+    /// ```
+    #[must_use]
+    pub fn with_conflict_policy(self, policy: ConflictPolicy) -> Self {
         Self {
-            access_token,
+            conflict_policy: Some(policy),
             ..self
         }
     }
 
-    /// Acquires a permit from the rate limiter and executes the given function
-    /// with the given context
-    pub(crate) fn with_permission<'a, F, T>(&'a self, f: F) -> APIResult<T>
-    where
-        F: FnOnce(&'a Self) -> APIResult<T>,
-    {
-        let permit = self.qbo_limiter.acquire();
-        let out = f(self);
-        drop(permit);
-        out
+    /// Enables retrying throttled and failed requests.
+    ///
+    /// With a [`RetryPolicy`] set, every request that is throttled, fails with a
+    /// server error or loses its connection is retried with exponential backoff,
+    /// honoring the `Retry-After` header. See [`RetryPolicy`] for what is retried.
+    ///
+    /// # Parameters
+    ///
+    /// - `policy`: The retry policy to use
+    ///
+    /// # Returns
+    ///
+    /// A new `QBContext` with the retry policy set.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use quick_oxibooks::functions::retry::RetryPolicy;
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap()
+    /// .with_retry_policy(RetryPolicy::new(5));
+    /// ```
+    #[must_use]
+    pub fn with_retry_policy(self, policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(policy),
+            ..self
+        }
+    }
+
+    /// Sets the limits requests to the company are kept within.
+    ///
+    /// The limits belong to the company in the context's [`LimiterRegistry`], so they
+    /// also apply to every other context for the company sharing the registry. Requests
+    /// already sent still count against them. See [`LimitConfig`].
+    ///
+    /// # Parameters
+    ///
+    /// - `limits`: The request limits to use
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use quick_oxibooks::limiter::LimitConfig;
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap();
+    ///
+    /// context.set_realm_limits(LimitConfig {
+    ///     max_concurrent: 4,
+    ///     ..LimitConfig::default()
+    /// });
+    /// ```
+    pub fn set_realm_limits(&self, limits: LimitConfig) {
+        self.limiter.set_limits(limits);
+    }
+
+    /// Shares the rate and concurrency limits of the company through the given registry.
+    ///
+    /// Contexts use [`LimiterRegistry::global`] unless given another one, which is
+    /// shared by every context in the process. A registry with a shared backend, like
+    /// [`FileRateLimitBackend`](crate::limiter::FileRateLimitBackend), also shares the
+    /// budget with other processes.
+    ///
+    /// # Parameters
+    ///
+    /// - `registry`: The registry to take the company's limiter from
+    ///
+    /// # Returns
+    ///
+    /// A new `QBContext` limited through the registry.
+    #[must_use]
+    pub fn with_limiter_registry(self, registry: &LimiterRegistry) -> Self {
+        Self {
+            limiter: registry.realm(&self.company_id),
+            ..self
+        }
+    }
+
+    /// Sets the longest time a request waits for the rate and concurrency limits.
+    ///
+    /// Requests that would have to wait longer fail with
+    /// [`ThrottleLimitReached`](crate::error::APIErrorInner::ThrottleLimitReached)
+    /// instead, carrying the time until the rate limit resets. With `Duration::ZERO`
+    /// they never wait. By default requests wait as long as it takes.
+    ///
+    /// # Parameters
+    ///
+    /// - `timeout`: The longest time to wait
+    ///
+    /// # Returns
+    ///
+    /// A new `QBContext` with the timeout set.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use quick_oxibooks::{QBContext, Environment};
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap()
+    /// .with_limit_timeout(Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn with_limit_timeout(self, timeout: Duration) -> Self {
+        Self {
+            limit_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reports how much of the company's request budget is left.
+    ///
+    /// The budget is shared with every context for the company in the context's
+    /// [`LimiterRegistry`], see [`LimitStatus`] for what is reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry's backend fails to read the request counts.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap();
+    ///
+    /// let status = context.limit_status().unwrap();
+    /// if status.requests.remaining < 50 {
+    ///     println!("Running low, full budget back in {:?}", status.requests.resets_in);
+    /// }
+    /// ```
+    pub fn limit_status(&self) -> APIResult<LimitStatus> {
+        self.limiter.status()
+    }
+
+    /// Takes a request slot and a rate limit permit of the company without waiting.
+    ///
+    /// Requests sent through the context take their own, this is for work that has
+    /// to stay within the company's budget without going through the context, e.g.
+    /// requests sent through another client. The slot is held until the returned
+    /// guard is dropped.
+    ///
+    /// # Parameters
+    ///
+    /// - `batch`: Whether to take the permit from the limit of batch requests
+    ///
+    /// # Errors
+    ///
+    /// - [`ThrottleLimitReached`](crate::error::APIErrorInner::ThrottleLimitReached)
+    ///   if no slot or permit is free, with the time until the rate limit frees one
+    ///   up if the permit was missing
+    /// - Errors of the registry's backend while taking the permit
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap();
+    ///
+    /// let slot = context.try_acquire(false);
+    /// match slot {
+    ///     Ok(_slot) => println!("Sending the request now"),
+    ///     Err(e) => println!("Over the limits: {e}"),
+    /// }
+    /// ```
+    pub fn try_acquire(&self, batch: bool) -> APIResult<ConcurrencyGuard<'_>> {
+        self.limiter.acquire_timeout(batch, Duration::ZERO)
     }
 
-    /// Acquires a permit from the batch rate limiter and executes the given function
-    /// with the given context
-    pub(crate) fn with_batch_permission<'a, F, T>(&'a self, f: F) -> APIResult<T>
-    where
-        F: FnOnce(&'a Self) -> APIResult<T>,
-    {
-        let permit = self.batch_limiter.acquire();
-        let out = f(self);
-        drop(permit);
-        out
+    /// Takes a request slot and a rate limit permit of the company, waiting at most
+    /// `timeout` for them.
+    ///
+    /// Like [`QBContext::try_acquire`], but waits for a slot to be released or for
+    /// the rate limit to free up a permit. Fails right away if the permit won't be
+    /// free within the timeout.
+    ///
+    /// # Parameters
+    ///
+    /// - `batch`: Whether to take the permit from the limit of batch requests
+    /// - `timeout`: The longest time to wait
+    ///
+    /// # Errors
+    ///
+    /// - [`ThrottleLimitReached`](crate::error::APIErrorInner::ThrottleLimitReached)
+    ///   if the slot or permit isn't free within `timeout`, with the time until the
+    ///   rate limit frees one up if the permit was missing
+    /// - Errors of the registry's backend while taking the permit
+    pub fn acquire_timeout(
+        &self,
+        batch: bool,
+        timeout: Duration,
+    ) -> APIResult<ConcurrencyGuard<'_>> {
+        self.limiter.acquire_timeout(batch, timeout)
+    }
+
+    /// Acquires a slot from the concurrency limiter and a permit from the rate
+    /// limiter of regular or batch requests, and sends the request through the
+    /// context's transport
+    ///
+    /// The slot is released as soon as the response is in, so each attempt of a
+    /// retried request takes its own permit and nothing is held while backing off.
+    pub(crate) fn send_with_permission(
+        &self,
+        request: Request<Vec<u8>>,
+        batch: bool,
+    ) -> APIResult<Response<Vec<u8>>> {
+        let slot = match self.limit_timeout {
+            Some(timeout) => self.limiter.acquire_timeout(batch, timeout)?,
+            None => self.limiter.acquire(batch)?,
+        };
+        let response = self.transport.send(request);
+        drop(slot);
+        response
+    }
+
+    /// Acquires a slot from the concurrency limiter and a permit from the rate
+    /// limiter of regular or batch requests for an async request, without blocking
+    #[cfg(feature = "async")]
+    pub(crate) async fn acquire_permission(&self, batch: bool) -> APIResult<ConcurrencyGuard<'_>> {
+        self.limiter.acquire_async(batch, self.limit_timeout).await
     }
 
     /// Checks if the current context is expired
     #[must_use]
     pub fn is_expired(&self) -> bool {
-        chrono::Utc::now() >= self.expires_in
+        self.expires_within(chrono::Duration::zero())
+    }
+
+    /// Checks if the access token expires within the given duration
+    #[must_use]
+    pub fn expires_within(&self, duration: chrono::Duration) -> bool {
+        let token = self.token.read().unwrap_or_else(PoisonError::into_inner);
+        chrono::Utc::now() + duration >= token.expires_at
+    }
+
+    /// The current access token
+    pub(crate) fn access_token(&self) -> String {
+        self.token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .access_token
+            .clone()
+    }
+
+    /// Replaces the access token, e.g. after a refresh
+    pub(crate) fn set_access_token(&self, access_token: String, expires_at: DateTime<Utc>) {
+        *self.token.write().unwrap_or_else(PoisonError::into_inner) = AccessToken {
+            access_token,
+            expires_at,
+        };
     }
 
     /// Checks if the current access token is authorized
-    pub fn check_authorized(&self, client: &Agent) -> APIResult<bool> {
-        let request = client
-            .get(self.environment.user_info_url())
-            .header("Authorization", format!("Bearer {}", &self.access_token))
-            .header("Accept", "application/json");
-        let response = request.call()?;
+    pub fn check_authorized(&self) -> APIResult<bool> {
+        let request = Request::get(self.endpoints.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", self.access_token()))
+            .header("Accept", "application/json")
+            .body(Vec::new())?;
+        let response = self.transport.send(request)?;
         let status = response.status();
         if !status.is_success() {
             #[cfg(feature = "logging")]
             log::error!(
                 "Failed to check authorized status: {} - {}",
                 status,
-                response
-                    .into_body()
-                    .read_json::<crate::error::QBErrorResponse>()?
+                serde_json::from_slice::<crate::error::QBErrorResponse>(response.body())?
             );
         }
         Ok(status.is_success())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use crate::{
+        error::APIErrorInner,
+        limiter::{LimitConfig, LimiterRegistry},
+        Endpoints, Environment, QBContext,
+    };
+
+    fn context(registry: &LimiterRegistry) -> QBContext {
+        let discovery_doc = serde_json::from_value(json!({
+            "issuer": "http://localhost",
+            "authorization_endpoint": "http://localhost/connect/oauth2",
+            "token_endpoint": "http://localhost/oauth2/v1/tokens/bearer",
+            "userinfo_endpoint": "http://localhost/v1/openid_connect/userinfo",
+            "revocation_endpoint": "http://localhost/oauth2/v1/tokens/revoke",
+            "jwks_uri": "http://localhost/oauth2/v1/keys",
+            "response_types_supported": [],
+            "subject_types_supported": [],
+            "id_token_signing_alg_values_supported": [],
+            "scopes_supported": [],
+            "token_endpoint_auth_methods_supported": [],
+            "claims_supported": [],
+        }))
+        .unwrap();
+        let qb = QBContext::builder(
+            Environment::SANDBOX,
+            "123".to_string(),
+            "access_token_xyz".to_string(),
+        )
+        .endpoints(Endpoints::from_base_url("http://localhost"))
+        .discovery_doc(discovery_doc)
+        .build()
+        .unwrap()
+        .with_limiter_registry(registry);
+        qb.set_realm_limits(LimitConfig {
+            requests_per_window: 2,
+            max_concurrent: 1,
+            ..LimitConfig::default()
+        });
+        qb
+    }
+
+    #[test]
+    fn test_try_acquire() {
+        let registry = LimiterRegistry::default();
+        let qb = context(&registry);
+
+        let slot = qb.try_acquire(false).unwrap();
+        assert!(matches!(
+            &*qb.try_acquire(true).map(drop).unwrap_err(),
+            APIErrorInner::ThrottleLimitReached { retry_after: None }
+        ));
+        drop(slot);
+
+        drop(qb.try_acquire(false).unwrap());
+        let err = qb.try_acquire(false).map(drop).unwrap_err();
+        let APIErrorInner::ThrottleLimitReached {
+            retry_after: Some(retry_after),
+        } = &*err
+        else {
+            panic!("Expected ThrottleLimitReached with a retry time, got {err}");
+        };
+        assert!(*retry_after > Duration::from_secs(59));
+
+        // Batch requests have a budget of their own
+        drop(qb.try_acquire(true).unwrap());
+        assert_eq!(qb.limit_status().unwrap().requests.remaining, 0);
+    }
+
+    #[test]
+    fn test_acquire_timeout() {
+        let registry = LimiterRegistry::default();
+        let qb = context(&registry);
+
+        let slot = qb.acquire_timeout(false, Duration::ZERO).unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            &*qb.acquire_timeout(false, Duration::from_millis(20))
+                .map(drop)
+                .unwrap_err(),
+            APIErrorInner::ThrottleLimitReached { retry_after: None }
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // Waits for the slot to be released
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                drop(slot);
+            });
+            let start = Instant::now();
+            drop(qb.acquire_timeout(false, Duration::from_secs(5)).unwrap());
+            assert!(start.elapsed() >= Duration::from_millis(40));
+        });
+
+        // Doesn't wait for a permit that won't be free in time
+        let start = Instant::now();
+        assert!(matches!(
+            &*qb.acquire_timeout(false, Duration::from_secs(5))
+                .map(drop)
+                .unwrap_err(),
+            APIErrorInner::ThrottleLimitReached {
+                retry_after: Some(_)
+            }
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -13,24 +13,66 @@
 //!
 //! ```no_run
 //! use quick_oxibooks::{QBContext, Environment};
-//! use ureq::Agent;
 //!
 //! // Create from environment variables
-//! let client = Agent::new_with_defaults();
-//! let context = QBContext::new_from_env(Environment::SANDBOX, &client).unwrap();
+//! let context = QBContext::new_from_env(Environment::SANDBOX).unwrap();
 //!
 //! // Create manually
 //! let context = QBContext::new(
 //!     Environment::PRODUCTION,
 //!     "company_id".to_string(),
 //!     "access_token".to_string(),
-//!     &client
 //! ).unwrap();
 //!
 //! // Create with refresh token capability
 //! let refreshable_context = context.with_refresh("refresh_token".to_string());
 //! ```
 //!
+//! ### Sending Requests
+//!
+//! A context sends its requests through a [`Transport`], which is a [`ureq::Agent`]
+//! with the default configuration unless it is given another one with
+//! [`QBContext::new_with_transport`].
+//!
+//! Requests go to the [`Endpoints`](crate::Endpoints) of the context's environment.
+//! [`QBContextBuilder`] replaces them, e.g. to run against a local stub server, and
+//! can take the discovery document instead of fetching it:
+//!
+//! ```no_run
+//! use quick_oxibooks::{DiscoveryDoc, Endpoints, Environment, QBContext};
+//!
+//! let discovery_doc: DiscoveryDoc = serde_json::from_str(
+//!     r#"{
+//!         "issuer": "http://localhost:8080",
+//!         "authorization_endpoint": "http://localhost:8080/connect/oauth2",
+//!         "token_endpoint": "http://localhost:8080/oauth2/v1/tokens/bearer",
+//!         "userinfo_endpoint": "http://localhost:8080/v1/openid_connect/userinfo",
+//!         "revocation_endpoint": "http://localhost:8080/oauth2/v1/tokens/revoke",
+//!         "jwks_uri": "http://localhost:8080/oauth2/v1/keys",
+//!         "response_types_supported": ["code"],
+//!         "subject_types_supported": ["public"],
+//!         "id_token_signing_alg_values_supported": ["RS256"],
+//!         "scopes_supported": ["com.intuit.quickbooks.accounting"],
+//!         "token_endpoint_auth_methods_supported": ["client_secret_basic"],
+//!         "claims_supported": ["sub"]
+//!     }"#,
+//! )
+//! .unwrap();
+//!
+//! let context = QBContext::builder(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//! )
+//! .endpoints(Endpoints::from_base_url("http://localhost:8080"))
+//! .discovery_doc(discovery_doc)
+//! .build()
+//! .unwrap();
+//! ```
+//!
+//! With the `oauth` feature, [`oauth::OAuthClient::new_with_endpoints`] connects
+//! companies against the same endpoints.
+//!
 //! ### Handling Refresh Tokens
 //!
 //! The [`RefreshableQBContext`] struct extends [`QBContext`] to support automatic token refreshing.
@@ -38,24 +80,68 @@
 //! This is useful for long-running applications that need to maintain access to the `QuickBooks` Online API,
 //! or use on a desktop application where the user may not be able to go through the OAuth flow frequently.
 //!
+//! With [`RefreshableQBContext::with_auto_refresh`], requests refresh an expiring or
+//! rejected access token on their own and are retried once. To share a context between
+//! threads, turn it into a clonable [`SharedQBContext`] with
+//! [`RefreshableQBContext::into_shared`]; refreshes through it run one at a time.
+//!
+//! Refresh tokens expire too. [`RefreshableQBContext`] tracks when, and
+//! [`RefreshableQBContext::revoke`] disconnects a company from the app.
+//!
+//! `QuickBooks` rotates the refresh token on refresh. Attach a [`TokenStore`], such as
+//! [`FileTokenStore`], so rotated tokens are saved as part of every refresh:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use quick_oxibooks::{QBContext, Environment};
+//! use quick_oxibooks::client::FileTokenStore;
+//!
+//! let context = QBContext::new(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     String::new(),
+//! )
+//! .unwrap()
+//! .with_stored_refresh(Arc::new(FileTokenStore::new("tokens.json")))
+//! .unwrap();
+//!
+//! context.refresh_access_token("client_id", "client_secret").unwrap();
+//! ```
+//!
+//! ### Connecting a Company
+//!
+//! With the `oauth` feature, the [`oauth`] module runs the OAuth 2.0 authorization code
+//! flow and produces a [`RefreshableQBContext`] for the newly connected company.
+//!
 //! ### Rate Limits
 //!
 //! - Sandbox: 500 requests per minute
 //! - Production: 500 requests per minute, 10 requests per second
 //! - Batch operations: 30 requests per batch, 40 batches per minute
+//! - Concurrency: 10 requests in flight at once
 //!
+//! Every [`QBContext`] keeps its requests within these limits, waiting when it
+//! would exceed them; [`QBContext::set_realm_limits`] changes the limits it uses.
+//! Contexts for the same company share their budget, see [`crate::limiter`].
 //! After being throttled, wait 60 seconds before retrying.
 
-use crate::{APIResult, Environment};
+use crate::{APIResult, Endpoints};
 use serde::Serialize;
-use ureq::{
-    http::{request::Builder, Method, Request},
-    SendBody,
-};
+use ureq::http::{request::Builder, Method, Request};
+mod builder;
 mod context;
+#[cfg(feature = "oauth")]
+pub mod oauth;
 mod refresh;
+mod token_store;
+mod transport;
+pub use builder::QBContextBuilder;
 pub use context::QBContext;
-pub use refresh::RefreshableQBContext;
+pub use refresh::{
+    RefreshableQBContext, SharedQBContext, AUTO_REFRESH_MARGIN, REFRESH_TOKEN_EXPIRY_WARNING,
+};
+pub use token_store::{FileTokenStore, MemoryTokenStore, StoredTokens, TokenStore};
+pub use transport::{AgentTransport, Transport, DEFAULT_BODY_LIMIT};
 use urlencoding::encode;
 
 pub(crate) fn set_headers(content_type: &str, access_token: &str, request: Builder) -> Builder {
@@ -73,26 +159,19 @@ pub(crate) fn build_request<B, S, SS>(
     body: Option<&B>,
     query: Option<impl IntoIterator<Item = (S, SS)>>,
     content_type: &str,
-    environment: Environment,
+    endpoints: &Endpoints,
     access_token: &str,
-) -> APIResult<Request<SendBody<'static>>>
+) -> APIResult<Request<Vec<u8>>>
 where
     B: Serialize,
     S: AsRef<str>,
     SS: AsRef<str>,
 {
-    let url = build_url(environment, path, query);
+    let url = build_url(endpoints, path, query);
     let mut request = Request::builder().method(method.clone()).uri(url.as_str());
     request = set_headers(content_type, access_token, request);
 
-    let request = match (method == Method::GET || method == Method::DELETE, body) {
-        (true, _) | (false, None) => request.body(SendBody::none()),
-        (false, Some(body)) => {
-            let json_bytes = serde_json::to_vec(body)?;
-            let reader = std::io::Cursor::new(json_bytes);
-            request.body(SendBody::from_owned_reader(reader))
-        }
-    }?;
+    let request = request.body(json_body(method, body)?.unwrap_or_default())?;
 
     #[cfg(feature = "logging")]
     log::debug!(
@@ -109,8 +188,19 @@ where
     Ok(request)
 }
 
+/// Serializes the body of a request, `GET` and `DELETE` requests are sent without one
+pub(crate) fn json_body<B: Serialize>(
+    method: &Method,
+    body: Option<&B>,
+) -> APIResult<Option<Vec<u8>>> {
+    match (method == Method::GET || method == Method::DELETE, body) {
+        (true, _) | (false, None) => Ok(None),
+        (false, Some(body)) => Ok(Some(serde_json::to_vec(body)?)),
+    }
+}
+
 pub(crate) fn build_url<S, SS>(
-    environment: Environment,
+    endpoints: &Endpoints,
     path: &str,
     query: Option<impl IntoIterator<Item = (S, SS)>>,
 ) -> String
@@ -118,8 +208,7 @@ where
     S: AsRef<str>,
     SS: AsRef<str>,
 {
-    // let url = Url::parse(environment.endpoint_url())?;
-    let mut url = environment.endpoint_url().to_string();
+    let mut url = endpoints.api_url.clone();
     url.push_str(path);
     if let Some(q) = query {
         let query_string: String = q
@@ -131,13 +220,36 @@ where
                 )
             })
             .map(|(k, v)| format!("{k}={v}"))
-            .chain(std::iter::once("minorversion=75".to_string()))
+            .chain(std::iter::once(format!(
+                "minorversion={}",
+                endpoints.minor_version
+            )))
             .collect::<Vec<_>>()
             .join("&");
         if !query_string.is_empty() {
-            url.push('?');
+            url.push(if path.contains('?') { '&' } else { '?' });
             url.push_str(&query_string);
         }
     }
     url
 }
+
+#[cfg(test)]
+mod tests {
+    use super::build_url;
+    use crate::Endpoints;
+
+    #[test]
+    fn test_build_url() {
+        let mut endpoints = Endpoints::from_base_url("http://localhost:8080/");
+        endpoints.minor_version = 70;
+        assert_eq!(
+            build_url(&endpoints, "company/123/query", Some([("query", "select * from Item")])),
+            "http://localhost:8080/v3/company/123/query?query=select%20%2A%20from%20Item&minorversion=70"
+        );
+        assert_eq!(
+            build_url(&endpoints, "company/123/upload", None::<[(&str, &str); 0]>),
+            "http://localhost:8080/v3/company/123/upload"
+        );
+    }
+}
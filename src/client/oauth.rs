@@ -0,0 +1,449 @@
+//! # OAuth 2.0 Authorization Code Flow
+//!
+//! This module connects a new `QuickBooks` company to your app, producing a
+//! [`RefreshableQBContext`] without a separate OAuth library.
+//!
+//! 1. Build an [`AuthorizationRequest`] with [`OAuthClient::authorization_request`]
+//!    (or [`OAuthClient::authorization_request_with_pkce`]), keep it around, e.g. in
+//!    the user's session, and redirect the user to its `url`.
+//! 2. `QuickBooks` redirects back to your redirect URI with `code`, `state` and `realmId`
+//!    query parameters. Pass that query to [`OAuthClient::handle_redirect`], which checks
+//!    the state and exchanges the code for tokens.
+//!
+//! ## Usage Example
+//!
+//! ```no_run
+//! use quick_oxibooks::Environment;
+//! use quick_oxibooks::client::oauth::{OAuthClient, Scope};
+//!
+//! let oauth = OAuthClient::new(
+//!     Environment::SANDBOX,
+//!     "client_id",
+//!     "client_secret",
+//!     "https://example.com/callback",
+//! ).unwrap();
+//!
+//! let request = oauth
+//!     .authorization_request_with_pkce(&[Scope::Accounting, Scope::OpenId])
+//!     .unwrap();
+//! println!("Visit {}", request.url);
+//!
+//! // Later, when QuickBooks redirects to https://example.com/callback?code=...&state=...&realmId=...
+//! let redirect_query = "code=abc&state=xyz&realmId=123";
+//! let context = oauth.handle_redirect(redirect_query, &request).unwrap();
+//! assert!(!context.is_expired());
+//! ```
+
+use std::sync::Arc;
+
+use base64::Engine;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use urlencoding::{decode, encode};
+
+use super::{
+    refresh::token_request, transport::default_transport, QBContext, RefreshableQBContext,
+    Transport,
+};
+use crate::{error::OAuthError, APIResult, DiscoveryDoc, Endpoints, Environment};
+
+/// OAuth 2.0 scopes an app can request access to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// `com.intuit.quickbooks.accounting`
+    Accounting,
+    /// `com.intuit.quickbooks.payment`
+    Payment,
+    /// `openid`
+    OpenId,
+    /// `profile`
+    Profile,
+    /// `email`
+    Email,
+    /// `phone`
+    Phone,
+    /// `address`
+    Address,
+    /// Any other scope, sent as is
+    Other(String),
+}
+
+impl Scope {
+    /// The scope as sent to the authorization endpoint
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scope::Accounting => "com.intuit.quickbooks.accounting",
+            Scope::Payment => "com.intuit.quickbooks.payment",
+            Scope::OpenId => "openid",
+            Scope::Profile => "profile",
+            Scope::Email => "email",
+            Scope::Phone => "phone",
+            Scope::Address => "address",
+            Scope::Other(scope) => scope,
+        }
+    }
+}
+
+/// A pending authorization, created before redirecting the user to `QuickBooks`.
+///
+/// Keep it until the user is redirected back, it holds the values the
+/// redirect is checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizationRequest {
+    /// URL to redirect the user to
+    pub url: String,
+    /// Random `state` parameter, checked against the one in the redirect
+    pub state: String,
+    /// PKCE code verifier, sent along with the code when exchanging it
+    pub pkce_verifier: Option<String>,
+}
+
+/// The app's OAuth 2.0 client credentials and the endpoints to use them with.
+///
+/// Token requests are sent through its [`Transport`], which the contexts it
+/// creates send their requests through too.
+#[derive(Clone)]
+pub struct OAuthClient {
+    endpoints: Endpoints,
+    discovery_doc: DiscoveryDoc,
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    transport: Arc<dyn Transport>,
+}
+
+impl std::fmt::Debug for OAuthClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthClient")
+            .field("endpoints", &self.endpoints)
+            .field("discovery_doc", &self.discovery_doc)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"[REDACTED]")
+            .field("redirect_uri", &self.redirect_uri)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OAuthClient {
+    /// Creates an OAuth client, fetching the discovery document for the environment
+    ///
+    /// Requests are sent through a [`ureq::Agent`] with the default configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the discovery document can't be fetched
+    pub fn new(
+        environment: Environment,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> APIResult<Self> {
+        Self::new_with_transport(
+            environment,
+            client_id,
+            client_secret,
+            redirect_uri,
+            default_transport(),
+        )
+    }
+
+    /// Creates an OAuth client like [`OAuthClient::new`], sending its requests
+    /// through the given [`Transport`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the discovery document can't be fetched
+    pub fn new_with_transport(
+        environment: Environment,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+        transport: Arc<dyn Transport>,
+    ) -> APIResult<Self> {
+        Self::new_with_endpoints(
+            environment.endpoints(),
+            client_id,
+            client_secret,
+            redirect_uri,
+            transport,
+        )
+    }
+
+    /// Creates an OAuth client for the given [`Endpoints`], e.g. those of a local stub
+    /// server, fetching the discovery document from their discovery URL
+    ///
+    /// The contexts it creates send their requests to the same endpoints.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the discovery document can't be fetched
+    pub fn new_with_endpoints(
+        endpoints: Endpoints,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+        transport: Arc<dyn Transport>,
+    ) -> APIResult<Self> {
+        let discovery_doc =
+            DiscoveryDoc::get_from_url(&endpoints.discovery_url, transport.as_ref())?;
+        Ok(Self {
+            endpoints,
+            discovery_doc,
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            redirect_uri: redirect_uri.into(),
+            transport,
+        })
+    }
+
+    /// Creates an OAuth client using an already fetched discovery document
+    #[must_use]
+    pub fn with_discovery_doc(
+        environment: Environment,
+        discovery_doc: DiscoveryDoc,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoints: environment.endpoints(),
+            discovery_doc,
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            redirect_uri: redirect_uri.into(),
+            transport: default_transport(),
+        }
+    }
+
+    /// Replaces the [`Transport`] requests are sent through
+    #[must_use]
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self { transport, ..self }
+    }
+
+    /// The client ID of the app
+    #[must_use]
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The discovery document the endpoints are taken from
+    #[must_use]
+    pub fn discovery_doc(&self) -> &DiscoveryDoc {
+        &self.discovery_doc
+    }
+
+    /// Builds the URL of the authorization endpoint
+    ///
+    /// `code_challenge` is the S256 PKCE challenge, if PKCE is used.
+    #[must_use]
+    pub fn authorize_url(
+        &self,
+        scopes: &[Scope],
+        state: &str,
+        code_challenge: Option<&str>,
+    ) -> String {
+        let scope = scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut url = format!(
+            "{}?client_id={}&response_type=code&scope={}&redirect_uri={}&state={}",
+            self.discovery_doc.authorization_endpoint,
+            encode(&self.client_id),
+            encode(&scope),
+            encode(&self.redirect_uri),
+            encode(state),
+        );
+        if let Some(challenge) = code_challenge {
+            url.push_str("&code_challenge=");
+            url.push_str(&encode(challenge));
+            url.push_str("&code_challenge_method=S256");
+        }
+        url
+    }
+
+    /// Starts an authorization with a random `state`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system random number generator fails
+    pub fn authorization_request(&self, scopes: &[Scope]) -> APIResult<AuthorizationRequest> {
+        let state = random_token()?;
+        Ok(AuthorizationRequest {
+            url: self.authorize_url(scopes, &state, None),
+            state,
+            pkce_verifier: None,
+        })
+    }
+
+    /// Starts an authorization with a random `state` and a PKCE code challenge
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system random number generator fails
+    pub fn authorization_request_with_pkce(
+        &self,
+        scopes: &[Scope],
+    ) -> APIResult<AuthorizationRequest> {
+        let state = random_token()?;
+        let verifier = random_token()?;
+        Ok(AuthorizationRequest {
+            url: self.authorize_url(scopes, &state, Some(&pkce_challenge(&verifier))),
+            state,
+            pkce_verifier: Some(verifier),
+        })
+    }
+
+    /// Completes an authorization from the query `QuickBooks` redirected back with
+    ///
+    /// `redirect` can be the full redirect URL or only its query. The `state` in it is
+    /// checked against the request, then the `code` is exchanged for tokens for the
+    /// company in `realmId`.
+    ///
+    /// # Errors
+    ///
+    /// - `OAuth(AuthorizationDenied)`: The user denied access
+    /// - `OAuth(StateMismatch)`: The `state` doesn't match the request
+    /// - `OAuth(MissingParameter)`: The `code` or `realmId` parameter is missing
+    /// - `OAuth(TokenRequest)`: The token endpoint rejected the code
+    pub fn handle_redirect(
+        &self,
+        redirect: &str,
+        request: &AuthorizationRequest,
+    ) -> APIResult<RefreshableQBContext> {
+        let params = RedirectParams::parse(redirect);
+        if let Some(error) = params.error {
+            return Err(OAuthError::AuthorizationDenied(error).into());
+        }
+        if params.state.as_deref() != Some(request.state.as_str()) {
+            return Err(OAuthError::StateMismatch.into());
+        }
+        let code = params.code.ok_or(OAuthError::MissingParameter("code"))?;
+        let realm_id = params
+            .realm_id
+            .ok_or(OAuthError::MissingParameter("realmId"))?;
+        self.exchange_code(&code, realm_id, request.pkce_verifier.as_deref())
+    }
+
+    /// Exchanges an authorization code for tokens for the company with the given realm ID
+    ///
+    /// # Errors
+    ///
+    /// Returns `OAuth(TokenRequest)` if the token endpoint rejects the code,
+    /// or an error if the request itself fails
+    pub fn exchange_code(
+        &self,
+        code: &str,
+        realm_id: impl Into<String>,
+        pkce_verifier: Option<&str>,
+    ) -> APIResult<RefreshableQBContext> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+        ];
+        if let Some(verifier) = pkce_verifier {
+            form.push(("code_verifier", verifier));
+        }
+
+        let tokens = token_request(
+            &self.discovery_doc.token_endpoint,
+            &self.client_id,
+            &self.client_secret,
+            &form,
+            self.transport.as_ref(),
+        )?;
+
+        let realm_id = realm_id.into();
+
+        #[cfg(feature = "logging")]
+        log::info!("Exchanged authorization code for company {realm_id}");
+
+        // Expiries are set from the token response
+        let context = QBContext::from_parts(
+            self.endpoints.clone(),
+            realm_id,
+            String::new(),
+            Utc::now(),
+            self.discovery_doc.clone(),
+            Arc::clone(&self.transport),
+        );
+        let context = context.with_refresh(String::new());
+        context.set_tokens(tokens);
+        Ok(context)
+    }
+}
+
+/// The parameters `QuickBooks` adds to the redirect URI
+#[derive(Debug, Default)]
+struct RedirectParams {
+    code: Option<String>,
+    state: Option<String>,
+    realm_id: Option<String>,
+    error: Option<String>,
+}
+
+impl RedirectParams {
+    fn parse(redirect: &str) -> Self {
+        let query = redirect.split_once('?').map_or(redirect, |(_, q)| q);
+        let query = query.split_once('#').map_or(query, |(q, _)| q);
+
+        let mut params = Self::default();
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(&value.replace('+', " "))
+                .map_or_else(|_| value.to_string(), std::borrow::Cow::into_owned);
+            match key {
+                "code" => params.code = Some(value),
+                "state" => params.state = Some(value),
+                "realmId" => params.realm_id = Some(value),
+                "error" => params.error = Some(value),
+                _ => {}
+            }
+        }
+        params
+    }
+}
+
+/// Generates 32 random bytes, base64url encoded without padding
+fn random_token() -> APIResult<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(std::io::Error::from)?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Derives the S256 PKCE code challenge from the code verifier
+fn pkce_challenge(verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pkce_challenge, RedirectParams};
+
+    #[test]
+    fn test_pkce_challenge() {
+        // Example from RFC 7636, Appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_redirect_params() {
+        let params = RedirectParams::parse(
+            "https://example.com/callback?code=AB%2Fc&state=xyz&realmId=123#_=_",
+        );
+        assert_eq!(params.code.as_deref(), Some("AB/c"));
+        assert_eq!(params.state.as_deref(), Some("xyz"));
+        assert_eq!(params.realm_id.as_deref(), Some("123"));
+        assert!(params.error.is_none());
+
+        let params = RedirectParams::parse("error=access_denied&state=xyz");
+        assert_eq!(params.error.as_deref(), Some("access_denied"));
+    }
+}
@@ -1,72 +1,627 @@
+use std::sync::{Arc, MutexGuard, PoisonError};
+
 use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use ureq::{http::Request, Agent};
+use ureq::http::{Request, StatusCode};
+use urlencoding::encode;
+
+use super::{
+    token_store::{StoredTokens, TokenStore},
+    QBContext, Transport,
+};
+use crate::{
+    error::{APIError, OAuthError},
+    APIResult,
+};
 
-use super::QBContext;
-use crate::error::{APIError, APIErrorInner};
+/// How long before the refresh token expires [`RefreshableQBContext`] starts warning about it
+pub const REFRESH_TOKEN_EXPIRY_WARNING: Duration = Duration::days(7);
+
+/// How long before the access token expires auto-refresh renews it, see
+/// [`RefreshableQBContext::with_auto_refresh`]
+pub const AUTO_REFRESH_MARGIN: Duration = Duration::minutes(5);
 
 /// `RefreshableQBContext`
 ///
 /// Holds a `QBContext` along with a refresh token to allow refreshing access tokens
+///
+/// The expiry of the refresh token is tracked from the `x_refresh_token_expires_in`
+/// sent with every new token. Once it is known, refreshing with an expired refresh
+/// token fails early with [`OAuthError::RefreshTokenExpired`], and with the `logging`
+/// feature a warning is logged on every refresh within
+/// [`REFRESH_TOKEN_EXPIRY_WARNING`] of the expiry. Past that point the company has
+/// to be connected again through the OAuth flow.
+///
+/// With a [`TokenStore`] attached, every refresh first picks up tokens another
+/// context or process already refreshed, and stores the new tokens as soon as
+/// they are received, through [`TokenStore::compare_and_swap`].
+///
+/// With [`RefreshableQBContext::with_auto_refresh`], requests made through the
+/// context refresh the access token on their own, see there.
 pub struct RefreshableQBContext {
     pub(crate) context: QBContext,
+}
+
+/// The refresh token of a refreshable context, locked while refreshing so
+/// refreshes through a shared `&QBContext` run one at a time
+pub(crate) struct RefreshState {
     pub(crate) refresh_token: String,
+    pub(crate) refresh_token_expires_at: Option<DateTime<Utc>>,
+    pub(crate) token_store: Option<Arc<dyn TokenStore>>,
+    /// Client credentials, set when auto-refresh is enabled
+    pub(crate) credentials: Option<(String, String)>,
+}
+
+impl RefreshState {
+    pub(crate) fn new(refresh_token: String) -> Self {
+        Self {
+            refresh_token,
+            refresh_token_expires_at: None,
+            token_store: None,
+            credentials: None,
+        }
+    }
+
+    fn refresh_token_expires_within(&self, duration: Duration) -> bool {
+        self.refresh_token_expires_at
+            .is_some_and(|expires_at| Utc::now() + duration >= expires_at)
+    }
 }
 
 impl RefreshableQBContext {
     /// Refreshes the access token using the refresh token
+    ///
+    /// Only one refresh runs at a time. If another refresh runs when this is called,
+    /// e.g. through a [`SharedQBContext`], it waits for that refresh and uses its token
+    /// instead of refreshing again.
+    ///
+    /// With a token store attached, tokens that were already refreshed elsewhere are
+    /// used instead if their access token is still valid, and the new tokens are
+    /// stored before returning.
+    ///
+    /// # Errors
+    ///
+    /// - `OAuth(RefreshTokenExpired)`: The refresh token is known to have expired
+    /// - `OAuth(TokenRequest)`: The token endpoint rejected the refresh token
+    /// - Errors from the token store
     pub fn refresh_access_token(
-        &mut self,
+        &self,
         client_id: &str,
         client_secret: &str,
-        client: &Agent,
     ) -> Result<(), APIError> {
-        let auth_string = format!("{client_id}:{client_secret}");
-        let auth_string = base64::engine::general_purpose::STANDARD.encode(auth_string);
+        let seen_token = self.context.access_token();
+        let mut state = self.state();
+        if self.context.access_token() != seen_token {
+            return Ok(());
+        }
+        self.context
+            .refresh_locked(&mut state, client_id, client_secret)
+    }
 
-        let request = Request::post(self.context.discovery_doc.token_endpoint.as_str())
-            .header("Authorization", format!("Basic {auth_string}"))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .header("Accept", "application/json")
-            .body(format!(
-                "grant_type=refresh_token&refresh_token={}",
-                &self.refresh_token
-            ))?;
+    /// Wraps the context in a clonable handle for sharing it between threads
+    #[must_use]
+    pub fn into_shared(self) -> SharedQBContext {
+        SharedQBContext(Arc::new(self))
+    }
 
-        let response = client.run(request)?;
+    /// Enables refreshing the access token automatically with the given client credentials
+    ///
+    /// Requests made through the context then refresh the access token before they
+    /// are sent if it expires within [`AUTO_REFRESH_MARGIN`], and once more if
+    /// `QuickBooks` rejects it with a 401 anyway, after which the request is retried
+    /// once. This works through the `&QBContext` every operation takes, so the
+    /// context can be passed to them as is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use quick_oxibooks::{QBContext, Environment};
+    /// use quick_oxibooks::functions::query::QBQuery;
+    /// use quickbooks_types::Customer;
+    ///
+    /// let context = QBContext::new(
+    ///     Environment::SANDBOX,
+    ///     "company_123".to_string(),
+    ///     "access_token_xyz".to_string(),
+    /// ).unwrap()
+    /// .with_refresh("refresh_token_abc".to_string())
+    /// .with_auto_refresh("client_id", "client_secret");
+    ///
+    /// let customers = Customer::query("WHERE Active = true", None, &context).unwrap();
+    /// ```
+    #[must_use]
+    pub fn with_auto_refresh(
+        self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        self.state().credentials = Some((client_id.into(), client_secret.into()));
+        self
+    }
 
-        if !response.status().is_success() {
-            return Err(APIErrorInner::InvalidClient.into());
+    /// Attaches a token store, see [`TokenStore`]
+    ///
+    /// If the store already holds different tokens for the company they replace the
+    /// context's tokens, otherwise the context's tokens are stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token store fails
+    pub fn with_token_store(self, store: Arc<dyn TokenStore>) -> APIResult<Self> {
+        {
+            let mut state = self.state();
+            let tokens = self.context.stored_tokens(&state);
+            let saved =
+                store.compare_and_swap(&self.context.company_id, &tokens.refresh_token, &tokens)?;
+            state.token_store = Some(store);
+            if !saved {
+                self.context.sync_from_store(&mut state)?;
+            }
         }
+        Ok(self)
+    }
 
-        let AuthTokenResponse {
-            access_token,
-            refresh_token,
-            expires_in,
-            ..
-        } = response.into_body().read_json()?;
+    /// The current tokens, in the shape they are stored in a [`TokenStore`]
+    #[must_use]
+    pub fn stored_tokens(&self) -> StoredTokens {
+        self.context.stored_tokens(&self.state())
+    }
 
-        self.refresh_token = refresh_token;
-        self.context.access_token = access_token;
-        self.context.expires_in =
-            chrono::Utc::now() + chrono::Duration::seconds(expires_in.cast_signed());
+    /// Revokes the refresh token, disconnecting the company from the app
+    ///
+    /// Revoking the refresh token also revokes every access token issued with it,
+    /// so the context can't be used afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OAuth(Revocation)` with the error sent by the revocation endpoint
+    /// if the token is rejected, or an error if the request itself fails
+    pub fn revoke(&self, client_id: &str, client_secret: &str) -> APIResult<()> {
+        let request = Request::post(self.context.discovery_doc.revocation_endpoint.as_str())
+            .header("Authorization", basic_auth(client_id, client_secret))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(serde_json::to_vec(&serde_json::json!({
+                "token": self.refresh_token()
+            }))?)?;
+
+        let response = self.context.transport.send(request)?;
+        let status = response.status();
+        if !status.is_success() {
+            let error = serde_json::from_slice(response.body()).unwrap_or_default();
+            return Err(OAuthError::Revocation {
+                status: status.as_u16(),
+                error,
+            }
+            .into());
+        }
+
+        #[cfg(feature = "logging")]
+        log::info!("Revoked tokens for company {}", self.context.company_id);
 
         Ok(())
     }
 
+    /// The current refresh token
+    #[must_use]
+    pub fn refresh_token(&self) -> String {
+        self.state().refresh_token.clone()
+    }
+
+    /// When the refresh token expires, if known
+    #[must_use]
+    pub fn refresh_token_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.state().refresh_token_expires_at
+    }
+
+    /// Returns `true` if the refresh token is known to expire within the given duration
+    #[must_use]
+    pub fn refresh_token_expires_within(&self, duration: Duration) -> bool {
+        self.state().refresh_token_expires_within(duration)
+    }
+
+    /// Sets when the refresh token expires, e.g. when restoring a saved refresh token
     #[must_use]
-    pub fn refresh_token(&self) -> &str {
-        &self.refresh_token
+    pub fn with_refresh_token_expiry(self, expires_at: DateTime<Utc>) -> Self {
+        self.state().refresh_token_expires_at = Some(expires_at);
+        self
+    }
+
+    /// Stores the tokens returned by the token endpoint
+    #[cfg(feature = "oauth")]
+    pub(crate) fn set_tokens(&self, tokens: AuthTokenResponse) {
+        self.context.set_tokens(&mut self.state(), tokens);
+    }
+
+    fn state(&self) -> MutexGuard<'_, RefreshState> {
+        self.context
+            .refresh
+            .as_ref()
+            .expect("RefreshableQBContext always has a refresh state")
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A clonable handle to a [`RefreshableQBContext`], for sharing one context
+/// between threads.
+///
+/// Every clone uses the same tokens and rate limiters. Any clone can refresh the
+/// access token, explicitly or through auto-refresh, while the others keep making
+/// requests. Only one refresh runs at a time; callers that find the token expired
+/// while it runs wait for it and use the new token instead of refreshing again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::query::QBQuery;
+/// use quickbooks_types::Customer;
+///
+/// let context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+/// ).unwrap()
+/// .with_refresh("refresh_token_abc".to_string())
+/// .with_auto_refresh("client_id", "client_secret")
+/// .into_shared();
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let context = context.clone();
+///         std::thread::spawn(move || Customer::query("", Some(10), &context))
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap().unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct SharedQBContext(Arc<RefreshableQBContext>);
+
+impl From<RefreshableQBContext> for SharedQBContext {
+    fn from(context: RefreshableQBContext) -> Self {
+        context.into_shared()
+    }
+}
+
+impl std::ops::Deref for SharedQBContext {
+    type Target = RefreshableQBContext;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl QBContext {
+    /// Refreshes the access token before a request if auto-refresh is enabled
+    /// and the token expires within [`AUTO_REFRESH_MARGIN`]
+    pub(crate) fn refresh_if_expiring(&self) -> APIResult<()> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(());
+        };
+        if !self.expires_within(AUTO_REFRESH_MARGIN) {
+            return Ok(());
+        }
+        let mut state = refresh.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some((client_id, client_secret)) = state.credentials.clone() else {
+            return Ok(());
+        };
+        // Someone else may have refreshed while we waited for the lock
+        if !self.expires_within(AUTO_REFRESH_MARGIN) {
+            return Ok(());
+        }
+        self.refresh_locked(&mut state, &client_id, &client_secret)
+    }
+
+    /// Refreshes the access token after `QuickBooks` rejected `rejected_token`,
+    /// unless it was already replaced since
+    ///
+    /// Returns `false` if auto-refresh is not enabled, in which case the request
+    /// shouldn't be retried.
+    pub(crate) fn refresh_rejected(&self, rejected_token: &str) -> APIResult<bool> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(false);
+        };
+        let mut state = refresh.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some((client_id, client_secret)) = state.credentials.clone() else {
+            return Ok(false);
+        };
+        if self.access_token() == rejected_token {
+            #[cfg(feature = "logging")]
+            log::info!(
+                "Access token for company {} was rejected, refreshing",
+                self.company_id
+            );
+            self.refresh_locked(&mut state, &client_id, &client_secret)?;
+        }
+        Ok(true)
+    }
+
+    /// [`QBContext::refresh_if_expiring`] for requests through the async client
+    ///
+    /// Refreshes through the async client run one at a time, without blocking the
+    /// executor while they wait for each other.
+    #[cfg(feature = "async")]
+    pub(crate) async fn refresh_if_expiring_async(
+        &self,
+        client: &reqwest::Client,
+    ) -> APIResult<()> {
+        if self.refresh.is_none() || !self.expires_within(AUTO_REFRESH_MARGIN) {
+            return Ok(());
+        }
+        let _gate = self.refresh_gate.lock().await;
+        // Someone else may have refreshed while we waited for the gate
+        if !self.expires_within(AUTO_REFRESH_MARGIN) {
+            return Ok(());
+        }
+        self.refresh_async(client).await.map(drop)
+    }
+
+    /// [`QBContext::refresh_rejected`] for requests through the async client
+    #[cfg(feature = "async")]
+    pub(crate) async fn refresh_rejected_async(
+        &self,
+        rejected_token: &str,
+        client: &reqwest::Client,
+    ) -> APIResult<bool> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(false);
+        };
+        let _gate = self.refresh_gate.lock().await;
+        if self.access_token() != rejected_token {
+            let state = refresh.lock().unwrap_or_else(PoisonError::into_inner);
+            return Ok(state.credentials.is_some());
+        }
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Access token for company {} was rejected, refreshing",
+            self.company_id
+        );
+        self.refresh_async(client).await
+    }
+
+    /// Refreshes the access token through the async client, the refresh gate has to
+    /// be held by the caller
+    ///
+    /// The refresh state is only locked around the token request, never across it.
+    /// Returns `false` if auto-refresh is not enabled.
+    #[cfg(feature = "async")]
+    async fn refresh_async(&self, client: &reqwest::Client) -> APIResult<bool> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(false);
+        };
+        let ((client_id, client_secret), previous_refresh_token) = {
+            let mut state = refresh.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some(credentials) = state.credentials.clone() else {
+                return Ok(false);
+            };
+            if !self.needs_token_request(&mut state)? {
+                return Ok(true);
+            }
+            (credentials, state.refresh_token.clone())
+        };
+
+        let tokens = token_request_async(
+            &self.discovery_doc.token_endpoint,
+            &client_id,
+            &client_secret,
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &previous_refresh_token),
+            ],
+            &self.transport,
+            client,
+        )
+        .await?;
+        let mut state = refresh.lock().unwrap_or_else(PoisonError::into_inner);
+        self.apply_refreshed(&mut state, &previous_refresh_token, tokens)?;
+        Ok(true)
+    }
+
+    /// Refreshes the access token, the refresh state has to be locked by the caller
+    fn refresh_locked(
+        &self,
+        state: &mut RefreshState,
+        client_id: &str,
+        client_secret: &str,
+    ) -> APIResult<()> {
+        if !self.needs_token_request(state)? {
+            return Ok(());
+        }
+
+        let previous_refresh_token = state.refresh_token.clone();
+        let tokens = token_request(
+            &self.discovery_doc.token_endpoint,
+            client_id,
+            client_secret,
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &previous_refresh_token),
+            ],
+            self.transport.as_ref(),
+        )?;
+        self.apply_refreshed(state, &previous_refresh_token, tokens)
+    }
+
+    /// Checks whether refreshing still needs a request to the token endpoint, after
+    /// picking up tokens refreshed elsewhere
+    fn needs_token_request(&self, state: &mut RefreshState) -> APIResult<bool> {
+        if self.sync_from_store(state)? && !self.is_expired() {
+            return Ok(false);
+        }
+
+        if let Some(expires_at) = state.refresh_token_expires_at {
+            if Utc::now() >= expires_at {
+                return Err(OAuthError::RefreshTokenExpired(expires_at).into());
+            }
+        }
+        Ok(true)
+    }
+
+    /// Stores the tokens a refresh with `previous_refresh_token` returned, and saves
+    /// them to the token store
+    fn apply_refreshed(
+        &self,
+        state: &mut RefreshState,
+        previous_refresh_token: &str,
+        tokens: AuthTokenResponse,
+    ) -> APIResult<()> {
+        self.set_tokens(state, tokens);
+
+        if let Some(store) = &state.token_store {
+            let stored = store.compare_and_swap(
+                &self.company_id,
+                previous_refresh_token,
+                &self.stored_tokens(state),
+            )?;
+            if !stored {
+                // Refreshed concurrently somewhere else, the stored tokens win
+                #[cfg(feature = "logging")]
+                log::info!(
+                    "Tokens for company {} were refreshed concurrently, using the stored ones",
+                    self.company_id
+                );
+                self.sync_from_store(state)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stored_tokens(&self, state: &RefreshState) -> StoredTokens {
+        let token = self.token.read().unwrap_or_else(PoisonError::into_inner);
+        StoredTokens {
+            refresh_token: state.refresh_token.clone(),
+            refresh_token_expires_at: state.refresh_token_expires_at,
+            access_token: token.access_token.clone(),
+            access_token_expires_at: token.expires_at,
+        }
+    }
+
+    /// Replaces the tokens with the ones in the token store if they differ,
+    /// returns whether they were replaced
+    fn sync_from_store(&self, state: &mut RefreshState) -> APIResult<bool> {
+        let Some(store) = &state.token_store else {
+            return Ok(false);
+        };
+        match store.load(&self.company_id)? {
+            Some(stored) if stored.refresh_token != state.refresh_token => {
+                self.set_stored_tokens(state, stored);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub(crate) fn set_stored_tokens(&self, state: &mut RefreshState, stored: StoredTokens) {
+        state.refresh_token = stored.refresh_token;
+        state.refresh_token_expires_at = stored.refresh_token_expires_at;
+        self.set_access_token(stored.access_token, stored.access_token_expires_at);
+    }
+
+    /// Stores the tokens returned by the token endpoint
+    fn set_tokens(&self, state: &mut RefreshState, tokens: AuthTokenResponse) {
+        let now = Utc::now();
+        state.refresh_token = tokens.refresh_token;
+        state.refresh_token_expires_at =
+            Some(now + Duration::seconds(tokens.x_refresh_token_expires_in.cast_signed()));
+        self.set_access_token(
+            tokens.access_token,
+            now + Duration::seconds(tokens.expires_in.cast_signed()),
+        );
+
+        #[cfg(feature = "logging")]
+        if state.refresh_token_expires_within(REFRESH_TOKEN_EXPIRY_WARNING) {
+            log::warn!(
+                "Refresh token for company {} expires at {:?}, the company will need to be reconnected",
+                self.company_id,
+                state.refresh_token_expires_at
+            );
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
-struct AuthTokenResponse {
-    token_type: String,
-    expires_in: u64,
-    refresh_token: String,
-    x_refresh_token_expires_in: u64,
-    access_token: String,
+pub(crate) struct AuthTokenResponse {
+    pub(crate) token_type: String,
+    pub(crate) expires_in: u64,
+    pub(crate) refresh_token: String,
+    pub(crate) x_refresh_token_expires_in: u64,
+    pub(crate) access_token: String,
+}
+
+/// Sends a form encoded request to the token endpoint, authenticated with the
+/// client credentials, and parses the tokens it returns
+///
+/// Failed requests are returned as [`OAuthError::TokenRequest`] with the
+/// `error` and `error_description` sent by the endpoint.
+pub(crate) fn token_request(
+    token_endpoint: &str,
+    client_id: &str,
+    client_secret: &str,
+    form: &[(&str, &str)],
+    transport: &dyn Transport,
+) -> Result<AuthTokenResponse, APIError> {
+    let request = token_http_request(token_endpoint, client_id, client_secret, form)?;
+    let response = transport.send(request)?;
+    token_response(response.status(), response.body())
+}
+
+/// [`token_request`] through the async client, or the transport if it
+/// [sends async requests](Transport::sends_async_requests)
+#[cfg(feature = "async")]
+pub(crate) async fn token_request_async(
+    token_endpoint: &str,
+    client_id: &str,
+    client_secret: &str,
+    form: &[(&str, &str)],
+    transport: &Arc<dyn Transport>,
+    client: &reqwest::Client,
+) -> Result<AuthTokenResponse, APIError> {
+    let request = token_http_request(token_endpoint, client_id, client_secret, form)?;
+    let response = crate::asynchronous::send(transport, client, request).await?;
+    token_response(response.status(), response.body())
+}
+
+fn token_http_request(
+    token_endpoint: &str,
+    client_id: &str,
+    client_secret: &str,
+    form: &[(&str, &str)],
+) -> APIResult<Request<Vec<u8>>> {
+    let body = form
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    Ok(Request::post(token_endpoint)
+        .header("Authorization", basic_auth(client_id, client_secret))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(body.into_bytes())?)
+}
+
+fn token_response(status: StatusCode, body: &[u8]) -> APIResult<AuthTokenResponse> {
+    if !status.is_success() {
+        let error = serde_json::from_slice(body).unwrap_or_default();
+        return Err(OAuthError::TokenRequest {
+            status: status.as_u16(),
+            error,
+        }
+        .into());
+    }
+
+    Ok(serde_json::from_slice(body)?)
+}
+
+/// Builds the `Authorization` header value for the client credentials
+pub(crate) fn basic_auth(client_id: &str, client_secret: &str) -> String {
+    let auth_string = format!("{client_id}:{client_secret}");
+    let auth_string = base64::engine::general_purpose::STANDARD.encode(auth_string);
+    format!("Basic {auth_string}")
 }
 
 impl std::ops::Deref for RefreshableQBContext {
@@ -75,3 +630,14 @@ impl std::ops::Deref for RefreshableQBContext {
         &self.context
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SharedQBContext;
+
+    #[test]
+    fn test_shared_context_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync + Clone + 'static>() {}
+        assert_send_sync::<SharedQBContext>();
+    }
+}
@@ -0,0 +1,269 @@
+//! Persistent storage for OAuth tokens.
+//!
+//! `QuickBooks` rotates the refresh token on every refresh, and the previous one
+//! stops working soon after. A [`TokenStore`] attached to a [`RefreshableQBContext`]
+//! is updated as part of every refresh, so the newest refresh token is never only
+//! held in memory.
+//!
+//! [`RefreshableQBContext`]: super::RefreshableQBContext
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use chrono::{DateTime, Utc};
+use fs4::fs_std::FileExt;
+use serde::{Deserialize, Serialize};
+
+use crate::APIResult;
+
+/// The tokens of a single company, as kept in a [`TokenStore`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredTokens {
+    pub refresh_token: String,
+    pub refresh_token_expires_at: Option<DateTime<Utc>>,
+    pub access_token: String,
+    pub access_token_expires_at: DateTime<Utc>,
+}
+
+/// Storage for the tokens of one or more companies, keyed by realm ID.
+///
+/// [`TokenStore::compare_and_swap`] is what makes sharing a store safe: a refresh
+/// only replaces the stored tokens if nobody else replaced them since they were
+/// read. Implementations shared between processes have to make it atomic across
+/// those processes, like [`FileTokenStore`] does with a lock file.
+pub trait TokenStore: Send + Sync {
+    /// Returns the stored tokens of the company, if any
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn load(&self, realm_id: &str) -> APIResult<Option<StoredTokens>>;
+
+    /// Stores the tokens of the company, replacing any stored ones
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn save(&self, realm_id: &str, tokens: &StoredTokens) -> APIResult<()>;
+
+    /// Stores the tokens of the company only if the currently stored refresh token
+    /// is `expected_refresh_token`, or nothing is stored yet
+    ///
+    /// Returns `false` without storing anything if the stored refresh token differs.
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn compare_and_swap(
+        &self,
+        realm_id: &str,
+        expected_refresh_token: &str,
+        tokens: &StoredTokens,
+    ) -> APIResult<bool>;
+}
+
+/// [`TokenStore`] that keeps the tokens in memory, for sharing between contexts
+/// in one process.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<HashMap<String, StoredTokens>>,
+}
+
+impl MemoryTokenStore {
+    /// Creates an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self, realm_id: &str) -> APIResult<Option<StoredTokens>> {
+        let tokens = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(tokens.get(realm_id).cloned())
+    }
+
+    fn save(&self, realm_id: &str, tokens: &StoredTokens) -> APIResult<()> {
+        let mut stored = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        stored.insert(realm_id.to_string(), tokens.clone());
+        Ok(())
+    }
+
+    fn compare_and_swap(
+        &self,
+        realm_id: &str,
+        expected_refresh_token: &str,
+        tokens: &StoredTokens,
+    ) -> APIResult<bool> {
+        let mut stored = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        if stored
+            .get(realm_id)
+            .is_some_and(|current| current.refresh_token != expected_refresh_token)
+        {
+            return Ok(false);
+        }
+        stored.insert(realm_id.to_string(), tokens.clone());
+        Ok(true)
+    }
+}
+
+/// [`TokenStore`] that keeps the tokens of every company in one JSON file.
+///
+/// Writes go to a temporary file that is renamed over the store, so the file always
+/// holds a complete set of tokens, even if the process crashes mid-write. Every
+/// operation holds an advisory lock on a `.lock` file next to the store, so several
+/// processes on the same host can share it.
+#[derive(Debug, Clone)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    /// Creates a store backed by the file at the given path,
+    /// the file is created on the first save
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The path of the JSON file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sibling_path(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    }
+
+    /// Opens the lock file, the lock is released when the file is dropped
+    fn lock_file(&self) -> APIResult<File> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.sibling_path(".lock"))?)
+    }
+
+    fn read_all(&self) -> APIResult<HashMap<String, StoredTokens>> {
+        match fs::read(&self.path) {
+            Ok(bytes) if bytes.is_empty() => Ok(HashMap::new()),
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_all(&self, tokens: &HashMap<String, StoredTokens>) -> APIResult<()> {
+        let tmp_path = self.sibling_path(".tmp");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Only the owner may read the tokens
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        // The mode only applies to new files, not to one left behind by a crash
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        serde_json::to_writer_pretty(&mut file, tokens)?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, realm_id: &str) -> APIResult<Option<StoredTokens>> {
+        let lock = self.lock_file()?;
+        FileExt::lock_shared(&lock)?;
+        Ok(self.read_all()?.remove(realm_id))
+    }
+
+    fn save(&self, realm_id: &str, tokens: &StoredTokens) -> APIResult<()> {
+        let lock = self.lock_file()?;
+        FileExt::lock_exclusive(&lock)?;
+        let mut stored = self.read_all()?;
+        stored.insert(realm_id.to_string(), tokens.clone());
+        self.write_all(&stored)
+    }
+
+    fn compare_and_swap(
+        &self,
+        realm_id: &str,
+        expected_refresh_token: &str,
+        tokens: &StoredTokens,
+    ) -> APIResult<bool> {
+        let lock = self.lock_file()?;
+        FileExt::lock_exclusive(&lock)?;
+        let mut stored = self.read_all()?;
+        if stored
+            .get(realm_id)
+            .is_some_and(|current| current.refresh_token != expected_refresh_token)
+        {
+            return Ok(false);
+        }
+        stored.insert(realm_id.to_string(), tokens.clone());
+        self.write_all(&stored)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{FileTokenStore, MemoryTokenStore, StoredTokens, TokenStore};
+
+    fn tokens(refresh_token: &str) -> StoredTokens {
+        StoredTokens {
+            refresh_token: refresh_token.to_string(),
+            refresh_token_expires_at: None,
+            access_token: "access".to_string(),
+            access_token_expires_at: Utc::now(),
+        }
+    }
+
+    fn check_compare_and_swap(store: &dyn TokenStore) {
+        assert!(store.load("123").unwrap().is_none());
+        assert!(store.compare_and_swap("123", "old", &tokens("a")).unwrap());
+        assert!(!store.compare_and_swap("123", "old", &tokens("b")).unwrap());
+        assert!(store.compare_and_swap("123", "a", &tokens("c")).unwrap());
+        assert_eq!(store.load("123").unwrap().unwrap().refresh_token, "c");
+
+        store.save("456", &tokens("d")).unwrap();
+        assert_eq!(store.load("456").unwrap().unwrap().refresh_token, "d");
+        assert_eq!(store.load("123").unwrap().unwrap().refresh_token, "c");
+    }
+
+    #[test]
+    fn test_memory_token_store() {
+        check_compare_and_swap(&MemoryTokenStore::new());
+    }
+
+    #[test]
+    fn test_file_token_store() {
+        let store = FileTokenStore::new(std::env::temp_dir().join(format!(
+            "quick_oxibooks_tokens_test_{}.json",
+            std::process::id()
+        )));
+        check_compare_and_swap(&store);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(store.path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(store.path()).unwrap();
+        std::fs::remove_file(store.sibling_path(".lock")).unwrap();
+    }
+}
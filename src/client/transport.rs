@@ -0,0 +1,192 @@
+//! The HTTP transport requests to `QuickBooks` are sent through.
+
+use std::sync::Arc;
+
+use ureq::{
+    http::{Request, Response},
+    Agent,
+};
+
+use crate::APIResult;
+
+/// Sends the HTTP requests of a [`QBContext`](crate::QBContext).
+///
+/// A transport takes a fully built request, with its URL, headers and body, and
+/// returns the response with its status, headers and body, whatever the status is.
+/// Authentication, rate limiting, retries and parsing the response are left to the
+/// context, so a transport only has to move bytes. Implement it to send requests
+/// through a proxy, with a custom TLS setup or to a fake backend, and give it to
+/// the context with [`QBContext::new_with_transport`](crate::QBContext::new_with_transport).
+///
+/// [`ureq::Agent`] implements it, and a context created with
+/// [`QBContext::new`](crate::QBContext::new) sends through an agent with the default
+/// configuration. An agent reads response bodies up to [`DEFAULT_BODY_LIMIT`], wrap it
+/// in an [`AgentTransport`] to read more or less. The async API in [`crate::asynchronous`] sends through the
+/// `reqwest::Client` it is given instead, unless the transport asks for its requests
+/// with [`Transport::sends_async_requests`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use quick_oxibooks::{APIResult, QBContext, Environment};
+/// use quick_oxibooks::client::Transport;
+/// use ureq::http::{Request, Response};
+/// use ureq::Agent;
+///
+/// struct LoggingTransport(Agent);
+///
+/// impl Transport for LoggingTransport {
+///     fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+///         println!("{} {}", request.method(), request.uri());
+///         self.0.send(request)
+///     }
+/// }
+///
+/// let context = QBContext::new_with_transport(
+///     Environment::SANDBOX,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+///     Arc::new(LoggingTransport(Agent::new_with_defaults())),
+/// )
+/// .unwrap();
+/// ```
+pub trait Transport: Send + Sync {
+    /// Sends the request and returns the response, whatever its status
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no response was received, e.g. because the connection
+    /// failed or timed out
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>>;
+
+    /// Returns `true` if the requests of the async API should be sent through this
+    /// transport too, on Tokio's blocking thread pool, instead of through the
+    /// `reqwest::Client` they are given
+    ///
+    /// `false` unless overridden. A [`Cassette`](crate::testing::Cassette) returns
+    /// `true`, so it records and replays the async API as well.
+    fn sends_async_requests(&self) -> bool {
+        false
+    }
+}
+
+/// The largest response body read through a [`ureq::Agent`] by default, 100 MiB.
+///
+/// PDFs and reports can be larger than the 10 MB ureq reads by default.
+pub const DEFAULT_BODY_LIMIT: u64 = 100 * 1024 * 1024;
+
+/// A [`Transport`] sending through a [`ureq::Agent`], failing on response bodies
+/// larger than its limit.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::client::AgentTransport;
+/// use ureq::Agent;
+///
+/// let transport = AgentTransport::new(Agent::new_with_defaults())
+///     .with_body_limit(500 * 1024 * 1024);
+/// let context = QBContext::new_with_transport(
+///     Environment::SANDBOX,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+///     Arc::new(transport),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct AgentTransport {
+    agent: Agent,
+    body_limit: u64,
+}
+
+impl AgentTransport {
+    /// Sends through the given agent, reading bodies up to [`DEFAULT_BODY_LIMIT`]
+    #[must_use]
+    pub fn new(agent: Agent) -> Self {
+        Self {
+            agent,
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+
+    /// Sets the largest response body to read, in bytes
+    #[must_use]
+    pub fn with_body_limit(self, body_limit: u64) -> Self {
+        Self { body_limit, ..self }
+    }
+}
+
+impl Transport for AgentTransport {
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+        send_with_limit(&self.agent, request, self.body_limit)
+    }
+}
+
+impl Transport for Agent {
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+        send_with_limit(self, request, DEFAULT_BODY_LIMIT)
+    }
+}
+
+fn send_with_limit(
+    agent: &Agent,
+    request: Request<Vec<u8>>,
+    body_limit: u64,
+) -> APIResult<Response<Vec<u8>>> {
+    let request = agent
+        .configure_request(request)
+        .http_status_as_error(false)
+        .build();
+    let (parts, mut body) = agent.run(request)?.into_parts();
+    let body = body.with_config().limit(body_limit).read_to_vec()?;
+    Ok(Response::from_parts(parts, body))
+}
+
+/// The transport of contexts created without one, an agent with the default configuration
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(Agent::new_with_defaults())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use ureq::{http::Request, Agent};
+
+    use super::{AgentTransport, Transport};
+
+    #[test]
+    fn test_body_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/report", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let body = "x".repeat(2048);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let request = || Request::get(url.as_str()).body(Vec::new()).unwrap();
+        let transport = AgentTransport::new(Agent::new_with_defaults());
+        assert_eq!(transport.send(request()).unwrap().body().len(), 2048);
+        let transport = transport.with_body_limit(1024);
+        assert!(transport.send(request()).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for `QuickBooks` API operations.
 
+use chrono::{DateTime, Utc};
 use quickbooks_types::QBTypeError;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -17,11 +18,9 @@ use crate::batch::{QBBatchOperation, QBBatchResponseData};
 /// ```no_run
 /// use quick_oxibooks::error::APIError;
 /// use quick_oxibooks::{QBContext, Environment};
-/// use ureq::Agent;
 ///
 /// fn create_context() -> Result<QBContext, APIError> {
-///     let client = Agent::new_with_defaults();
-///     QBContext::new_from_env(Environment::SANDBOX, &client)
+///     QBContext::new_from_env(Environment::SANDBOX)
 /// }
 ///
 /// let _ = match create_context() {
@@ -45,8 +44,8 @@ use crate::batch::{QBBatchOperation, QBBatchResponseData};
 /// use quick_oxibooks::functions::create::QBCreate;
 /// use quickbooks_types::{Customer, QBItem};
 ///
-/// fn handle_customer_creation(customer: &Customer, qb_context: &quick_oxibooks::QBContext, client: &ureq::Agent) {
-///     match customer.create(qb_context, client) {
+/// fn handle_customer_creation(customer: &Customer, qb_context: &quick_oxibooks::QBContext) {
+///     match customer.create(qb_context) {
 ///         Ok(created) => println!("Created: {:?}", created.id()),
 ///         Err(e) => {
 ///             match &*e {
@@ -77,6 +76,13 @@ impl std::error::Error for APIError {
     }
 }
 
+impl APIError {
+    /// Takes the inner error out of its box
+    pub(crate) fn into_inner(self) -> APIErrorInner {
+        *self.0
+    }
+}
+
 impl std::ops::Deref for APIError {
     type Target = APIErrorInner;
     fn deref(&self) -> &Self::Target {
@@ -104,20 +110,29 @@ where
 /// ## Network and HTTP Errors
 /// - [`UreqError`](APIErrorInner::UreqError): HTTP client errors (network, timeout, etc.)
 /// - [`HttpError`](APIErrorInner::HttpError): HTTP protocol errors (malformed requests, etc.)
+/// - `ReqwestError`: HTTP client errors of the async API, with the `async` feature
 /// - [`IoError`](APIErrorInner::IoError): I/O errors (file operations, etc.)
 ///
 /// ## API Response Errors
 /// - [`BadRequest`](APIErrorInner::BadRequest): `QuickBooks` API returned an error response
 /// - [`InvalidClient`](APIErrorInner::InvalidClient): Authentication/authorization failures
-/// - [`ThrottleLimitReached`](APIErrorInner::ThrottleLimitReached): Rate limit exceeded
+/// - [`OAuth`](APIErrorInner::OAuth): OAuth authorization or token endpoint failures
+/// - [`InvalidWebhookSignature`](APIErrorInner::InvalidWebhookSignature): Webhook payload failed signature verification
+/// - [`ThrottleLimitReached`](APIErrorInner::ThrottleLimitReached): Rate limit exceeded, with the time until it resets if known
+/// - [`ConflictRetriesExhausted`](APIErrorInner::ConflictRetriesExhausted): Stale `SyncToken` conflict persisted after retrying
 ///
 /// ## Data Validation Errors
 /// - [`QBTypeError`](APIErrorInner::QBTypeError): Entity validation failures
 /// - [`CreateMissingItems`](APIErrorInner::CreateMissingItems): Required fields missing for creation
 /// - [`DeleteMissingItems`](APIErrorInner::DeleteMissingItems): ID/sync token missing for deletion
+/// - [`UpdateMissingItems`](APIErrorInner::UpdateMissingItems): ID/sync token missing for update
+/// - [`VoidMissingItems`](APIErrorInner::VoidMissingItems): ID/sync token missing for void
 /// - [`NoIdOnRead`](APIErrorInner::NoIdOnRead): Entity missing ID for read operation
 ///
 /// ## Query and Operation Errors
+/// - [`InvalidQuery`](APIErrorInner::InvalidQuery): Query builder rendered an invalid field name
+/// - [`CdcMissingEntities`](APIErrorInner::CdcMissingEntities): CDC request without any entity types
+/// - [`CdcLookbackExceeded`](APIErrorInner::CdcLookbackExceeded): CDC `changedSince` older than 30 days
 /// - [`BatchRequestMissingItems`](APIErrorInner::BatchRequestMissingItems): Batch operation failures
 /// - [`BatchLimitExceeded`](APIErrorInner::BatchLimitExceeded): Too many items in batch request
 ///
@@ -148,7 +163,7 @@ where
 ///                 APIErrorInner::CreateMissingItems => {
 ///                     eprintln!("Please provide required fields like display_name");
 ///                 }
-///                 APIErrorInner::ThrottleLimitReached => {
+///                 APIErrorInner::ThrottleLimitReached { .. } => {
 ///                     eprintln!("Rate limit hit, please wait before retrying");
 ///                 }
 ///                 APIErrorInner::BadRequest(qb_error) => {
@@ -170,6 +185,9 @@ pub enum APIErrorInner {
     UreqError(#[from] ureq::Error),
     #[error("HTTP Error: {0}")]
     HttpError(#[from] ureq::http::Error),
+    #[cfg(feature = "async")]
+    #[error("Error on Reqwest Request: {0}")]
+    ReqwestError(#[from] reqwest::Error),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("Bad request: {0}")]
@@ -188,22 +206,130 @@ pub enum APIErrorInner {
     CreateMissingItems,
     #[error("Can't delete objects without ID or SyncToken")]
     DeleteMissingItems,
+    #[error("Can't update objects without ID or SyncToken")]
+    UpdateMissingItems,
+    #[error("Can't void objects without ID or SyncToken")]
+    VoidMissingItems,
     #[error("Missing ID when trying to get PDF of object")]
     NoIdOnGetPDF,
     #[error("Couldn't write all the bytes of file")]
     ByteLengthMismatch,
     #[error("Missing Attachable object on upload response")]
     NoAttachableObjects,
-    #[error("Throttle limit reached")]
-    ThrottleLimitReached,
+    #[error("Throttle limit reached{}", retry_in(*.retry_after))]
+    ThrottleLimitReached {
+        /// Time until the limit resets, if known
+        retry_after: Option<std::time::Duration>,
+    },
     #[error("Batch limit exceeded")]
     BatchLimitExceeded,
+    #[error("Stale SyncToken conflict persisted after {retries} retries : {fault}")]
+    ConflictRetriesExhausted {
+        /// Number of times the request was retried
+        retries: usize,
+        /// The stale object fault of the last attempt
+        fault: QBErrorResponse,
+    },
     #[error("Env Var error : {0}")]
     EnvVarError(#[from] std::env::VarError),
     #[error("Invalid Batch Response, Missing items for : {0}")]
     BatchRequestMissingItems(BatchMissingItemsError),
     #[error("Invalid File name or extenstion : {0}")]
     InvalidFile(String),
+    #[error("Invalid query : {0}")]
+    InvalidQuery(String),
+    #[error("Missing entity types for CDC request")]
+    CdcMissingEntities,
+    #[error("CDC changedSince {0} is more than 30 days in the past")]
+    CdcLookbackExceeded(DateTime<Utc>),
+    #[error("Webhook signature does not match the payload")]
+    InvalidWebhookSignature,
+    #[error("OAuth error : {0}")]
+    OAuth(#[from] OAuthError),
+    #[cfg(feature = "testing")]
+    #[error("No recorded interaction matches request : {0}")]
+    UnmatchedCassetteRequest(String),
+}
+
+/// Describes when a throttled request can be retried, if known
+fn retry_in(retry_after: Option<std::time::Duration>) -> String {
+    retry_after
+        .map(|retry_after| format!(", retry in {}ms", retry_after.as_millis()))
+        .unwrap_or_default()
+}
+
+impl APIErrorInner {
+    /// Returns `true` if this is a `QuickBooks` fault carrying the given error code
+    #[must_use]
+    pub fn has_fault_code(&self, code: &str) -> bool {
+        match self {
+            APIErrorInner::BadRequest(response)
+            | APIErrorInner::ConflictRetriesExhausted {
+                fault: response, ..
+            } => response.has_fault_code(code),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the request was rejected because of an outdated `SyncToken`
+    #[must_use]
+    pub fn is_stale_object(&self) -> bool {
+        self.has_fault_code(crate::functions::conflict::STALE_OBJECT_FAULT_CODE)
+    }
+}
+
+/// Errors from the OAuth 2.0 authorization and token endpoints.
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error("State returned to the redirect URI does not match the authorization request")]
+    StateMismatch,
+    #[error("Redirect URI query is missing the {0} parameter")]
+    MissingParameter(&'static str),
+    #[error("Authorization was denied : {0}")]
+    AuthorizationDenied(String),
+    #[error("Token request failed with status {status} : {error}")]
+    TokenRequest {
+        status: u16,
+        error: OAuthErrorResponse,
+    },
+    #[error("Token revocation failed with status {status} : {error}")]
+    Revocation {
+        status: u16,
+        error: OAuthErrorResponse,
+    },
+    #[error("Refresh token expired at {0}, the company has to be connected again")]
+    RefreshTokenExpired(DateTime<Utc>),
+    #[error("No stored tokens for company {0}")]
+    NoStoredTokens(String),
+}
+
+impl OAuthError {
+    /// Returns `true` if the token endpoint rejected the grant, e.g. because the
+    /// refresh token expired or was revoked, and the company has to be connected again
+    #[must_use]
+    pub fn is_invalid_grant(&self) -> bool {
+        match self {
+            OAuthError::TokenRequest { error, .. } => error.error == "invalid_grant",
+            OAuthError::RefreshTokenExpired(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Error body returned by the OAuth 2.0 token endpoint, e.g. `invalid_grant`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    pub error_description: Option<String>,
+}
+
+impl std::fmt::Display for OAuthErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{} ({description})", self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
 }
 
 /// Error type for missing items in batch requests.
@@ -287,6 +413,16 @@ pub struct QBErrorResponse {
     pub cdc_response: Option<Vec<Value>>,
 }
 
+impl QBErrorResponse {
+    /// Returns `true` if the fault in this response carries the given error code
+    #[must_use]
+    pub fn has_fault_code(&self, code: &str) -> bool {
+        self.fault
+            .as_ref()
+            .is_some_and(|fault| fault.error.iter().any(|e| e.code == code))
+    }
+}
+
 impl std::fmt::Display for QBErrorResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -12,26 +12,23 @@
 //!
 //! # fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! # let qb_context = todo!();
-//! # let client = reqwest::Client::new();
 //! let attachment = Attachable {
 //!     file_name: Some("invoice.pdf".into()),
 //!     note: Some("Invoice attachment".into()),
 //!     ..Default::default()
 //! };
 //!
-//! let uploaded = attachment.upload(&qb_context, &Agent)?;
+//! let uploaded = attachment.upload(&qb_context)?;
 //! # Ok(())
 //! # }
 //! ```
 use base64::Engine;
 use quickbooks_types::{Attachable, QBAttachable};
-use ureq::{
-    http::{request::Builder, Request, StatusCode},
-    Agent,
-};
+use ureq::http::{request::Builder, Request, StatusCode};
 
 use crate::{
     error::{APIError, APIErrorInner, QBErrorResponse},
+    functions::retry::retry_after,
     APIResult, QBContext,
 };
 
@@ -70,9 +67,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::QBUpload};
 /// use quickbooks_types::{Attachable, AttachmentCategory};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Create an attachment for a receipt
@@ -82,7 +77,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// attachment.category = Some(AttachmentCategory::Receipt);
 ///
 /// // Upload to QuickBooks
-/// let uploaded = attachment.upload(&qb_context, &client)?;
+/// let uploaded = attachment.upload(&qb_context)?;
 /// println!("Uploaded attachment with ID: {:?}", uploaded.id());
 /// ```
 ///
@@ -91,13 +86,11 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::{QBUpload, QBQuery}};
 /// use quickbooks_types::{Attachable, AttachableRef, Invoice, QBToAttachableRef};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Find the invoice to attach to
-/// let invoice = Invoice::query_single("WHERE DocNumber = 'INV-001'", &qb_context, &client)?;
+/// let invoice = Invoice::query_single("WHERE DocNumber = 'INV-001'", &qb_context)?;
 ///
 /// // Create attachment linked to invoice
 /// let mut attachment = Attachable::default();
@@ -106,7 +99,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// attachment.attachable_ref = Some(vec![invoice.to_attachable_ref()?]);
 ///
 /// // Upload with link
-/// let uploaded = attachment.upload(&qb_context, &client)?;
+/// let uploaded = attachment.upload(&qb_context)?;
 /// ```
 ///
 /// ## Upload Company Logo
@@ -114,9 +107,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::QBUpload};
 /// use quickbooks_types::{Attachable, AttachmentCategory};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Upload a company logo
@@ -125,7 +116,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// logo.note = Some("Company logo for letterhead".to_string());
 /// logo.category = Some(AttachmentCategory::Image);
 ///
-/// let uploaded = logo.upload(&qb_context, &client)?;
+/// let uploaded = logo.upload(&qb_context)?;
 /// ```
 ///
 /// # Return Value
@@ -145,7 +136,7 @@ const BOUNDARY: &str = "----------------quick-oxibooks"; // Multipart boundary f
 /// - `AttachableUploadMissingItems`: Missing required fields (file_name or note)
 /// - `InvalidFile`: File doesn't exist or invalid file extension
 /// - `IoError`: File reading errors
-/// - `UreqError`: Network or HTTP errors during upload
+/// - `UreqError`: Network or HTTP errors during upload, with the default transport
 /// - `BadRequest`: QuickBooks rejected the attachment (size, type, etc.)
 /// - `NoAttachableObjects`: Upload succeeded but no attachment in response
 ///
@@ -157,14 +148,14 @@ pub trait QBUpload {
     /// Uploads the attachment
     /// returns an error if the attachment is not suitable for upload
     /// or if the request itself fails
-    fn upload(&self, qb: &QBContext, client: &Agent) -> APIResult<Self>
+    fn upload(&self, qb: &QBContext) -> APIResult<Self>
     where
         Self: Sized;
 }
 
 impl QBUpload for Attachable {
-    fn upload(&self, qb: &QBContext, client: &Agent) -> APIResult<Self> {
-        qb_upload(self, qb, client)
+    fn upload(&self, qb: &QBContext) -> APIResult<Self> {
+        qb_upload(self, qb)
     }
 }
 
@@ -173,24 +164,39 @@ impl QBUpload for Attachable {
 ///
 /// Uploads the file and makes the `attachable` object
 /// in `QuickBooks`.
-fn qb_upload(attachable: &Attachable, qb: &QBContext, client: &Agent) -> APIResult<Attachable> {
+fn qb_upload(attachable: &Attachable, qb: &QBContext) -> APIResult<Attachable> {
     attachable.can_upload()?;
+    let file_content = std::fs::read(attachable.file_path().unwrap())?;
 
-    let request = make_upload_request(attachable, qb)?;
+    let send = |access_token: &str| -> APIResult<_> {
+        let request = make_upload_request(attachable, &file_content, qb, access_token)?;
+        qb.send_with_permission(request.map(String::into_bytes), false)
+    };
 
-    let mut qb_response: AttachableResponseExt = qb.with_permission(|_| {
-        let response = client.run(request)?;
-        if response.status() == StatusCode::TOO_MANY_REQUESTS {
-            // Handle rate limiting by QuickBooks
-            return Err(APIErrorInner::ThrottleLimitReached.into());
-        }
-        if !response.status().is_success() {
-            return Err(APIErrorInner::BadRequest(response.into_body().read_json()?).into());
+    qb.refresh_if_expiring()?;
+    let access_token = qb.access_token();
+    let mut response = send(&access_token)?;
+    if response.status() == StatusCode::UNAUTHORIZED && qb.refresh_rejected(&access_token)? {
+        // Retry once with the refreshed token
+        response = send(&qb.access_token())?;
+    }
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        // Handle rate limiting by QuickBooks
+        return Err(APIErrorInner::ThrottleLimitReached {
+            retry_after: retry_after(response.headers()),
         }
-        let out = response.into_body().read_json()?;
-        Ok(out)
-    })?;
+        .into());
+    }
+    if !response.status().is_success() {
+        return Err(APIErrorInner::BadRequest(serde_json::from_slice(response.body())?).into());
+    }
+    let qb_response: AttachableResponseExt = serde_json::from_slice(response.body())?;
+
+    uploaded_attachable(qb_response)
+}
 
+/// Takes the uploaded attachable out of the upload response
+pub(crate) fn uploaded_attachable(mut qb_response: AttachableResponseExt) -> APIResult<Attachable> {
     if qb_response.ar.is_empty() {
         return Err(APIErrorInner::NoAttachableObjects.into());
     }
@@ -212,22 +218,28 @@ fn qb_upload(attachable: &Attachable, qb: &QBContext, client: &Agent) -> APIResu
     Ok(obj)
 }
 
-fn make_upload_request(attachable: &Attachable, qb: &QBContext) -> APIResult<Request<String>> {
+/// Builds the multipart upload request for the attachable and the content of its file
+pub(crate) fn make_upload_request(
+    attachable: &Attachable,
+    file_content: &[u8],
+    qb: &QBContext,
+    access_token: &str,
+) -> APIResult<Request<String>> {
     let path = format!("company/{}/upload", qb.company_id);
-    let url = crate::client::build_url(
-        qb.environment,
-        &path,
-        None::<std::iter::Empty<(&str, &str)>>,
-    );
+    let url =
+        crate::client::build_url(&qb.endpoints, &path, None::<std::iter::Empty<(&str, &str)>>);
     let mut request = Request::post(url.as_str());
-    request = crate::client::set_headers("multipart/form-data", &qb.access_token, request);
-    let request = make_multipart(request, attachable)?;
+    request = crate::client::set_headers("multipart/form-data", access_token, request);
+    let request = make_multipart(request, attachable, file_content)?;
     Ok(request)
 }
 
-fn make_multipart(req: Builder, attachable: &Attachable) -> Result<Request<String>, APIError> {
+fn make_multipart(
+    req: Builder,
+    attachable: &Attachable,
+    file_content: &[u8],
+) -> Result<Request<String>, APIError> {
     attachable.can_upload()?;
-    let file_path = attachable.file_path().unwrap();
     let ct = attachable.content_type.as_deref().unwrap();
     let file_name = attachable.file_name.as_deref().unwrap();
     let mut body = String::new();
@@ -241,7 +253,6 @@ fn make_multipart(req: Builder, attachable: &Attachable) -> Result<Request<Strin
     body.push_str(&json_body);
     body.push_str("\r\n");
 
-    let file_content = std::fs::read(file_path)?;
     let encoded = base64::engine::general_purpose::STANDARD_NO_PAD.encode(file_content);
     body.push_str(&format!("--{BOUNDARY}\r\n"));
 
@@ -263,7 +274,7 @@ fn make_multipart(req: Builder, attachable: &Attachable) -> Result<Request<Strin
 }
 
 #[derive(Debug, serde::Deserialize)]
-struct AttachableResponseExt {
+pub(crate) struct AttachableResponseExt {
     #[serde(rename = "AttachableResponse")]
     ar: Vec<AttachableResponse>,
     #[allow(dead_code)]
@@ -271,7 +282,7 @@ struct AttachableResponseExt {
 }
 
 #[derive(serde::Deserialize, Debug)]
-enum AttachableResponse {
+pub(crate) enum AttachableResponse {
     Attachable(Attachable),
     Fault(crate::error::Fault),
 }
@@ -0,0 +1,427 @@
+//! Change Data Capture (CDC) for `QuickBooks` entities.
+//!
+//! The CDC endpoint returns every entity of the requested types that changed
+//! since a point in time, including entities that were deleted. `QuickBooks`
+//! limits it in two ways:
+//!
+//! - `changedSince` can be at most [`CDC_MAX_LOOKBACK_DAYS`] days in the past,
+//!   older timestamps are rejected with [`APIErrorInner::CdcLookbackExceeded`]
+//!   before any request is sent.
+//! - At most [`CDC_MAX_OBJECTS`] objects are returned per response, across all entity
+//!   types together. A response that hits this limit is flagged as truncated, and the
+//!   changes to any of its entity types may be missing, so they should be re-read with
+//!   a paginated query instead.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use chrono::{Duration, Utc};
+//! use quick_oxibooks::{QBContext, Environment};
+//! use quick_oxibooks::functions::cdc::CdcRequest;
+//! use quickbooks_types::{Customer, Invoice};
+//!
+//! let qb_context = QBContext::new(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//! ).unwrap();
+//!
+//! let changes = CdcRequest::new(Utc::now() - Duration::days(1))
+//!     .entity::<Customer>()
+//!     .entity::<Invoice>()
+//!     .send(&qb_context)
+//!     .unwrap();
+//!
+//! for customer in changes.changed::<Customer>().unwrap() {
+//!     println!("Changed customer: {:?}", customer.display_name);
+//! }
+//! for deleted in changes.deleted::<Invoice>() {
+//!     println!("Deleted invoice: {}", deleted.id);
+//! }
+//! if changes.is_truncated() {
+//!     println!("Too many changes, re-query them instead");
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use quickbooks_types::QBItem;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use ureq::http::Method;
+
+use crate::{
+    error::{APIError, APIErrorInner},
+    APIResult, QBContext,
+};
+
+use super::qb_request;
+
+/// How far back in days `QuickBooks` allows `changedSince` to be
+pub const CDC_MAX_LOOKBACK_DAYS: i64 = 30;
+
+/// The maximum number of objects `QuickBooks` returns in a CDC response, across all entity types
+pub const CDC_MAX_OBJECTS: usize = 1000;
+
+/// Request for the entities that changed since a point in time.
+///
+/// Add the entity types to look for with [`CdcRequest::entity`], then send it
+/// with [`CdcRequest::send`] or [`qb_cdc`].
+#[derive(Debug, Clone)]
+pub struct CdcRequest {
+    entities: Vec<&'static str>,
+    changed_since: DateTime<Utc>,
+}
+
+impl CdcRequest {
+    /// Creates a request for changes made after `changed_since`
+    #[must_use]
+    pub fn new(changed_since: DateTime<Utc>) -> Self {
+        Self {
+            entities: Vec::new(),
+            changed_since,
+        }
+    }
+
+    /// Adds the entity type `T` to the request
+    #[must_use]
+    pub fn entity<T: QBItem>(mut self) -> Self {
+        if !self.entities.contains(&T::name()) {
+            self.entities.push(T::name());
+        }
+        self
+    }
+
+    /// The names of the entity types in the request
+    #[must_use]
+    pub fn entities(&self) -> &[&'static str] {
+        &self.entities
+    }
+
+    /// The point in time changes are looked for after
+    #[must_use]
+    pub fn changed_since(&self) -> DateTime<Utc> {
+        self.changed_since
+    }
+
+    /// Sends the request, see [`qb_cdc`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is invalid or if the request itself fails
+    pub fn send(&self, qb: &QBContext) -> APIResult<CdcResponse> {
+        qb_cdc(self, qb)
+    }
+
+    /// Checks the request against the `QuickBooks` limits at the time `now`
+    fn validate(&self, now: DateTime<Utc>) -> APIResult<()> {
+        if self.entities.is_empty() {
+            return Err(APIErrorInner::CdcMissingEntities.into());
+        }
+        if self.changed_since < now - Duration::days(CDC_MAX_LOOKBACK_DAYS) {
+            return Err(APIErrorInner::CdcLookbackExceeded(self.changed_since).into());
+        }
+        Ok(())
+    }
+}
+
+/// An entity reported as deleted by a CDC request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedEntity {
+    /// ID of the deleted entity
+    pub id: String,
+    /// Status `QuickBooks` reported for the entity, usually `Deleted`
+    pub status: String,
+    /// When the entity was deleted, if `QuickBooks` reported it
+    pub last_updated_time: Option<DateTime<Utc>>,
+}
+
+/// The changes to a single entity type in a CDC response
+#[derive(Debug, Clone, Default)]
+pub struct CdcEntityChanges {
+    changed: Vec<Value>,
+    deleted: Vec<DeletedEntity>,
+    truncated: bool,
+}
+
+impl CdcEntityChanges {
+    /// The raw JSON of the entities that were created or updated
+    #[must_use]
+    pub fn changed_raw(&self) -> &[Value] {
+        &self.changed
+    }
+
+    /// The entities that were deleted
+    #[must_use]
+    pub fn deleted(&self) -> &[DeletedEntity] {
+        &self.deleted
+    }
+
+    /// Whether `QuickBooks` cut the response these changes are part of short at
+    /// [`CDC_MAX_OBJECTS`] objects, so some of them may be missing
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// The result of a CDC request, grouped by entity type.
+///
+/// Deserializes from the JSON `QuickBooks` returns for a CDC request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "CdcResponseExt")]
+pub struct CdcResponse {
+    entities: BTreeMap<String, CdcEntityChanges>,
+    truncated: bool,
+    time: Option<DateTime<Utc>>,
+}
+
+impl CdcResponse {
+    /// The changes for the entity type with the given name, e.g. `Customer`
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&CdcEntityChanges> {
+        self.entities.get(name)
+    }
+
+    /// Iterates over the entity type names and their changes
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CdcEntityChanges)> {
+        self.entities
+            .iter()
+            .map(|(name, changes)| (name.as_str(), changes))
+    }
+
+    /// The entities of type `T` that were created or updated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entity can't be deserialized into `T`
+    pub fn changed<T: QBItem>(&self) -> APIResult<Vec<T>> {
+        self.get(T::name())
+            .map_or(&[][..], CdcEntityChanges::changed_raw)
+            .iter()
+            .map(|value| T::deserialize(value).map_err(APIError::from))
+            .collect()
+    }
+
+    /// The entities of type `T` that were deleted
+    #[must_use]
+    pub fn deleted<T: QBItem>(&self) -> &[DeletedEntity] {
+        self.get(T::name()).map_or(&[], CdcEntityChanges::deleted)
+    }
+
+    /// Whether `QuickBooks` cut the response short at [`CDC_MAX_OBJECTS`] objects
+    /// across all entity types, so changes to any requested type may be missing
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The server time of the response, usable as `changed_since` for the next request
+    #[must_use]
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        self.time
+    }
+}
+
+/// Requests the entities that changed since the time in the request
+///
+/// Sends `GET /cdc?entities={entities}&changedSince={changed_since}` and groups the
+/// returned entities by type, separating out the deleted ones.
+///
+/// # Errors
+///
+/// - `CdcMissingEntities`: No entity types were added to the request
+/// - `CdcLookbackExceeded`: `changed_since` is more than 30 days in the past
+/// - `UreqError`: Network or HTTP errors during API call
+/// - `BadRequest`: `QuickBooks` API error
+/// - `JsonError`: Response parsing errors
+pub fn qb_cdc(request: &CdcRequest, qb: &QBContext) -> APIResult<CdcResponse> {
+    request.validate(Utc::now())?;
+
+    let response: CdcResponse = qb_request(
+        qb,
+        Method::GET,
+        &format!("company/{}/cdc", qb.company_id),
+        None::<&()>,
+        None,
+        Some([
+            ("entities", request.entities.join(",")),
+            (
+                "changedSince",
+                request
+                    .changed_since
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+        ]),
+    )?;
+
+    #[cfg(feature = "logging")]
+    log::info!(
+        "Successfully fetched CDC changes for {} since {}",
+        request.entities.join(","),
+        request.changed_since
+    );
+
+    Ok(response)
+}
+
+/// Internal struct that Quickbooks returns for CDC requests
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CdcResponseExt {
+    #[serde(rename = "CDCResponse")]
+    cdc_response: Vec<CdcQueryResponses>,
+    time: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CdcQueryResponses {
+    #[serde(rename = "QueryResponse")]
+    query_response: Vec<Map<String, Value>>,
+}
+
+impl From<CdcResponseExt> for CdcResponse {
+    fn from(response: CdcResponseExt) -> Self {
+        let mut entities = BTreeMap::<String, CdcEntityChanges>::new();
+        let query_responses = response
+            .cdc_response
+            .into_iter()
+            .flat_map(|r| r.query_response);
+
+        // Each query response holds the entity list under the entity name,
+        // next to the startPosition/maxResults/totalCount fields
+        for query_response in query_responses {
+            for (name, items) in query_response {
+                let Value::Array(items) = items else {
+                    continue;
+                };
+                let changes = entities.entry(name).or_default();
+                for item in items {
+                    match deleted_entity(&item) {
+                        Some(deleted) => changes.deleted.push(deleted),
+                        None => changes.changed.push(item),
+                    }
+                }
+            }
+        }
+
+        // The limit applies to the response as a whole, not to each entity type
+        let total: usize = entities
+            .values()
+            .map(|changes| changes.changed.len() + changes.deleted.len())
+            .sum();
+        let truncated = total >= CDC_MAX_OBJECTS;
+        for changes in entities.values_mut() {
+            changes.truncated = truncated;
+        }
+
+        CdcResponse {
+            entities,
+            truncated,
+            time: response.time.as_deref().and_then(parse_time),
+        }
+    }
+}
+
+/// Returns the deleted entity if the item is marked as deleted
+fn deleted_entity(item: &Value) -> Option<DeletedEntity> {
+    let status = item.get("status")?.as_str()?;
+    if status != "Deleted" {
+        return None;
+    }
+    Some(DeletedEntity {
+        id: item.get("Id")?.as_str()?.to_string(),
+        status: status.to_string(),
+        last_updated_time: item
+            .pointer("/MetaData/LastUpdatedTime")
+            .and_then(Value::as_str)
+            .and_then(parse_time),
+    })
+}
+
+fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use quickbooks_types::{Customer, Invoice};
+
+    use super::{CdcRequest, CdcResponse};
+
+    #[test]
+    fn test_cdc_response() {
+        let json = r#"{
+            "CDCResponse": [{
+                "QueryResponse": [{
+                    "Customer": [
+                        {"Id": "1", "SyncToken": "2", "DisplayName": "Acme"},
+                        {"domain": "QBO", "status": "Deleted", "Id": "63",
+                         "MetaData": {"LastUpdatedTime": "2024-01-02T09:23:01-08:00"}}
+                    ],
+                    "startPosition": 1, "maxResults": 2, "totalCount": 2
+                }, {
+                    "Invoice": [{"Id": "7", "SyncToken": "0"}],
+                    "startPosition": 1, "maxResults": 1, "totalCount": 1
+                }]
+            }],
+            "time": "2024-01-03T10:00:00.000-08:00"
+        }"#;
+        let response = serde_json::from_str::<CdcResponse>(json).unwrap();
+
+        let customers = response.changed::<Customer>().unwrap();
+        assert_eq!(customers.len(), 1);
+        assert_eq!(customers[0].display_name.as_deref(), Some("Acme"));
+
+        let deleted = response.deleted::<Customer>();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].id, "63");
+        assert_eq!(deleted[0].status, "Deleted");
+        assert!(deleted[0].last_updated_time.is_some());
+
+        assert_eq!(response.changed::<Invoice>().unwrap().len(), 1);
+        assert!(response.deleted::<Invoice>().is_empty());
+        assert!(!response.is_truncated());
+        assert!(response.time().is_some());
+    }
+
+    #[test]
+    fn test_cdc_truncated_across_types() {
+        let items = |count: usize| {
+            (0..count)
+                .map(|i| serde_json::json!({ "Id": i.to_string(), "SyncToken": "0" }))
+                .collect::<Vec<_>>()
+        };
+        let json = serde_json::json!({
+            "CDCResponse": [{
+                "QueryResponse": [
+                    { "Customer": items(600) },
+                    { "Invoice": items(400) },
+                ]
+            }]
+        });
+        let response = serde_json::from_value::<CdcResponse>(json).unwrap();
+
+        assert!(response.is_truncated());
+        assert!(response.iter().all(|(_, changes)| changes.is_truncated()));
+    }
+
+    #[test]
+    fn test_cdc_validation() {
+        let now = Utc::now();
+        let request = CdcRequest::new(now - Duration::days(1));
+        assert!(request.validate(now).is_err());
+
+        let request = request.entity::<Customer>().entity::<Customer>();
+        assert_eq!(request.entities(), ["Customer"]);
+        assert!(request.validate(now).is_ok());
+
+        let request = CdcRequest::new(now - Duration::days(31)).entity::<Customer>();
+        assert!(request.validate(now).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Automatic recovery from stale `SyncToken` conflicts.
+//!
+//! `QuickBooks` uses the `SyncToken` of an entity for optimistic concurrency control.
+//! When an update or delete is sent with a token that is no longer current, the API
+//! answers with a stale object fault (code `5010`). This module provides the opt-in
+//! [`ConflictPolicy`] that lets updates and deletes re-read the entity and retry.
+
+use quickbooks_types::QBItem;
+use serde_json::Value;
+
+use crate::{
+    error::{APIError, APIErrorInner},
+    functions::read::qb_get_single,
+    APIResult, QBContext,
+};
+
+/// Fault code `QuickBooks` returns when a request carries an outdated `SyncToken`
+pub const STALE_OBJECT_FAULT_CODE: &str = "5010";
+
+/// Policy for recovering from stale `SyncToken` conflicts.
+///
+/// When set on a [`QBContext`] with [`QBContext::with_conflict_policy`], updates and
+/// deletes that fail with a stale object fault re-read the latest version of the
+/// entity and retry, up to `max_retries` times.
+///
+/// - **Deletes** are retried with the latest `SyncToken`.
+/// - **Updates** call the merge closure given to
+///   [`QBUpdate::update_with_merge`](crate::functions::update::QBUpdate::update_with_merge)
+///   with the local and latest versions, and send the merged entity. Plain `update`
+///   and `sparse_update` aren't retried and fail with the stale object fault, as
+///   resending the local copy would overwrite the changes made in the meantime.
+/// - **Diff updates** ([`SparseDiff`](crate::functions::update::SparseDiff)) resend
+///   the same changed fields with the latest `SyncToken`.
+///
+/// When the conflict persists after `max_retries` retries, the operation fails with
+/// [`APIErrorInner::ConflictRetriesExhausted`], carrying the last stale object fault.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::{conflict::ConflictPolicy, read::qb_get_single, update::QBUpdate};
+/// use quickbooks_types::Customer;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// )
+/// .unwrap()
+/// .with_conflict_policy(ConflictPolicy::new(3));
+///
+/// let mut customer: Customer = qb_get_single("123", &qb_context).unwrap();
+/// customer.display_name = Some("Acme Corporation".to_string());
+///
+/// // If someone else changed the customer in the meantime, keep their
+/// // changes and only re-apply our new name on top of them.
+/// let updated = customer
+///     .update_with_merge(&qb_context, |ours, mut latest| {
+///         latest.display_name = ours.display_name.clone();
+///         latest
+///     })
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConflictPolicy {
+    /// Maximum number of times a conflicting request is retried
+    pub max_retries: usize,
+}
+
+impl ConflictPolicy {
+    /// Creates a policy that retries conflicting requests up to `max_retries` times
+    #[must_use]
+    pub fn new(max_retries: usize) -> Self {
+        Self { max_retries }
+    }
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// Runs `attempt`, re-reading the entity with the given ID and retrying
+/// according to the context's conflict policy when it fails with a stale object fault.
+///
+/// `attempt` is first called with `None`, then with the latest version of the entity
+/// after each conflict.
+pub(crate) fn retry_on_conflict<T, R, F>(id: &str, qb: &QBContext, mut attempt: F) -> APIResult<R>
+where
+    T: QBItem,
+    F: FnMut(Option<T>) -> APIResult<R>,
+{
+    let mut latest = None;
+    let mut retries = 0;
+    loop {
+        match attempt(latest.take()) {
+            Err(e) if e.is_stale_object() => {
+                count_conflict_retry::<T>(id, qb, &mut retries, e)?;
+                latest = Some(qb_get_single::<T>(id, qb)?);
+            }
+            other => return other,
+        }
+    }
+}
+
+/// Counts another retry after the stale object fault `error`, or returns the
+/// error to fail with if the context's conflict policy allows no more retries
+#[cfg_attr(
+    not(feature = "logging"),
+    allow(unused_variables, clippy::extra_unused_type_parameters)
+)]
+pub(crate) fn count_conflict_retry<T: QBItem>(
+    id: &str,
+    qb: &QBContext,
+    retries: &mut usize,
+    error: APIError,
+) -> APIResult<()> {
+    let Some(policy) = qb.conflict_policy else {
+        return Err(error);
+    };
+    if *retries >= policy.max_retries {
+        return Err(match error.into_inner() {
+            APIErrorInner::BadRequest(fault) => APIErrorInner::ConflictRetriesExhausted {
+                retries: *retries,
+                fault,
+            },
+            other => other,
+        }
+        .into());
+    }
+    *retries += 1;
+
+    #[cfg(feature = "logging")]
+    log::warn!(
+        "Stale SyncToken for {} with ID {id}, re-reading and retrying ({}/{})",
+        T::name(),
+        retries,
+        policy.max_retries
+    );
+    Ok(())
+}
+
+/// Returns the `SyncToken` of an entity re-read during conflict recovery
+pub(crate) fn latest_sync_token<T: QBItem>(latest: &T) -> APIResult<String> {
+    latest
+        .sync_token()
+        .map(ToString::to_string)
+        .ok_or_else(|| APIErrorInner::UpdateMissingItems.into())
+}
+
+/// Returns a copy of `item` carrying the given `SyncToken`
+pub(crate) fn with_sync_token<T: QBItem>(item: &T, sync_token: &str) -> APIResult<T> {
+    let mut value = serde_json::to_value(item)?;
+    if let Value::Object(map) = &mut value {
+        map.insert("SyncToken".into(), Value::String(sync_token.to_string()));
+    }
+    serde_json::from_value(value).map_err(APIError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use quickbooks_types::{Customer, Invoice};
+    use serde_json::json;
+    use ureq::http::Method;
+
+    use super::ConflictPolicy;
+    use crate::{
+        error::APIErrorInner,
+        functions::{
+            delete::QBDelete,
+            stub::{stale_object_fault, StubTransport},
+            update::QBUpdate,
+        },
+    };
+
+    fn customer() -> Customer {
+        Customer {
+            id: Some("1".into()),
+            sync_token: Some("0".into()),
+            display_name: Some("Acme Inc".into()),
+            ..Default::default()
+        }
+    }
+
+    fn invoice() -> Invoice {
+        Invoice {
+            id: Some("42".into()),
+            sync_token: Some("3".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_update_merges_on_conflict() {
+        let transport = StubTransport::new([
+            stale_object_fault(),
+            (
+                200,
+                json!({ "Customer": {
+                    "Id": "1", "SyncToken": "2", "DisplayName": "Acme Corp", "GivenName": "Jane",
+                } }),
+            ),
+            (
+                200,
+                json!({ "Customer": {
+                    "Id": "1", "SyncToken": "3", "DisplayName": "Acme Inc", "GivenName": "Jane",
+                } }),
+            ),
+        ]);
+        let qb = transport
+            .context()
+            .with_conflict_policy(ConflictPolicy::new(2));
+
+        let mut merges = 0;
+        let updated = customer()
+            .update_with_merge(&qb, |ours, mut latest| {
+                merges += 1;
+                latest.display_name.clone_from(&ours.display_name);
+                latest
+            })
+            .unwrap();
+        assert_eq!(merges, 1);
+        assert_eq!(updated.sync_token.as_deref(), Some("3"));
+
+        let sent = transport.take_sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[0].body["SyncToken"], json!("0"));
+        assert_eq!(sent[1].method, Method::GET);
+        assert_eq!(sent[1].path, "/v3/company/123/customer/1");
+        // Their change is kept, ours is re-applied on top with the latest sync token
+        assert_eq!(sent[2].body["SyncToken"], json!("2"));
+        assert_eq!(sent[2].body["DisplayName"], json!("Acme Inc"));
+        assert_eq!(sent[2].body["GivenName"], json!("Jane"));
+        assert_ne!(sent[0].param("requestid"), sent[2].param("requestid"));
+    }
+
+    #[test]
+    fn test_update_without_merge_is_not_retried() {
+        let transport = StubTransport::new([stale_object_fault()]);
+        let qb = transport
+            .context()
+            .with_conflict_policy(ConflictPolicy::new(2));
+
+        assert!(customer().update(&qb).unwrap_err().is_stale_object());
+        assert_eq!(transport.take_sent().len(), 1);
+    }
+
+    #[test]
+    fn test_delete_retries_on_conflict() {
+        let transport = StubTransport::new([
+            stale_object_fault(),
+            (200, json!({ "Invoice": { "Id": "42", "SyncToken": "5" } })),
+            (
+                200,
+                json!({ "Invoice": { "Id": "42", "status": "Deleted", "domain": "QBO" } }),
+            ),
+        ]);
+        let qb = transport
+            .context()
+            .with_conflict_policy(ConflictPolicy::new(1));
+
+        let deleted = invoice().delete(&qb).unwrap();
+        assert_eq!(deleted.status, "Deleted");
+
+        let sent = transport.take_sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[0].body, json!({ "Id": "42", "SyncToken": "3" }));
+        assert_eq!(sent[1].path, "/v3/company/123/invoice/42");
+        assert_eq!(sent[2].body, json!({ "Id": "42", "SyncToken": "5" }));
+        assert_eq!(sent[2].param("operation"), Some("delete"));
+    }
+
+    #[test]
+    fn test_conflict_retries_exhausted() {
+        let latest = (200, json!({ "Invoice": { "Id": "42", "SyncToken": "5" } }));
+        let transport = StubTransport::new([
+            stale_object_fault(),
+            latest.clone(),
+            stale_object_fault(),
+            latest,
+            stale_object_fault(),
+        ]);
+        let qb = transport
+            .context()
+            .with_conflict_policy(ConflictPolicy::new(2));
+
+        let err = invoice().delete(&qb).unwrap_err();
+        let APIErrorInner::ConflictRetriesExhausted { retries, fault } = &*err else {
+            panic!("Expected ConflictRetriesExhausted, got {err}");
+        };
+        assert_eq!(*retries, 2);
+        assert!(fault.to_string().contains("Stale Object Error"));
+        assert_eq!(transport.take_sent().len(), 5);
+
+        // Without a policy the conflict is returned as it is
+        let transport = StubTransport::new([stale_object_fault()]);
+        let err = invoice().delete(&transport.context()).unwrap_err();
+        assert!(err.is_stale_object());
+        assert_eq!(transport.take_sent().len(), 1);
+    }
+}
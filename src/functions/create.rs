@@ -6,11 +6,15 @@
 //! that entities meet the necessary requirements before being sent to the API.
 
 use quickbooks_types::{QBCreatable, QBItem};
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{
     error::{APIError, APIErrorInner},
-    functions::{qb_request, QBResponse},
+    functions::{
+        qb_request,
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
     APIResult, QBContext,
 };
 
@@ -36,14 +40,11 @@ use crate::{
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::create::QBCreate;
 /// use quickbooks_types::Customer;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Create a new customer
@@ -51,7 +52,7 @@ use crate::{
 /// customer.display_name = Some("John Doe".to_string());
 ///
 /// // Send to QuickBooks
-/// let created_customer = customer.create(&qb_context, &client).unwrap();
+/// let created_customer = customer.create(&qb_context).unwrap();
 /// println!("Created customer with ID: {:?}", created_customer.id);
 /// ```
 ///
@@ -70,13 +71,36 @@ pub trait QBCreate {
     /// Creates the item
     /// returns an error if the item is not suitable for creation
     /// or if the request itself fails
-    fn create(&self, qb: &QBContext, client: &Agent) -> APIResult<Self>
+    fn create(&self, qb: &QBContext) -> APIResult<Self>
+    where
+        Self: Sized;
+
+    /// Creates the item with the given request ID, or a new one if `None`,
+    /// so `QuickBooks` creates it only once however many times it is sent,
+    /// and returns the created item along with the request ID
+    ///
+    /// # Errors
+    ///
+    /// If the item is not suitable for creation or if the request itself fails
+    fn create_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>>
     where
         Self: Sized;
 }
 impl<T: QBItem + QBCreatable> QBCreate for T {
-    fn create(&self, qb: &QBContext, client: &Agent) -> Result<Self, APIError> {
-        qb_create(self, qb, client)
+    fn create(&self, qb: &QBContext) -> Result<Self, APIError> {
+        qb_create(self, RequestId::new()?, qb).map(WithRequestId::into_value)
+    }
+
+    fn create_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>> {
+        qb_create(self, request_id.map_or_else(RequestId::new, Ok)?, qb)
     }
 }
 
@@ -84,21 +108,20 @@ impl<T: QBItem + QBCreatable> QBCreate for T {
 /// checks if the item is suitable to be created.
 fn qb_create<T: QBItem + QBCreatable>(
     item: &T,
+    request_id: RequestId,
     qb: &QBContext,
-    client: &Agent,
-) -> Result<T, APIError> {
+) -> Result<WithRequestId<T>, APIError> {
     if !item.can_create() {
         return Err(APIErrorInner::CreateMissingItems.into());
     }
 
     let response: QBResponse<T> = qb_request(
         qb,
-        client,
         Method::POST,
         &format!("company/{}/{}", qb.company_id, T::qb_id()),
         Some(item),
         None,
-        None::<std::iter::Empty<(&str, &str)>>,
+        Some([(REQUEST_ID_PARAM, request_id.as_str())]),
     )?;
 
     #[cfg(feature = "logging")]
@@ -108,5 +131,8 @@ fn qb_create<T: QBItem + QBCreatable>(
         response.object.id().into_iter().next()
     );
 
-    Ok(response.object)
+    Ok(WithRequestId {
+        value: response.object,
+        request_id,
+    })
 }
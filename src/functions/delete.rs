@@ -2,11 +2,16 @@
 
 use quickbooks_types::{QBDeletable, QBItem};
 use serde::{Deserialize, Serialize};
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{
     error::{APIError, APIErrorInner},
-    functions::{qb_request, QBResponse},
+    functions::{
+        conflict::retry_on_conflict,
+        qb_request,
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
     APIResult, QBContext,
 };
 
@@ -33,7 +38,9 @@ use crate::{
 /// - **Permanent Action**: Deletion cannot be undone
 /// - **Referential Integrity**: `QuickBooks` may prevent deletion if entity is referenced elsewhere
 /// - **Audit Trail**: Some entities may be marked as inactive instead of deleted
-/// - **Sync Token**: Must be current or deletion will fail with sync error
+/// - **Sync Token**: Must be current or deletion will fail with sync error, unless the
+///   context has a [`ConflictPolicy`](crate::functions::conflict::ConflictPolicy) set,
+///   in which case the entity is re-read and the delete retried with the latest sync token
 ///
 /// # Examples
 ///
@@ -42,25 +49,21 @@ use crate::{
 /// use quick_oxibooks::functions::delete::QBDelete;
 /// use quick_oxibooks::functions::query::QBQuery;
 /// use quickbooks_types::Invoice;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Find and delete an invoice
 /// let invoice = Invoice::query_single(
 ///     "WHERE DocNumber = 'INV-0001'",
 ///     &qb_context,
-///     &client
-/// ).unwrap();
+/// ).unwrap().unwrap();
 ///
 /// // Delete the invoice
-/// let deleted_info = invoice.delete(&qb_context, &client).unwrap();
+/// let deleted_info = invoice.delete(&qb_context).unwrap();
 /// println!("Deleted invoice with ID: {}", deleted_info.id);
 /// ```
 ///
@@ -76,19 +79,43 @@ use crate::{
 /// - `DeleteMissingItems`: Entity missing ID or sync token
 /// - `UreqError`: Network or HTTP errors during API call
 /// - `BadRequest`: `QuickBooks` API error (e.g., entity referenced elsewhere, sync conflict)
+/// - `ConflictRetriesExhausted`: Stale sync token conflict persisted after retrying
 /// - `JsonError`: Response parsing errors
 pub trait QBDelete {
     /// Deletes the item
     /// returns an error if the item has no ID and sync token
     /// available or if the request itself fails
-    fn delete(&self, qb: &QBContext, client: &Agent) -> APIResult<QBDeleted>
+    fn delete(&self, qb: &QBContext) -> APIResult<QBDeleted>
+    where
+        Self: Sized;
+
+    /// Deletes the item with the given request ID, or a new one if `None`,
+    /// so `QuickBooks` deletes it only once however many times it is sent,
+    /// and returns the deleted item's status along with the request ID
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn delete_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<QBDeleted>>
     where
         Self: Sized;
 }
 
 impl<T: QBItem + QBDeletable> QBDelete for T {
-    fn delete(&self, qb: &QBContext, client: &Agent) -> APIResult<QBDeleted> {
-        qb_delete(self, qb, client)
+    fn delete(&self, qb: &QBContext) -> APIResult<QBDeleted> {
+        qb_delete(self, RequestId::new()?, qb).map(WithRequestId::into_value)
+    }
+
+    fn delete_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<QBDeleted>> {
+        qb_delete(self, request_id.map_or_else(RequestId::new, Ok)?, qb)
     }
 }
 
@@ -97,43 +124,52 @@ impl<T: QBItem + QBDeletable> QBDelete for T {
 /// available or if the request itself fails
 fn qb_delete<T: QBItem + QBDeletable>(
     item: &T,
+    mut request_id: RequestId,
     qb: &QBContext,
-    client: &Agent,
-) -> Result<QBDeleted, APIError> {
-    let (Some(_), Some(_)) = (item.sync_token(), item.id()) else {
+) -> Result<WithRequestId<QBDeleted>, APIError> {
+    let (Some(_), Some(id)) = (item.sync_token(), item.id()) else {
         return Err(APIErrorInner::DeleteMissingItems.into());
     };
 
-    let delete_object: QBToDelete = item.to_delete();
+    let value = retry_on_conflict(id, qb, |latest: Option<T>| {
+        if latest.is_some() {
+            // A different request, which QuickBooks must not deduplicate
+            request_id = RequestId::new()?;
+        }
+        let delete_object: QBToDelete = latest.as_ref().unwrap_or(item).to_delete();
 
-    let response: QBResponse<QBDeleted> = qb_request(
-        qb,
-        client,
-        Method::POST,
-        &format!("company/{}/{}?operation=delete", qb.company_id, T::qb_id()),
-        Some(&delete_object),
-        None,
-        None::<std::iter::Empty<(&str, &str)>>,
-    )?;
+        let response: QBResponse<QBDeleted> = qb_request(
+            qb,
+            Method::POST,
+            &format!("company/{}/{}", qb.company_id, T::qb_id()),
+            Some(&delete_object),
+            None,
+            Some([
+                ("operation", "delete"),
+                (REQUEST_ID_PARAM, request_id.as_str()),
+            ]),
+        )?;
 
-    #[cfg(feature = "logging")]
-    log::info!(
-        "Successfully deleted {} with ID of {}",
-        T::name(),
-        delete_object.id
-    );
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Successfully deleted {} with ID of {}",
+            T::name(),
+            delete_object.id
+        );
 
-    Ok(response.object)
+        Ok(response.object)
+    })?;
+    Ok(WithRequestId { value, request_id })
 }
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
-struct QBToDelete<'a> {
-    id: &'a str,
+pub(crate) struct QBToDelete<'a> {
+    pub(crate) id: &'a str,
     sync_token: &'a str,
 }
 
-trait QBToDeleteTrait {
+pub(crate) trait QBToDeleteTrait {
     fn id(&self) -> &str;
     fn sync_token(&self) -> &str;
     fn to_delete(&self) -> QBToDelete<'_> {
@@ -172,18 +208,17 @@ impl<T: QBItem> QBToDeleteTrait for T {
 /// use quick_oxibooks::functions::delete::{QBDelete, QBDeleted};
 /// use quick_oxibooks::functions::query::QBQuery;
 /// use quickbooks_types::Invoice;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 /// // Assume `entity` is fetched and has id/sync token
-/// let entity = Invoice::query_single("WHERE DocNumber = 'INV-0001'", &qb_context, &client).unwrap();
-/// let deleted_info: QBDeleted = entity.delete(&qb_context, &client).unwrap();
+/// let entity = Invoice::query_single("WHERE DocNumber = 'INV-0001'", &qb_context)
+///     .unwrap()
+///     .unwrap();
+/// let deleted_info: QBDeleted = entity.delete(&qb_context).unwrap();
 ///
 /// println!("Deletion status: {}", deleted_info.status);
 /// println!("Deleted entity ID: {}", deleted_info.id);
@@ -3,26 +3,36 @@
 //! This module contains functions for interacting with the `QuickBooks` API, including
 //! creating, reading, updating, and deleting various `QuickBooks` entities.
 
+use std::{ops::ControlFlow, time::Duration};
+
 use quickbooks_types::{QBItem, QBSendable};
 use serde::{Deserialize, Serialize};
-use ureq::http::Response;
-use ureq::Body;
-use ureq::{http::Method, Agent};
+use ureq::http::{Method, Response, StatusCode};
 
 use crate::{
-    error::{APIError, APIErrorInner},
+    error::{APIError, APIErrorInner, QBErrorResponse},
+    functions::retry::RetryPolicy,
     APIResult, QBContext,
 };
 
 #[cfg(feature = "attachments")]
 pub mod attachment;
+pub mod cdc;
+pub mod conflict;
 pub mod create;
 pub mod delete;
 #[cfg(feature = "pdf")]
 pub mod pdf;
 pub mod query;
+pub mod query_builder;
 pub mod read;
 pub mod reports;
+pub mod request_id;
+pub mod retry;
+#[cfg(test)]
+pub(crate) mod stub;
+pub mod update;
+pub mod void;
 
 /// Sends a request to the `QuickBooks` API endpoint with the given parameters,
 /// accounts for rate limiting
@@ -37,7 +47,6 @@ pub mod reports;
 /// * `query` - Optional query parameters
 pub(crate) fn qb_request<S, SS, T, U>(
     qb: &QBContext,
-    client: &Agent,
     method: Method,
     path: &str,
     body: Option<&T>,
@@ -50,39 +59,148 @@ where
     S: AsRef<str>,
     SS: AsRef<str>,
 {
-    let response = qb.with_permission(|qb| {
-        execute_request(qb, client, method, path, body, content_type, query)
-    })?;
-    Ok(response.into_body().read_json()?)
+    let response = execute_request(qb, method, path, body, content_type, query, false)?;
+    Ok(serde_json::from_slice(response.body())?)
 }
 
+/// Sends a request to the `QuickBooks` API endpoint through the context's
+/// [`Transport`](crate::client::Transport)
+///
+/// Every attempt acquires its own slot and permit from the limiter of regular or
+/// `batch` requests, and releases them before backing off.
+///
+/// If the context has auto-refresh enabled, the access token is refreshed before
+/// sending when it is about to expire, and the request is retried once after
+/// refreshing if `QuickBooks` rejects the token with a 401.
+///
+/// If the context has a [`RetryPolicy`](retry::RetryPolicy), failed requests are
+/// retried according to it. A `429` that is not retried fails with
+/// `ThrottleLimitReached`.
 pub(crate) fn execute_request<S, SS, T: Serialize>(
     qb: &QBContext,
-    client: &Agent,
     method: Method,
     path: &str,
     body: Option<&T>,
     content_type: Option<&str>,
     query: Option<impl IntoIterator<Item = (S, SS)>>,
-) -> Result<Response<Body>, APIError>
+    batch: bool,
+) -> Result<Response<Vec<u8>>, APIError>
 where
     S: AsRef<str>,
     SS: AsRef<str>,
 {
-    let request = crate::client::build_request(
-        &method,
-        path,
-        body,
-        query,
-        content_type.unwrap_or("application/json"),
-        qb.environment,
-        &qb.access_token,
-    )?;
-    let response = client.run(request)?;
-    if !response.status().is_success() {
-        return Err(APIErrorInner::BadRequest(response.into_body().read_json()?).into());
+    qb.refresh_if_expiring()?;
+
+    let query: Option<Vec<(S, SS)>> = query.map(|q| q.into_iter().collect());
+    // Retrying a POST could apply it twice, unless QuickBooks can deduplicate it
+    let idempotent = method != Method::POST
+        || query
+            .iter()
+            .flatten()
+            .any(|(k, _)| k.as_ref() == request_id::REQUEST_ID_PARAM);
+    let policy = qb.retry_policy.as_ref().filter(|_| idempotent);
+
+    let mut attempt = 1;
+    let mut refreshed = false;
+    loop {
+        let access_token = qb.access_token();
+        let request = crate::client::build_request(
+            &method,
+            path,
+            body,
+            query
+                .as_ref()
+                .map(|q| q.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))),
+            content_type.unwrap_or("application/json"),
+            &qb.endpoints,
+            &access_token,
+        )?;
+        let can_retry = policy.filter(|p| attempt < p.max_attempts);
+
+        let outcome = qb.send_with_permission(request, batch);
+        if is_unauthorized(&outcome) && !refreshed && qb.refresh_rejected(&access_token)? {
+            refreshed = true;
+            continue;
+        }
+        let (policy, retry_after) = match check_attempt(outcome, can_retry)? {
+            ControlFlow::Break(response) => return Ok(response),
+            ControlFlow::Continue(retry) => retry,
+        };
+
+        let delay = policy.delay(attempt, retry_after);
+
+        #[cfg(feature = "logging")]
+        log::warn!(
+            "Request to {path} failed, retrying in {delay:?} ({attempt}/{})",
+            policy.max_attempts
+        );
+
+        std::thread::sleep(delay);
+        attempt += 1;
     }
-    Ok(response)
+}
+
+/// Returns `true` if `QuickBooks` rejected the access token of the attempt
+pub(crate) fn is_unauthorized(outcome: &APIResult<Response<Vec<u8>>>) -> bool {
+    outcome
+        .as_ref()
+        .is_ok_and(|response| response.status() == StatusCode::UNAUTHORIZED)
+}
+
+/// Either the response of a successful attempt, or the retry policy and
+/// `Retry-After` delay to retry a failed one with
+pub(crate) type AttemptOutcome<'a> =
+    ControlFlow<Response<Vec<u8>>, (&'a RetryPolicy, Option<Duration>)>;
+
+/// Decides how an attempt at a request ended, for both the blocking and the async
+/// client, once a rejected access token has been dealt with
+///
+/// Breaks with the response of a successful request, or continues with the policy
+/// and `Retry-After` delay to retry with when `policy`, the retry policy if the
+/// request may be attempted again, retries the failure. Any other failure is an
+/// error, a `429` failing with `ThrottleLimitReached`.
+pub(crate) fn check_attempt(
+    outcome: APIResult<Response<Vec<u8>>>,
+    policy: Option<&RetryPolicy>,
+) -> APIResult<AttemptOutcome<'_>> {
+    let response = match outcome {
+        Ok(response) if response.status().is_success() => return Ok(ControlFlow::Break(response)),
+        Ok(response) => response,
+        Err(e) => {
+            return match policy {
+                Some(policy) if policy.retries_transport_error(&e) => {
+                    Ok(ControlFlow::Continue((policy, None)))
+                }
+                _ => Err(e),
+            }
+        }
+    };
+    let status = response.status();
+    let retry_after = retry::retry_after(response.headers());
+    match policy {
+        Some(policy) if policy.retries_status(status.as_u16()) => {
+            return Ok(ControlFlow::Continue((policy, retry_after)));
+        }
+        _ if status == StatusCode::TOO_MANY_REQUESTS => {
+            return Err(APIErrorInner::ThrottleLimitReached { retry_after }.into());
+        }
+        _ => {}
+    }
+    let error: QBErrorResponse = serde_json::from_slice(response.body())?;
+    match policy {
+        Some(policy) if policy.retries_fault(&error) => {
+            Ok(ControlFlow::Continue((policy, retry_after)))
+        }
+        _ => Err(APIErrorInner::BadRequest(error).into()),
+    }
+}
+
+/// A random number for jitter, not unpredictable enough for request IDs
+pub(crate) fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
 }
 
 /// Internal struct that Quickbooks returns most
@@ -96,6 +214,7 @@ pub(crate) struct QBResponse<T> {
         alias = "Invoice",
         alias = "Attachable",
         alias = "Bill",
+        alias = "BillPayment",
         alias = "CompanyInfo",
         alias = "Customer",
         alias = "Employee",
@@ -105,7 +224,7 @@ pub(crate) struct QBResponse<T> {
         alias = "Vendor"
     )]
     // TODO : Add more aliases as needed
-    object: T,
+    pub(crate) object: T,
     time: String,
 }
 
@@ -114,7 +233,6 @@ pub fn qb_send_email<T: QBItem + QBSendable>(
     item: &T,
     email: &str,
     qb: &QBContext,
-    client: &Agent,
 ) -> Result<T, APIError> {
     let Some(id) = item.id() else {
         return Err(APIErrorInner::NoIdOnSend.into());
@@ -122,7 +240,6 @@ pub fn qb_send_email<T: QBItem + QBSendable>(
 
     let response: QBResponse<T> = qb_request(
         qb,
-        client,
         Method::POST,
         &format!("company/{}/{}/{}/send", qb.company_id, T::qb_id(), id),
         None::<&()>,
@@ -133,3 +250,127 @@ pub fn qb_send_email<T: QBItem + QBSendable>(
     log::info!("Successfully Sent {} object with ID : {}", T::name(), id);
     Ok(response.object)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use quickbooks_types::Customer;
+    use serde_json::json;
+
+    use crate::{functions::read::qb_get_single, Endpoints, Environment, QBContext};
+
+    /// Serves requests on a local port through a default [`ureq::Agent`], answering
+    /// each with the status and body `respond` returns for its request line and
+    /// `Authorization` header
+    fn serve(respond: impl Fn(&str, &str) -> (u16, String) + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let (mut authorization, mut content_length) = (String::new(), 0);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    if name.eq_ignore_ascii_case("authorization") {
+                        authorization = value.trim().to_string();
+                    } else if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; content_length]).unwrap();
+                let (status, body) = respond(&request_line, &authorization);
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} X\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        base_url
+    }
+
+    #[test]
+    fn test_refresh_on_unauthorized() {
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let api_requests = Arc::new(AtomicUsize::new(0));
+        let base_url = {
+            let (token_requests, api_requests) =
+                (Arc::clone(&token_requests), Arc::clone(&api_requests));
+            serve(move |request_line, authorization| {
+                if request_line.contains("/tokens/bearer") {
+                    token_requests.fetch_add(1, Ordering::SeqCst);
+                    let tokens = json!({
+                        "token_type": "bearer",
+                        "expires_in": 3600,
+                        "refresh_token": "new_refresh",
+                        "x_refresh_token_expires_in": 8_726_400,
+                        "access_token": "new_access",
+                    });
+                    return (200, tokens.to_string());
+                }
+                api_requests.fetch_add(1, Ordering::SeqCst);
+                if authorization == "Bearer new_access" {
+                    let customer = json!({ "Customer": { "Id": "1", "SyncToken": "0" } });
+                    (200, customer.to_string())
+                } else {
+                    let fault = json!({
+                        "Fault": {
+                            "Error": [{ "Message": "AuthenticationFailed", "code": "3200" }],
+                            "type": "AUTHENTICATION",
+                        }
+                    });
+                    (401, fault.to_string())
+                }
+            })
+        };
+
+        let discovery_doc = serde_json::from_value(json!({
+            "issuer": base_url,
+            "authorization_endpoint": format!("{base_url}/connect/oauth2"),
+            "token_endpoint": format!("{base_url}/oauth2/v1/tokens/bearer"),
+            "userinfo_endpoint": format!("{base_url}/v1/openid_connect/userinfo"),
+            "revocation_endpoint": format!("{base_url}/oauth2/v1/tokens/revoke"),
+            "jwks_uri": format!("{base_url}/oauth2/v1/keys"),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+            "scopes_supported": ["openid"],
+            "token_endpoint_auth_methods_supported": ["client_secret_basic"],
+            "claims_supported": ["sub"],
+        }))
+        .unwrap();
+        let qb = QBContext::builder(
+            Environment::SANDBOX,
+            "refresh_on_unauthorized".to_string(),
+            "old_access".to_string(),
+        )
+        .endpoints(Endpoints::from_base_url(&base_url))
+        .discovery_doc(discovery_doc)
+        .build()
+        .unwrap()
+        .with_refresh("old_refresh".to_string())
+        .with_auto_refresh("client_id", "client_secret");
+
+        let customer: Customer = qb_get_single("1", &qb).unwrap();
+        assert_eq!(customer.id.as_deref(), Some("1"));
+        assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(api_requests.load(Ordering::SeqCst), 2);
+        assert_eq!(qb.access_token(), "new_access");
+    }
+}
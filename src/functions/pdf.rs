@@ -17,7 +17,6 @@
 //! ```
 //! use quick_oxibooks::{QBContext, Environment};
 //! use quickbooks_types::{Invoice, QBGetPDF};
-//! use reqwest::Client;
 //!
 //! #[tokio::main]
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -28,16 +27,14 @@
 //!         Environment::Production,
 //!     );
 //!     
-//!     let client = Client::new();
-//!     
 //!     // Get invoice (assuming you have retrieved it from QuickBooks API)
 //!     let invoice = Invoice::new();
 //!     
 //!     // Save invoice PDF to file
-//!     invoice.save_pdf_to_file("invoice.pdf", &qb_context, &Agent)?;
+//!     invoice.save_pdf_to_file("invoice.pdf", &qb_context)?;
 //!     
 //!     // Alternatively, get PDF bytes
-//!     let pdf_bytes = invoice.get_pdf_bytes(&qb_context, &Agent)?;
+//!     let pdf_bytes = invoice.get_pdf_bytes(&qb_context)?;
 //!     
 //!     Ok(())
 //! }
@@ -45,10 +42,11 @@
 use std::io::Write;
 
 use quickbooks_types::{QBItem, QBPDFable};
-use ureq::Agent;
+use ureq::http::Method;
 
 use crate::{
     error::{APIError, APIErrorInner},
+    functions::execute_request,
     APIResult, QBContext,
 };
 
@@ -80,16 +78,14 @@ use crate::{
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::{QBGetPDF, QBQuery}};
 /// use quickbooks_types::Invoice;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Find an invoice
-/// let invoice = Invoice::query_single("WHERE DocNumber = 'INV-001'", &qb_context, &client)?;
+/// let invoice = Invoice::query_single("WHERE DocNumber = 'INV-001'", &qb_context)?;
 ///
 /// // Get PDF as bytes
-/// let pdf_bytes = invoice.get_pdf_bytes(&qb_context, &client)?;
+/// let pdf_bytes = invoice.get_pdf_bytes(&qb_context)?;
 ///
 /// // Use the bytes (save to file, send via email, etc.)
 /// std::fs::write("invoice_001.pdf", pdf_bytes)?;
@@ -100,16 +96,14 @@ use crate::{
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::{QBGetPDF, QBQuery}};
 /// use quickbooks_types::Estimate;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Find an estimate
-/// let estimate = Estimate::query_single("WHERE DocNumber = 'EST-001'", &qb_context, &client)?;
+/// let estimate = Estimate::query_single("WHERE DocNumber = 'EST-001'", &qb_context)?;
 ///
 /// // Save directly to file
-/// estimate.save_pdf_to_file("estimate_001.pdf", &qb_context, &client)?;
+/// estimate.save_pdf_to_file("estimate_001.pdf", &qb_context)?;
 /// println!("PDF saved successfully!");
 /// ```
 ///
@@ -118,9 +112,7 @@ use crate::{
 /// ```rust
 /// use quick_oxibooks::{QBContext, functions::{QBGetPDF, QBQuery}};
 /// use quickbooks_types::Invoice;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(/* ... */)?;
 ///
 /// // Get multiple invoices
@@ -128,14 +120,13 @@ use crate::{
 ///     "WHERE MetaData.CreateTime >= '2024-01-01'",
 ///     Some(50),
 ///     &qb_context,
-///     &client
 /// )?;
 ///
 /// // Generate PDFs for all invoices
 /// for invoice in invoices {
 ///     if let Some(doc_number) = &invoice.doc_number {
 ///         let filename = format!("invoice_{}.pdf", doc_number);
-///         match invoice.save_pdf_to_file(&filename, &qb_context, &client) {
+///         match invoice.save_pdf_to_file(&filename, &qb_context) {
 ///             Ok(_) => println!("Saved {}", filename),
 ///             Err(e) => eprintln!("Failed to save {}: {}", filename, e),
 ///         }
@@ -185,54 +176,44 @@ pub trait QBGetPDF {
     /// Gets the PDF bytes
     /// returns an error if the item has no ID
     /// or if the request itself fails
-    fn get_pdf_bytes(&self, qb: &QBContext, client: &Agent) -> APIResult<Vec<u8>>
+    fn get_pdf_bytes(&self, qb: &QBContext) -> APIResult<Vec<u8>>
     where
         Self: Sized;
 
     /// Saves the PDF to a file
     /// returns an error if the item has no ID
     /// or if the request itself fails
-    fn save_pdf_to_file(&self, file_name: &str, qb: &QBContext, client: &Agent) -> APIResult<()>
+    fn save_pdf_to_file(&self, file_name: &str, qb: &QBContext) -> APIResult<()>
     where
         Self: Sized + QBPDFable + QBItem,
     {
-        qb_save_pdf_to_file(self, file_name, qb, client)
+        qb_save_pdf_to_file(self, file_name, qb)
     }
 }
 impl<T: QBItem + QBPDFable> QBGetPDF for T {
-    fn get_pdf_bytes(&self, qb: &QBContext, client: &Agent) -> APIResult<Vec<u8>> {
-        qb_get_pdf_bytes(self, qb, client)
+    fn get_pdf_bytes(&self, qb: &QBContext) -> APIResult<Vec<u8>> {
+        qb_get_pdf_bytes(self, qb)
     }
 }
 
 /// Gets the PDF bytes of the item
 /// returns an error if the item has no ID
 /// or if the request itself fails
-fn qb_get_pdf_bytes<T: QBItem + QBPDFable>(
-    item: &T,
-    qb: &QBContext,
-    client: &Agent,
-) -> APIResult<Vec<u8>> {
+fn qb_get_pdf_bytes<T: QBItem + QBPDFable>(item: &T, qb: &QBContext) -> APIResult<Vec<u8>> {
     let Some(id) = item.id() else {
         return Err(APIErrorInner::NoIdOnGetPDF.into());
     };
 
-    let request = crate::client::build_request(
-        &ureq::http::Method::GET,
+    let response = execute_request(
+        qb,
+        Method::GET,
         &format!("company/{}/{}/{}/pdf", qb.company_id, T::qb_id(), id),
         None::<&()>,
+        None,
         None::<std::iter::Empty<(&str, &str)>>,
-        "application/json",
-        qb.environment,
-        &qb.access_token,
+        false,
     )?;
 
-    let response = qb.with_permission(|_| Ok(client.run(request)?))?;
-
-    if !response.status().is_success() {
-        return Err(APIErrorInner::BadRequest(response.into_body().read_json()?).into());
-    }
-
     #[cfg(feature = "logging")]
     log::info!(
         "Successfully got PDF of {} with ID : {}",
@@ -240,16 +221,15 @@ fn qb_get_pdf_bytes<T: QBItem + QBPDFable>(
         item.id().ok_or(APIErrorInner::NoIdOnGetPDF)?
     );
 
-    Ok(response.into_body().read_to_vec()?)
+    Ok(response.into_body())
 }
 
 fn qb_save_pdf_to_file<T: QBItem + QBPDFable>(
     item: &T,
     file_name: &str,
     qb: &QBContext,
-    client: &Agent,
 ) -> Result<(), APIError> {
-    let bytes = qb_get_pdf_bytes(item, qb, client)?;
+    let bytes = qb_get_pdf_bytes(item, qb)?;
     let mut file = std::fs::OpenOptions::new()
         .create(true)
         .write(true)
@@ -1,14 +1,17 @@
 //! Functions for querying `QuickBooks` entities using SQL-like syntax.
 
-use std::fmt::{Display, Write};
+use std::{
+    fmt::{Display, Write},
+    marker::PhantomData,
+};
 
 use quickbooks_types::QBItem;
 use serde::Deserialize;
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{error::APIError, APIResult, QBContext};
 
-use super::qb_request;
+use super::{qb_request, query_builder::QueryBuilder};
 
 /// Trait for querying `QuickBooks` entities using SQL-like syntax.
 ///
@@ -28,6 +31,7 @@ use super::qb_request;
 /// - `WHERE field LIKE '%pattern%'`: Pattern matching
 /// - `ORDER BY field ASC/DESC`: Sort results
 /// - `MAXRESULTS n`: Limit number of results
+/// - `STARTPOSITION n`: Skip to the n-th result (1-based), used for pagination
 ///
 /// # Examples
 ///
@@ -37,14 +41,11 @@ use super::qb_request;
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::query::QBQuery;
 /// use quickbooks_types::{Customer, Invoice};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Query active customers
@@ -52,7 +53,6 @@ use super::qb_request;
 ///     "WHERE Active = true ORDER BY DisplayName",
 ///     Some(50),
 ///     &qb_context,
-///     &client
 /// ).unwrap();
 ///
 /// // Query recent invoices
@@ -60,7 +60,6 @@ use super::qb_request;
 ///     "WHERE TotalAmt > '1000.00' AND MetaData.CreateTime > '2024-01-01'",
 ///     Some(25),
 ///     &qb_context,
-///     &client
 /// ).unwrap();
 /// ```
 ///
@@ -68,31 +67,96 @@ use super::qb_request;
 ///
 /// ```no_run
 /// use quick_oxibooks::{QBContext, Environment};
-/// use ureq::Agent;
 /// use quickbooks_types::{Customer, Invoice};
 /// use quick_oxibooks::functions::query::QBQuery;
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 /// // Find a specific customer by name
 /// let customer = Customer::query_single(
 ///     "WHERE DisplayName = 'Acme Corp'",
 ///     &qb_context,
-///     &client
 /// ).unwrap();
 ///
 /// // Find an invoice by document number
 /// let invoice = Invoice::query_single(
 ///     "WHERE DocNumber = 'INV-001'",
 ///     &qb_context,
-///     &client
 /// ).unwrap();
 /// ```
 ///
+/// ## Paginated Queries
+///
+/// `QuickBooks` returns at most 1000 entities per query. Use `query_all` or
+/// `query_pages` to lazily fetch every matching entity, one page at a time.
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::query::QBQuery;
+/// use quickbooks_types::Invoice;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// // Iterate over every invoice, 1000 per request
+/// for invoice in Invoice::query_all("WHERE Balance > '0'", &qb_context) {
+///     let invoice = invoice.unwrap();
+///     println!("{:?}", invoice.doc_number);
+/// }
+///
+/// // Or work page by page
+/// let mut pages = Invoice::query_pages("ORDER BY Id", 500, &qb_context);
+/// while let Some(page) = pages.next() {
+///     let page = page.unwrap();
+///     println!("Got {} invoices starting at {:?}", page.len(), pages.start_position());
+/// }
+/// ```
+///
+/// ## Counting Entities
+///
+/// `count` asks `QuickBooks` only for the number of matching entities,
+/// without downloading any of them.
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::query::QBQuery;
+/// use quickbooks_types::Invoice;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// let open = Invoice::count("WHERE Balance > '500.00'", &qb_context).unwrap();
+/// println!("{open} open invoices over $500");
+/// ```
+///
+/// ## Type-Safe Queries
+///
+/// ```no_run
+/// use quick_oxibooks::{qb_field, QBContext, Environment};
+/// use quick_oxibooks::functions::{query::QBQuery, query_builder::QueryBuilder};
+/// use quickbooks_types::Customer;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// // Values are escaped, so user input can be passed in directly
+/// let query = QueryBuilder::<Customer>::new()
+///     .eq(qb_field!(Customer.display_name), "O'Brien & Sons")
+///     .eq(qb_field!(Customer.active), true);
+/// let customers = Customer::query_with(&query, &qb_context).unwrap();
+/// ```
+///
 /// # Field Names
 ///
 /// Use `QuickBooks` field names (`PascalCase`) in queries, not Rust field names (`snake_case)`:
@@ -102,6 +166,8 @@ use super::qb_request;
 /// # Performance Notes
 ///
 /// - Use `MAXRESULTS` to limit large result sets
+/// - `query` sends a single request; results past 1000 rows are cut short, use
+///   `query_all` or `query_pages` instead
 /// - Index-friendly queries (ID, `DisplayName`) perform better
 /// - Complex queries may timeout on large datasets
 ///
@@ -110,6 +176,7 @@ use super::qb_request;
 /// - `NoQueryObjects`: No entities matched the query
 /// - `UreqError`: Network or HTTP errors during API call
 /// - `BadRequest`: Invalid query syntax or field names
+/// - `InvalidQuery`: Query builder contains an invalid field name
 /// - `JsonError`: Response parsing errors
 pub trait QBQuery {
     /// Queries the `QuickBooks` API for objects of type T
@@ -120,12 +187,7 @@ pub trait QBQuery {
     /// ```ignore
     /// "select * from {type_name} {query_str} MAXRESULTS {max_results}"
     /// ```
-    fn query(
-        query_str: &str,
-        max_results: Option<usize>,
-        qb: &QBContext,
-        client: &Agent,
-    ) -> APIResult<Vec<Self>>
+    fn query(query_str: &str, max_results: Option<usize>, qb: &QBContext) -> APIResult<Vec<Self>>
     where
         Self: Sized;
 
@@ -136,26 +198,268 @@ pub trait QBQuery {
     /// ```ignore
     /// "select * from {type_name} {query_str} MAXRESULTS {max_results}"
     /// ```
-    fn query_single(query_str: &str, qb: &QBContext, client: &Agent) -> APIResult<Option<Self>>
+    fn query_single(query_str: &str, qb: &QBContext) -> APIResult<Option<Self>>
     where
         Self: Sized,
     {
-        let mut results = Self::query(query_str, Some(1), qb, client)?;
+        let mut results = Self::query(query_str, Some(1), qb)?;
         if results.is_empty() {
             return Ok(None);
         }
         Ok(Some(results.swap_remove(0)))
     }
+
+    /// Lazily queries the `QuickBooks` API for all objects of type T,
+    /// one page of `page_size` objects per request
+    /// `page_size` is clamped to the range `1..=1000`
+    /// `query_str` is the query string to use,
+    ///  this will be placed into the query like so:
+    /// ```ignore
+    /// "select * from {type_name} {query_str} STARTPOSITION {start} MAXRESULTS {page_size}"
+    /// ```
+    fn query_pages<'a>(
+        query_str: &str,
+        page_size: usize,
+        qb: &'a QBContext,
+    ) -> QueryPages<'a, Self>
+    where
+        Self: Sized;
+
+    /// Lazily queries the `QuickBooks` API for all objects of type T,
+    /// yielding them one at a time and fetching pages of 1000 objects as needed
+    /// `query_str` is the query string to use,
+    ///  this will be placed into the query like so:
+    /// ```ignore
+    /// "select * from {type_name} {query_str} STARTPOSITION {start} MAXRESULTS 1000"
+    /// ```
+    fn query_all<'a>(query_str: &str, qb: &'a QBContext) -> QueryAll<'a, Self>
+    where
+        Self: Sized;
+
+    /// Counts the objects of type T matching the where clause
+    /// without fetching them
+    /// `where_clause` is the where clause to use,
+    ///  this will be placed into the query like so:
+    /// ```ignore
+    /// "select count(*) from {type_name} {where_clause}"
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the request fails or `QuickBooks` rejects the query
+    fn count(where_clause: &str, qb: &QBContext) -> APIResult<usize>
+    where
+        Self: Sized;
+
+    /// Queries the `QuickBooks` API for objects of type T
+    /// using a [`QueryBuilder`] instead of a raw query string
+    ///
+    /// # Errors
+    ///
+    /// If the query contains an invalid field name or if the request itself fails
+    fn query_with(query: &QueryBuilder<Self>, qb: &QBContext) -> APIResult<Vec<Self>>
+    where
+        Self: Sized;
 }
 
 impl<T: QBItem> QBQuery for T {
-    fn query(
+    fn query(query_str: &str, max_results: Option<usize>, qb: &QBContext) -> APIResult<Vec<Self>> {
+        qb_query(query_str, max_results, qb)
+    }
+
+    fn query_pages<'a>(
         query_str: &str,
-        max_results: Option<usize>,
-        qb: &QBContext,
-        client: &Agent,
-    ) -> APIResult<Vec<Self>> {
-        qb_query(query_str, max_results, qb, client)
+        page_size: usize,
+        qb: &'a QBContext,
+    ) -> QueryPages<'a, Self> {
+        QueryPages::new(query_str, page_size, qb)
+    }
+
+    fn query_all<'a>(query_str: &str, qb: &'a QBContext) -> QueryAll<'a, Self> {
+        QueryAll {
+            pages: QueryPages::new(query_str, MAX_PAGE_SIZE, qb),
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    fn count(where_clause: &str, qb: &QBContext) -> APIResult<usize> {
+        qb_count::<T>(where_clause, qb)
+    }
+
+    fn query_with(query: &QueryBuilder<Self>, qb: &QBContext) -> APIResult<Vec<Self>> {
+        Ok(qb_query_response(query.build()?, qb)?.items)
+    }
+}
+
+/// The maximum number of entities `QuickBooks` returns for a single query
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Lazy iterator over the pages of a `QuickBooks` query.
+///
+/// Created by [`QBQuery::query_pages`]. Each call to `next` sends one request with
+/// `STARTPOSITION` and `MAXRESULTS` set, going through the context's rate limiter,
+/// and yields the page of entities it returned. Iteration stops after the first
+/// page that is shorter than the page size, or after the first error.
+pub struct QueryPages<'a, T> {
+    cursor: PageCursor<T>,
+    qb: &'a QBContext,
+}
+
+impl<'a, T: QBItem> QueryPages<'a, T> {
+    fn new(query_str: &str, page_size: usize, qb: &'a QBContext) -> Self {
+        Self {
+            cursor: PageCursor::new(query_str, page_size),
+            qb,
+        }
+    }
+
+    /// The `totalCount` reported by `QuickBooks` for the most recent page
+    #[must_use]
+    pub fn total_count(&self) -> Option<usize> {
+        self.cursor.total_count
+    }
+
+    /// The 1-based `startPosition` of the most recent page
+    #[must_use]
+    pub fn start_position(&self) -> Option<usize> {
+        self.cursor.start_position
+    }
+}
+
+impl<T: QBItem> Iterator for QueryPages<'_, T> {
+    type Item = APIResult<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let query = self.cursor.next_query()?;
+        let response = qb_query_response::<T>(query, self.qb);
+        self.cursor.advance(response)
+    }
+}
+
+/// Where a paged query is at, for both the blocking and the async pages
+pub(crate) struct PageCursor<T> {
+    query_str: String,
+    page_size: usize,
+    next_start: usize,
+    done: bool,
+    pub(crate) total_count: Option<usize>,
+    pub(crate) start_position: Option<usize>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: QBItem> PageCursor<T> {
+    pub(crate) fn new(query_str: &str, page_size: usize) -> Self {
+        Self {
+            query_str: query_str.to_string(),
+            page_size: page_size.clamp(1, MAX_PAGE_SIZE),
+            next_start: 1,
+            done: false,
+            total_count: None,
+            start_position: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The query for the next page, `None` once the last page was fetched
+    pub(crate) fn next_query(&self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+        Some(format!(
+            "select * from {} {} STARTPOSITION {} MAXRESULTS {}",
+            T::name(),
+            self.query_str,
+            self.next_start,
+            self.page_size
+        ))
+    }
+
+    /// Moves past the page fetched with [`PageCursor::next_query`], returning its
+    /// entities, or `None` if it was empty
+    pub(crate) fn advance(
+        &mut self,
+        response: APIResult<QueryResponse<T>>,
+    ) -> Option<APIResult<Vec<T>>> {
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        self.total_count = response.total_count;
+        self.start_position = response.start_position.or(Some(self.next_start));
+        self.next_start += response.items.len();
+        if response.items.len() < self.page_size {
+            self.done = true;
+        }
+
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Queried page of {} {} objects starting at {}",
+            response.items.len(),
+            T::name(),
+            self.start_position.unwrap_or_default()
+        );
+
+        if response.items.is_empty() {
+            return None;
+        }
+        Some(Ok(response.items))
+    }
+}
+
+/// The query [`QBQuery::query`] sends
+pub(crate) fn select_query<T: QBItem>(query_str: &str, max_results: Option<usize>) -> String {
+    let mut query = format!("select * from {} {query_str}", T::name());
+    if let Some(max) = max_results {
+        write!(&mut query, " MAXRESULTS {max}").expect("Writing to string should not fail");
+    }
+    query
+}
+
+/// The query [`QBQuery::count`] sends
+pub(crate) fn count_query<T: QBItem>(where_clause: &str) -> String {
+    format!("select count(*) from {} {where_clause}", T::name())
+}
+
+/// Lazy iterator over every entity matching a `QuickBooks` query.
+///
+/// Created by [`QBQuery::query_all`]. Fetches pages of up to 1000 entities as the
+/// previous page is used up; see [`QueryPages`] for how pages are requested.
+pub struct QueryAll<'a, T> {
+    pages: QueryPages<'a, T>,
+    current: std::vec::IntoIter<T>,
+}
+
+impl<T: QBItem> QueryAll<'_, T> {
+    /// The `totalCount` reported by `QuickBooks` for the most recent page
+    #[must_use]
+    pub fn total_count(&self) -> Option<usize> {
+        self.pages.total_count()
+    }
+
+    /// The 1-based `startPosition` of the most recent page
+    #[must_use]
+    pub fn start_position(&self) -> Option<usize> {
+        self.pages.start_position()
+    }
+}
+
+impl<T: QBItem> Iterator for QueryAll<'_, T> {
+    type Item = APIResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.current.next() {
+                return Some(Ok(item));
+            }
+            match self.pages.next()? {
+                Ok(page) => self.current = page.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
     }
 }
 
@@ -167,14 +471,23 @@ impl<T: QBItem> QBQuery for T {
 /// against the QuickBooks API. Malformed or malicious query strings could lead to
 /// unexpected behavior or security vulnerabilities. It is the caller's responsibility
 /// to ensure that the query string is well-formed and safe to execute.
+///
+/// Prefer building the query with [`QueryBuilder`] and running it with
+/// [`QBQuery::query_with`], which escapes values and checks field names.
 pub unsafe fn qb_query_raw<T: QBItem>(
     query_str: impl Display,
     qb: &QBContext,
-    client: &Agent,
 ) -> Result<Vec<T>, APIError> {
+    Ok(qb_query_response(query_str, qb)?.items)
+}
+
+/// Sends the query string as is and returns the full query response
+fn qb_query_response<T: QBItem>(
+    query_str: impl Display,
+    qb: &QBContext,
+) -> Result<QueryResponse<T>, APIError> {
     let response: QueryResponseExt<T> = qb_request(
         qb,
-        client,
         Method::GET,
         &format!("company/{}/query", qb.company_id),
         None::<&()>,
@@ -183,7 +496,7 @@ pub unsafe fn qb_query_raw<T: QBItem>(
     )?;
     #[cfg(feature = "logging")]
     log::info!("Successfully Queried Raw Data for query string : {query_str}",);
-    Ok(response.query_response.items)
+    Ok(response.query_response)
 }
 
 /// Query the quickbooks context using the query string,
@@ -202,20 +515,27 @@ fn qb_query<T: QBItem>(
     query_str: &str,
     max_results: Option<usize>,
     qb: &QBContext,
-    client: &Agent,
 ) -> Result<Vec<T>, APIError> {
-    let mut query = format!("select * from {} {query_str}", T::name());
-    if let Some(max) = max_results {
-        write!(&mut query, " MAXRESULTS {max}").expect("Writing to string should not fail");
-    }
-    unsafe { qb_query_raw::<T>(query, qb, client) }
+    let query = select_query::<T>(query_str, max_results);
+    Ok(qb_query_response(query, qb)?.items)
+}
+
+/// Count the objects of type `T` matching the where clause
+///
+/// Sends `select count(*) from {type_name} {where_clause}` and
+/// reads the `totalCount` of the response, no entities are returned.
+fn qb_count<T: QBItem>(where_clause: &str, qb: &QBContext) -> Result<usize, APIError> {
+    let query = count_query::<T>(where_clause);
+    let response = qb_query_response::<T>(query, qb)?;
+    Ok(response.total_count.unwrap_or_default())
 }
 
 /// Internal struct that Quickbooks returns when querying objects
 #[derive(Debug, Clone, Default, Deserialize)]
-#[serde(rename_all = "PascalCase", default)]
-struct QueryResponse<T> {
-    total_count: i64,
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct QueryResponse<T> {
+    #[serde(alias = "TotalCount")]
+    pub(crate) total_count: Option<usize>,
     #[serde(
         alias = "Item",
         alias = "Account",
@@ -230,16 +550,52 @@ struct QueryResponse<T> {
         alias = "SalesReceipt",
         alias = "Vendor"
     )]
-    items: Vec<T>,
-    start_position: i64,
-    max_results: i64,
+    pub(crate) items: Vec<T>,
+    #[serde(alias = "StartPosition")]
+    start_position: Option<usize>,
+    #[serde(alias = "MaxResults")]
+    max_results: Option<usize>,
 }
 
 /// Internal struct that Quickbooks returns when querying objects
 #[derive(Debug, Clone, Deserialize)]
-struct QueryResponseExt<T> {
+pub(crate) struct QueryResponseExt<T> {
     #[serde(default, rename = "QueryResponse")]
-    query_response: QueryResponse<T>,
+    pub(crate) query_response: QueryResponse<T>,
     #[allow(dead_code)]
     time: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use quickbooks_types::Invoice;
+
+    use super::QueryResponseExt;
+
+    #[test]
+    fn test_query_response_counts() {
+        let json = r#"{
+            "QueryResponse": {
+                "Invoice": [{"Id": "1"}, {"Id": "2"}],
+                "startPosition": 1001,
+                "maxResults": 2,
+                "totalCount": 2
+            },
+            "time": "2024-01-01T00:00:00.000-08:00"
+        }"#;
+        let resp: QueryResponseExt<Invoice> = serde_json::from_str(json).unwrap();
+        assert_eq!(resp.query_response.items.len(), 2);
+        assert_eq!(resp.query_response.start_position, Some(1001));
+        assert_eq!(resp.query_response.max_results, Some(2));
+        assert_eq!(resp.query_response.total_count, Some(2));
+    }
+
+    #[test]
+    fn test_count_response() {
+        let json =
+            r#"{"QueryResponse": {"totalCount": 42}, "time": "2024-01-01T00:00:00.000-08:00"}"#;
+        let resp: QueryResponseExt<Invoice> = serde_json::from_str(json).unwrap();
+        assert!(resp.query_response.items.is_empty());
+        assert_eq!(resp.query_response.total_count, Some(42));
+    }
+}
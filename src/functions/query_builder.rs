@@ -0,0 +1,533 @@
+//! Type-safe builder for `QuickBooks` queries.
+//!
+//! [`QueryBuilder`] renders queries in the `QuickBooks` SQL dialect from typed
+//! fields and values instead of raw strings. Values are always quoted and escaped,
+//! and field names are either checked against the entity type at compile time
+//! with [`qb_field!`](crate::qb_field) or validated when the query is rendered.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use quick_oxibooks::{qb_field, QBContext, Environment};
+//! use quick_oxibooks::functions::{query::QBQuery, query_builder::{Field, Order, QueryBuilder}};
+//! use quickbooks_types::Invoice;
+//!
+//! let qb_context = QBContext::new(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//! ).unwrap();
+//!
+//! let query = QueryBuilder::<Invoice>::new()
+//!     .select([qb_field!(Invoice.id), qb_field!(Invoice.doc_number)])
+//!     .gt(qb_field!(Invoice.total_amt), 100.0)
+//!     .like(qb_field!(Invoice.doc_number), "INV-%")
+//!     .eq(Field::raw("CustomerRef"), "42")
+//!     .order_by(qb_field!(Invoice.doc_number), Order::Desc)
+//!     .max_results(50);
+//!
+//! let invoices = Invoice::query_with(&query, &qb_context).unwrap();
+//! ```
+
+use std::{borrow::Cow, fmt::Write, marker::PhantomData};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use quickbooks_types::QBItem;
+
+use crate::{error::APIErrorInner, APIResult};
+
+/// A field of the entity type `T`, as named by `QuickBooks`.
+///
+/// Use the [`qb_field!`](crate::qb_field) macro to create a field that is checked
+/// against `T` at compile time, or [`Field::raw`] for names that can't be checked
+/// that way, such as nested fields like `MetaData.CreateTime`.
+pub struct Field<T> {
+    name: Cow<'static, str>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Field<T> {
+    /// Creates a field from a Rust field name, converting it to the
+    /// `QuickBooks` spelling (`total_amt` becomes `TotalAmt`).
+    ///
+    /// Prefer the [`qb_field!`](crate::qb_field) macro, which also checks that
+    /// the field exists on `T`.
+    #[must_use]
+    pub fn from_snake_case(name: &str) -> Self {
+        Self::raw(to_pascal_case(name))
+    }
+
+    /// Creates a field from a name already spelled the way `QuickBooks` expects,
+    /// e.g. `DisplayName` or `MetaData.LastUpdatedTime`.
+    ///
+    /// The name is not checked against `T`, but it must only contain ASCII letters,
+    /// digits, `_` and `.`, otherwise rendering the query fails with `InvalidQuery`.
+    pub fn raw(name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: name.into(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The name of the field as it will appear in the query
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validated(&self) -> APIResult<&str> {
+        let valid = self.name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if valid {
+            Ok(&self.name)
+        } else {
+            Err(APIErrorInner::InvalidQuery(format!("Invalid field name: {:?}", self.name)).into())
+        }
+    }
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        Self::raw(self.name.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for Field<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Field").field(&self.name).finish()
+    }
+}
+
+/// Creates a [`Field`] of an entity type, checking at compile time that the field exists.
+///
+/// The Rust field name is converted to the `QuickBooks` spelling, so
+/// `qb_field!(Invoice.total_amt)` refers to `TotalAmt`.
+///
+/// # Examples
+///
+/// ```rust
+/// use quick_oxibooks::qb_field;
+/// use quickbooks_types::Customer;
+///
+/// let field = qb_field!(Customer.display_name);
+/// assert_eq!(field.name(), "DisplayName");
+/// ```
+///
+/// ```compile_fail
+/// use quick_oxibooks::qb_field;
+/// use quickbooks_types::Customer;
+///
+/// // This will fail to compile since `invalid_field` doesn't exist on Customer
+/// let field = qb_field!(Customer.invalid_field);
+/// ```
+#[macro_export]
+macro_rules! qb_field {
+    ($struct_name:ident . $field:ident) => {{
+        // Compiler doesn't include this in the binary,
+        // just uses it to make sure the field exists
+        const _: () = {
+            #[allow(dead_code)]
+            fn dummy(v: $struct_name) {
+                let _ = v.$field;
+            }
+        };
+        $crate::functions::query_builder::Field::<$struct_name>::from_snake_case(stringify!($field))
+    }};
+}
+
+/// A value compared against a field in a query.
+///
+/// Created from strings, numbers, booleans and dates through `From`.
+/// Strings and dates are quoted and escaped when the query is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    /// A string, rendered quoted and escaped
+    String(String),
+    /// A number, rendered as is
+    Number(String),
+    /// A boolean, rendered as `true` or `false`
+    Bool(bool),
+}
+
+impl QueryValue {
+    fn render(&self, out: &mut String) {
+        match self {
+            QueryValue::String(s) => {
+                out.push('\'');
+                escape_into(s, out);
+                out.push('\'');
+            }
+            QueryValue::Number(n) => out.push_str(n),
+            QueryValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        }
+    }
+}
+
+macro_rules! impl_number_value {
+    ($($t:ty),+) => {
+        $(
+            impl From<$t> for QueryValue {
+                fn from(value: $t) -> Self {
+                    QueryValue::Number(value.to_string())
+                }
+            }
+        )+
+    };
+}
+impl_number_value!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize, f32, f64);
+
+impl From<&str> for QueryValue {
+    fn from(value: &str) -> Self {
+        QueryValue::String(value.to_string())
+    }
+}
+
+impl From<String> for QueryValue {
+    fn from(value: String) -> Self {
+        QueryValue::String(value)
+    }
+}
+
+impl From<&String> for QueryValue {
+    fn from(value: &String) -> Self {
+        QueryValue::String(value.clone())
+    }
+}
+
+impl From<bool> for QueryValue {
+    fn from(value: bool) -> Self {
+        QueryValue::Bool(value)
+    }
+}
+
+impl From<NaiveDate> for QueryValue {
+    fn from(value: NaiveDate) -> Self {
+        QueryValue::String(value.format("%Y-%m-%d").to_string())
+    }
+}
+
+impl From<DateTime<Utc>> for QueryValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        QueryValue::String(value.to_rfc3339_opts(chrono::SecondsFormat::Secs, false))
+    }
+}
+
+/// Comparison operators supported by `QuickBooks` queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `=`
+    Eq,
+    /// `<`
+    Lt,
+    /// `>`
+    Gt,
+    /// `<=`
+    Le,
+    /// `>=`
+    Ge,
+}
+
+impl Operator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Lt => "<",
+            Operator::Gt => ">",
+            Operator::Le => "<=",
+            Operator::Ge => ">=",
+        }
+    }
+}
+
+/// Sort direction for `ORDERBY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone)]
+enum Condition<T> {
+    Compare(Field<T>, Operator, QueryValue),
+    In(Field<T>, Vec<QueryValue>),
+    Like(Field<T>, String),
+}
+
+/// Builder for `QuickBooks` queries over the entity type `T`.
+///
+/// Conditions added with `eq`, `lt`, `gt`, `le`, `ge`, `is_in` and `like` are joined
+/// with `AND`, the only conjunction `QuickBooks` supports. Rendering fails with
+/// [`APIErrorInner::InvalidQuery`] if a raw field name contains characters that
+/// are not allowed in `QuickBooks` field names.
+///
+/// Run the query with [`QBQuery::query_with`](crate::functions::query::QBQuery::query_with).
+#[derive(Debug, Clone)]
+pub struct QueryBuilder<T> {
+    fields: Vec<Field<T>>,
+    conditions: Vec<Condition<T>>,
+    order_by: Vec<(Field<T>, Order)>,
+    start_position: Option<usize>,
+    max_results: Option<usize>,
+}
+
+impl<T> Default for QueryBuilder<T> {
+    fn default() -> Self {
+        Self {
+            fields: Vec::new(),
+            conditions: Vec::new(),
+            order_by: Vec::new(),
+            start_position: None,
+            max_results: None,
+        }
+    }
+}
+
+impl<T: QBItem> QueryBuilder<T> {
+    /// Creates an empty query selecting every field of `T`
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects only the given fields instead of `*`
+    #[must_use]
+    pub fn select(mut self, fields: impl IntoIterator<Item = Field<T>>) -> Self {
+        self.fields.extend(fields);
+        self
+    }
+
+    /// Adds a comparison condition, joined to the others with `AND`
+    #[must_use]
+    pub fn filter(mut self, field: Field<T>, op: Operator, value: impl Into<QueryValue>) -> Self {
+        self.conditions
+            .push(Condition::Compare(field, op, value.into()));
+        self
+    }
+
+    /// Adds a `field = value` condition
+    #[must_use]
+    pub fn eq(self, field: Field<T>, value: impl Into<QueryValue>) -> Self {
+        self.filter(field, Operator::Eq, value)
+    }
+
+    /// Adds a `field < value` condition
+    #[must_use]
+    pub fn lt(self, field: Field<T>, value: impl Into<QueryValue>) -> Self {
+        self.filter(field, Operator::Lt, value)
+    }
+
+    /// Adds a `field > value` condition
+    #[must_use]
+    pub fn gt(self, field: Field<T>, value: impl Into<QueryValue>) -> Self {
+        self.filter(field, Operator::Gt, value)
+    }
+
+    /// Adds a `field <= value` condition
+    #[must_use]
+    pub fn le(self, field: Field<T>, value: impl Into<QueryValue>) -> Self {
+        self.filter(field, Operator::Le, value)
+    }
+
+    /// Adds a `field >= value` condition
+    #[must_use]
+    pub fn ge(self, field: Field<T>, value: impl Into<QueryValue>) -> Self {
+        self.filter(field, Operator::Ge, value)
+    }
+
+    /// Adds a `field IN (values...)` condition
+    #[must_use]
+    pub fn is_in<V: Into<QueryValue>>(
+        mut self,
+        field: Field<T>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values = values.into_iter().map(Into::into).collect();
+        self.conditions.push(Condition::In(field, values));
+        self
+    }
+
+    /// Adds a `field LIKE 'pattern'` condition,
+    /// `%` in the pattern matches any sequence of characters
+    #[must_use]
+    pub fn like(mut self, field: Field<T>, pattern: impl Into<String>) -> Self {
+        self.conditions.push(Condition::Like(field, pattern.into()));
+        self
+    }
+
+    /// Sorts the results by the field, may be called more than once
+    #[must_use]
+    pub fn order_by(mut self, field: Field<T>, order: Order) -> Self {
+        self.order_by.push((field, order));
+        self
+    }
+
+    /// Sets the 1-based position of the first result to return
+    #[must_use]
+    pub fn start_position(mut self, start_position: usize) -> Self {
+        self.start_position = Some(start_position);
+        self
+    }
+
+    /// Sets the maximum number of results to return, `QuickBooks` allows up to 1000
+    #[must_use]
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.max_results = Some(max_results);
+        self
+    }
+
+    /// Renders the full query, e.g.
+    /// `SELECT * FROM Invoice WHERE TotalAmt > 100 MAXRESULTS 10`
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidQuery` if a field name is not a valid `QuickBooks` field name
+    pub fn build(&self) -> APIResult<String> {
+        let mut query = String::from("SELECT ");
+        if self.fields.is_empty() {
+            query.push('*');
+        } else {
+            for (i, field) in self.fields.iter().enumerate() {
+                if i > 0 {
+                    query.push_str(", ");
+                }
+                query.push_str(field.validated()?);
+            }
+        }
+        query.push_str(" FROM ");
+        query.push_str(T::name());
+        self.render_clauses(&mut query)?;
+        Ok(query)
+    }
+
+    fn render_clauses(&self, out: &mut String) -> APIResult<()> {
+        for (i, condition) in self.conditions.iter().enumerate() {
+            out.push_str(if i == 0 { " WHERE " } else { " AND " });
+            match condition {
+                Condition::Compare(field, op, value) => {
+                    out.push_str(field.validated()?);
+                    out.push(' ');
+                    out.push_str(op.as_str());
+                    out.push(' ');
+                    value.render(out);
+                }
+                Condition::In(field, values) => {
+                    out.push_str(field.validated()?);
+                    out.push_str(" IN (");
+                    for (i, value) in values.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        value.render(out);
+                    }
+                    out.push(')');
+                }
+                Condition::Like(field, pattern) => {
+                    out.push_str(field.validated()?);
+                    out.push_str(" LIKE '");
+                    escape_into(pattern, out);
+                    out.push('\'');
+                }
+            }
+        }
+
+        for (i, (field, order)) in self.order_by.iter().enumerate() {
+            out.push_str(if i == 0 { " ORDERBY " } else { ", " });
+            out.push_str(field.validated()?);
+            if *order == Order::Desc {
+                out.push_str(" DESC");
+            }
+        }
+
+        if let Some(start) = self.start_position {
+            write!(out, " STARTPOSITION {start}").expect("Writing to string should not fail");
+        }
+        if let Some(max) = self.max_results {
+            write!(out, " MAXRESULTS {max}").expect("Writing to string should not fail");
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a string for use inside single quotes in a `QuickBooks` query
+fn escape_into(value: &str, out: &mut String) {
+    for c in value.chars() {
+        if c == '\'' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Converts a Rust `snake_case` field name to the `QuickBooks` `PascalCase` spelling
+fn to_pascal_case(name: &str) -> String {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    let mut out = String::with_capacity(name.len());
+    for part in name.split('_').filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.push(first.to_ascii_uppercase());
+            out.extend(chars);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use quickbooks_types::{Customer, Invoice};
+
+    use super::{Field, Order, QueryBuilder};
+
+    #[test]
+    fn test_build_query() {
+        let query = QueryBuilder::<Invoice>::new()
+            .select([qb_field!(Invoice.id), qb_field!(Invoice.doc_number)])
+            .gt(qb_field!(Invoice.total_amt), 100.5)
+            .le(
+                Field::raw("TxnDate"),
+                NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            )
+            .is_in(qb_field!(Invoice.doc_number), ["1001", "1002"])
+            .like(qb_field!(Invoice.doc_number), "INV-%")
+            .order_by(Field::raw("MetaData.CreateTime"), Order::Desc)
+            .start_position(1)
+            .max_results(10)
+            .build()
+            .unwrap();
+        assert_eq!(
+            query,
+            "SELECT Id, DocNumber FROM Invoice WHERE TotalAmt > 100.5 AND TxnDate <= '2024-01-31' \
+             AND DocNumber IN ('1001', '1002') AND DocNumber LIKE 'INV-%' \
+             ORDERBY MetaData.CreateTime DESC STARTPOSITION 1 MAXRESULTS 10"
+        );
+    }
+
+    #[test]
+    fn test_values_are_escaped() {
+        let query = QueryBuilder::<Customer>::new()
+            .eq(
+                qb_field!(Customer.display_name),
+                r"O'Brien \ Sons' OR '1'='1",
+            )
+            .eq(qb_field!(Customer.active), true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            query,
+            r"SELECT * FROM Customer WHERE DisplayName = 'O\'Brien \\ Sons\' OR \'1\'=\'1' AND Active = true"
+        );
+    }
+
+    #[test]
+    fn test_invalid_raw_field() {
+        let result = QueryBuilder::<Customer>::new()
+            .eq(Field::raw("Id = '1' OR Id"), "2")
+            .build();
+        assert!(result.is_err());
+    }
+}
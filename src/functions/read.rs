@@ -1,7 +1,7 @@
 //! Functions for reading `QuickBooks` entities from the API.
 
 use quickbooks_types::QBItem;
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{
     error::{APIError, APIErrorInner},
@@ -33,14 +33,11 @@ use super::{qb_request, QBResponse};
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::read::QBRead;
 /// use quickbooks_types::Customer;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Entity with ID that needs fresh data
@@ -48,7 +45,7 @@ use super::{qb_request, QBResponse};
 /// customer.id = Some("123".to_string());
 ///
 /// // Read fresh data from QuickBooks into this entity
-/// customer.read(&qb_context, &client).unwrap();
+/// customer.read(&qb_context).unwrap();
 /// println!("Updated customer: {}", customer.display_name.unwrap_or_default());
 /// ```
 ///
@@ -58,18 +55,15 @@ use super::{qb_request, QBResponse};
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::read::qb_get_single;
 /// use quickbooks_types::Customer;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Fetch a customer by ID
-/// let customer: Customer = qb_get_single("123", &qb_context, &client).unwrap();
+/// let customer: Customer = qb_get_single("123", &qb_context).unwrap();
 /// println!("Fetched customer: {}", customer.display_name.unwrap_or_default());
 /// ```
 ///
@@ -80,24 +74,23 @@ use super::{qb_request, QBResponse};
 /// - `BadRequest`: `QuickBooks` API returned an error (e.g., entity not found)
 /// - `JsonError`: Response parsing errors
 pub trait QBRead {
-    fn read(&mut self, qb: &QBContext, client: &Agent) -> Result<(), APIError>;
+    fn read(&mut self, qb: &QBContext) -> Result<(), APIError>;
 }
 impl<T: QBItem> QBRead for T {
-    fn read(&mut self, qb: &QBContext, client: &Agent) -> Result<(), APIError> {
-        qb_read(self, qb, client)
+    fn read(&mut self, qb: &QBContext) -> Result<(), APIError> {
+        qb_read(self, qb)
     }
 }
 
 /// Read the object by ID from quickbooks context
 /// and write it to an item
-fn qb_read<T: QBItem>(item: &mut T, qb: &QBContext, client: &Agent) -> Result<(), APIError> {
+fn qb_read<T: QBItem>(item: &mut T, qb: &QBContext) -> Result<(), APIError> {
     let Some(id) = item.id() else {
         return Err(APIErrorInner::NoIdOnRead.into());
     };
 
     let response: QBResponse<T> = qb_request(
         qb,
-        client,
         Method::GET,
         &format!("company/{}/{}/{}", qb.company_id, T::qb_id(), id),
         None::<&()>,
@@ -147,20 +140,17 @@ fn qb_read<T: QBItem>(item: &mut T, qb: &QBContext, client: &Agent) -> Result<()
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::read::qb_get_single;
 /// use quickbooks_types::{Customer, Invoice, Item};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Fetch different types of entities
-/// let customer: Customer = qb_get_single("123", &qb_context, &client).unwrap();
-/// let invoice: Invoice = qb_get_single("456", &qb_context, &client).unwrap();
-/// let item: Item = qb_get_single("789", &qb_context, &client).unwrap();
+/// let customer: Customer = qb_get_single("123", &qb_context).unwrap();
+/// let invoice: Invoice = qb_get_single("456", &qb_context).unwrap();
+/// let item: Item = qb_get_single("789", &qb_context).unwrap();
 /// ```
 ///
 /// # Errors
@@ -169,10 +159,9 @@ fn qb_read<T: QBItem>(item: &mut T, qb: &QBContext, client: &Agent) -> Result<()
 /// - `BadRequest`: `QuickBooks` API returned an error (e.g., entity not found, invalid ID)
 /// - `JsonError`: Response parsing errors
 /// - Rate limiting errors if API limits are exceeded
-pub fn qb_get_single<T: QBItem>(id: &str, qb: &QBContext, client: &Agent) -> Result<T, APIError> {
+pub fn qb_get_single<T: QBItem>(id: &str, qb: &QBContext) -> Result<T, APIError> {
     let response: QBResponse<T> = qb_request(
         qb,
-        client,
         Method::GET,
         &format!("company/{}/{}/{}", qb.company_id, T::qb_id(), id),
         None::<&()>,
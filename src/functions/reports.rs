@@ -1,7 +1,7 @@
 //! Functions for handling `QuickBooks` financial reports via the API.
 
 use quickbooks_types::reports::{types::QBReportType, Report};
-use ureq::{http::Method, Agent};
+use ureq::http::Method;
 
 use crate::{functions::qb_request, APIResult, QBContext};
 
@@ -37,20 +37,16 @@ use crate::{functions::qb_request, APIResult, QBContext};
 /// use quick_oxibooks::{QBContext, Environment};
 /// use quick_oxibooks::functions::reports::QBReport;
 /// use quickbooks_types::reports::{Report, types::ProfitAndLoss};
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Get a basic Profit & Loss report
 /// let report = Report::get(
 ///     &qb_context,
-///     &client,
 ///     &ProfitAndLoss,
 ///     None
 /// ).unwrap();
@@ -65,14 +61,11 @@ use crate::{functions::qb_request, APIResult, QBContext};
 /// use quickbooks_types::reports::{Report, types::{BalanceSheet, BalanceSheetParams}};
 /// use quickbooks_types::reports::params::SummarizeColumnBy;
 /// use chrono::NaiveDate;
-/// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
 /// let qb_context = QBContext::new(
 ///     Environment::SANDBOX,
 ///     "company_id".to_string(),
 ///     "access_token".to_string(),
-///     &client,
 /// ).unwrap();
 ///
 /// // Create parameters for the report
@@ -84,7 +77,6 @@ use crate::{functions::qb_request, APIResult, QBContext};
 /// // Get Balance Sheet with parameters
 /// let report = Report::get(
 ///     &qb_context,
-///     &client,
 ///     &BalanceSheet,
 ///     Some(params)
 /// ).unwrap();
@@ -124,7 +116,6 @@ use crate::{functions::qb_request, APIResult, QBContext};
 pub trait QBReport {
     fn get<T: QBReportType>(
         qb: &QBContext,
-        client: &Agent,
         report_type: &T,
         params: Option<T::QueryParams>,
     ) -> APIResult<Self>
@@ -135,20 +126,13 @@ pub trait QBReport {
 impl QBReport for Report {
     fn get<T: QBReportType>(
         qb: &QBContext,
-        client: &Agent,
         report_type: &T,
         params: Option<T::QueryParams>,
     ) -> APIResult<Self> {
-        let path = format!(
-            "/v3/company/{}/reports/{}",
-            qb.company_id,
-            report_type.url_name()
-        );
         qb_request(
             qb,
-            client,
             Method::GET,
-            dbg!(&path),
+            &report_path(qb, report_type),
             None::<&()>,
             Some("application/json"),
             params
@@ -157,3 +141,12 @@ impl QBReport for Report {
         )
     }
 }
+
+/// The path of a report of the given type
+pub(crate) fn report_path<T: QBReportType>(qb: &QBContext, report_type: &T) -> String {
+    format!(
+        "company/{}/reports/{}",
+        qb.company_id,
+        report_type.url_name()
+    )
+}
@@ -0,0 +1,109 @@
+//! Request IDs for idempotent writes.
+//!
+//! `QuickBooks` deduplicates writes sent with the same `requestid` query parameter:
+//! a repeated request returns the response of the first one instead of being applied
+//! again. Every create, update, delete, void and batch request is sent with one, so a
+//! [`RetryPolicy`](crate::functions::retry::RetryPolicy) can safely retry them, and
+//! the retries reuse it.
+//!
+//! The plain operations generate a new request ID for every call. To retry a write
+//! later, e.g. after a timeout in a previous run, send it with a request ID you keep,
+//! through the `*_with_request_id` variants, which also return the ID that was used.
+
+use std::fmt::{self, Write};
+
+use crate::APIResult;
+
+/// Name of the query parameter `QuickBooks` reads the request ID from
+pub(crate) const REQUEST_ID_PARAM: &str = "requestid";
+
+/// A request ID, a random UUID unless given
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Generates a new random (version 4) UUID from the operating system's random
+    /// number generator
+    ///
+    /// # Errors
+    ///
+    /// If the operating system's random number generator is unavailable
+    pub fn new() -> APIResult<Self> {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).map_err(std::io::Error::from)?;
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = bytes.iter().fold(String::with_capacity(32), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        });
+        Ok(Self(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )))
+    }
+
+    /// The request ID as sent to `QuickBooks`
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for RequestId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for RequestId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+/// The result of a write along with the request ID it was sent with
+///
+/// Writes retried after a stale `SyncToken` conflict send a different request,
+/// so they get a new request ID; `request_id` is the one of the last request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithRequestId<T> {
+    pub value: T,
+    pub request_id: RequestId,
+}
+
+impl<T> WithRequestId<T> {
+    /// Discards the request ID
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestId;
+
+    #[test]
+    fn test_request_id_is_uuid_v4() {
+        let id = RequestId::new().unwrap();
+        let parts: Vec<_> = id.as_str().split('-').collect();
+        assert_eq!(
+            parts.iter().map(|p| p.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(parts[2].starts_with('4'));
+        assert!(matches!(&parts[3][..1], "8" | "9" | "a" | "b"));
+        assert_ne!(id, RequestId::new().unwrap());
+    }
+}
@@ -0,0 +1,211 @@
+//! Automatic retries of throttled and failed requests.
+//!
+//! `QuickBooks` answers with a `429` when a company's rate limit is exceeded, and now
+//! and then with a `5xx` or a dropped connection. This module provides the opt-in
+//! [`RetryPolicy`] that retries those requests with exponential backoff.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use ureq::http::HeaderMap;
+
+use super::random_u64;
+use crate::error::{APIError, APIErrorInner, QBErrorResponse};
+
+/// Fault code `QuickBooks` returns when a request is throttled
+pub const THROTTLE_FAULT_CODE: &str = "3001";
+
+/// Policy for retrying throttled and failed requests.
+///
+/// When set on a [`QBContext`](crate::QBContext) with
+/// [`QBContext::with_retry_policy`](crate::QBContext::with_retry_policy), every request
+/// is retried up to `max_attempts` attempts in total when:
+///
+/// - The response status is in `retry_statuses` (`429`, `500`, `502`, `503` and `504` by default)
+/// - The response is a fault carrying one of the `retry_fault_codes`
+///   ([`THROTTLE_FAULT_CODE`] by default)
+/// - The request failed to connect or timed out, if `retry_transport_errors` is set
+///
+/// Before each retry the request waits for the `Retry-After` header of the response
+/// if it has one, or otherwise for `initial_backoff`, doubled after every attempt.
+/// Either way it waits no longer than `max_backoff`. With `jitter`, the backoff is randomized between half and
+/// all of it, so clients throttled together don't retry together.
+///
+/// `POST` requests create or change entities, and are only retried when they carry
+/// a `requestid` query parameter that lets `QuickBooks` deduplicate them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::retry::RetryPolicy;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// )
+/// .unwrap()
+/// .with_retry_policy(RetryPolicy {
+///     max_attempts: 5,
+///     max_backoff: Duration::from_secs(60),
+///     ..RetryPolicy::default()
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: usize,
+    /// Backoff before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for the backoff, also applied to `Retry-After`
+    pub max_backoff: Duration,
+    /// Randomizes each backoff between half and all of it
+    pub jitter: bool,
+    /// Response statuses that are retried
+    pub retry_statuses: Vec<u16>,
+    /// Fault codes that are retried, whatever the response status
+    pub retry_fault_codes: Vec<String>,
+    /// Retries requests that failed to connect, timed out or lost the connection
+    pub retry_transport_errors: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a policy with the default settings and the given maximum number of attempts
+    #[must_use]
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_attempts,
+            ..Self::default()
+        }
+    }
+
+    /// Returns `true` if responses with the given status are retried
+    #[must_use]
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.retry_statuses.contains(&status)
+    }
+
+    /// Returns `true` if the fault in the response carries a retried fault code
+    #[must_use]
+    pub fn retries_fault(&self, response: &QBErrorResponse) -> bool {
+        self.retry_fault_codes
+            .iter()
+            .any(|code| response.has_fault_code(code))
+    }
+
+    /// Returns `true` if the error a [`Transport`](crate::client::Transport) failed with is retried
+    ///
+    /// Connection failures and timeouts of `ureq` and `reqwest` are retried, as well
+    /// as the I/O errors of other transports.
+    #[must_use]
+    pub fn retries_transport_error(&self, error: &APIError) -> bool {
+        self.retry_transport_errors
+            && match &**error {
+                APIErrorInner::UreqError(error) => matches!(
+                    error,
+                    ureq::Error::Io(_)
+                        | ureq::Error::Timeout(_)
+                        | ureq::Error::HostNotFound
+                        | ureq::Error::ConnectionFailed
+                        | ureq::Error::BodyStalled
+                ),
+                #[cfg(feature = "async")]
+                APIErrorInner::ReqwestError(error) => {
+                    error.is_timeout() || error.is_connect() || error.is_request()
+                }
+                APIErrorInner::IoError(_) => true,
+                _ => false,
+            }
+    }
+
+    /// The delay before retrying after the given attempt, starting at 1
+    ///
+    /// `retry_after` is the delay asked for by the response, which is used without
+    /// jitter, up to `max_backoff`.
+    #[must_use]
+    pub fn delay(&self, attempt: usize, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_backoff);
+        }
+        let exponent = u32::try_from(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(exponent))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        if self.jitter {
+            backoff / 2 + random_duration(backoff / 2)
+        } else {
+            backoff
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            retry_statuses: vec![429, 500, 502, 503, 504],
+            retry_fault_codes: vec![THROTTLE_FAULT_CODE.to_string()],
+            retry_transport_errors: true,
+        }
+    }
+}
+
+/// Parses the `Retry-After` header, given either in seconds or as an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get("Retry-After")?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// A random duration between zero and `max`, without pulling in a random number generator
+fn random_duration(max: Duration) -> Duration {
+    let random = random_u64();
+    let nanos = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+    Duration::from_nanos(random % nanos.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1, None), Duration::from_millis(500));
+        assert_eq!(policy.delay(3, None), Duration::from_secs(2));
+        assert_eq!(policy.delay(20, None), Duration::from_secs(30));
+        let retry_after = Duration::from_secs(20);
+        assert_eq!(policy.delay(1, Some(retry_after)), retry_after);
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600))),
+            Duration::from_secs(30)
+        );
+
+        let policy = RetryPolicy::default();
+        for attempt in 1..10 {
+            let delay = policy.delay(attempt, None);
+            let backoff = Duration::from_millis(500)
+                .saturating_mul(1 << (attempt - 1))
+                .min(Duration::from_secs(30));
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+    }
+}
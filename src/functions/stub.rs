@@ -0,0 +1,134 @@
+//! A [`Transport`] for unit tests, answering requests with canned responses.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use serde_json::{json, Value};
+use ureq::http::{header::CONTENT_TYPE, Method, Request, Response};
+use urlencoding::decode;
+
+use crate::{
+    client::Transport, functions::conflict::STALE_OBJECT_FAULT_CODE, limiter::LimiterRegistry,
+    APIResult, Endpoints, Environment, QBContext,
+};
+
+/// A request sent through a [`StubTransport`]
+#[derive(Debug)]
+pub(crate) struct SentRequest {
+    pub(crate) method: Method,
+    pub(crate) path: String,
+    pub(crate) query: Vec<(String, String)>,
+    pub(crate) body: Value,
+}
+
+impl SentRequest {
+    /// The value of the query parameter with the given name
+    pub(crate) fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Answers every request with the next of its responses, in order, and keeps the
+/// requests it was sent
+pub(crate) struct StubTransport {
+    responses: Mutex<VecDeque<(u16, Value)>>,
+    sent: Mutex<Vec<SentRequest>>,
+}
+
+impl StubTransport {
+    pub(crate) fn new(responses: impl IntoIterator<Item = (u16, Value)>) -> Arc<Self> {
+        Arc::new(Self {
+            responses: Mutex::new(responses.into_iter().collect()),
+            sent: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// A context for company `123` sending its requests through the stub, with a
+    /// limiter of its own
+    pub(crate) fn context(self: &Arc<Self>) -> QBContext {
+        let discovery_doc = serde_json::from_value(json!({
+            "issuer": "http://localhost",
+            "authorization_endpoint": "http://localhost/connect/oauth2",
+            "token_endpoint": "http://localhost/oauth2/v1/tokens/bearer",
+            "userinfo_endpoint": "http://localhost/v1/openid_connect/userinfo",
+            "revocation_endpoint": "http://localhost/oauth2/v1/tokens/revoke",
+            "jwks_uri": "http://localhost/oauth2/v1/keys",
+            "response_types_supported": [],
+            "subject_types_supported": [],
+            "id_token_signing_alg_values_supported": [],
+            "scopes_supported": [],
+            "token_endpoint_auth_methods_supported": [],
+            "claims_supported": [],
+        }))
+        .unwrap();
+        QBContext::builder(
+            Environment::SANDBOX,
+            "123".to_string(),
+            "access_token_xyz".to_string(),
+        )
+        .endpoints(Endpoints::from_base_url("http://localhost"))
+        .discovery_doc(discovery_doc)
+        .transport(Arc::clone(self) as Arc<dyn Transport>)
+        .build()
+        .unwrap()
+        .with_limiter_registry(&LimiterRegistry::default())
+    }
+
+    /// Takes the requests sent so far
+    pub(crate) fn take_sent(&self) -> Vec<SentRequest> {
+        std::mem::take(&mut *self.sent.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Transport for StubTransport {
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+        let uri = request.uri();
+        let sent = SentRequest {
+            method: request.method().clone(),
+            path: uri.path().to_string(),
+            query: uri
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.to_string(), decode(value).unwrap().into_owned()))
+                .collect(),
+            body: serde_json::from_slice(request.body()).unwrap_or(Value::Null),
+        };
+        let (status, body) = self
+            .responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop_front()
+            .unwrap_or_else(|| panic!("Unexpected request {} {}", sent.method, uri));
+        self.sent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sent);
+        Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?)?)
+    }
+}
+
+/// The fault `QuickBooks` answers with when a request carries an outdated `SyncToken`
+pub(crate) fn stale_object_fault() -> (u16, Value) {
+    let fault = json!({
+        "Fault": {
+            "Error": [{
+                "Message": "Stale Object Error",
+                "Detail": "Stale Object Error : You and another user were working on this at the same time.",
+                "code": STALE_OBJECT_FAULT_CODE,
+            }],
+            "type": "ValidationFault",
+        },
+        "time": "2024-01-01T00:00:00.000-08:00",
+    });
+    (400, fault)
+}
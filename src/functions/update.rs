@@ -0,0 +1,478 @@
+//! Functions for updating `QuickBooks` entities via the API.
+//!
+//! This module defines the `QBUpdate` trait, which provides methods for sending
+//! full and sparse updates of existing entities to `QuickBooks` Online. The trait is
+//! automatically implemented for all types that implement `QBItem`.
+//!
+//! It also provides [`SparseDiff`], which compares an entity as it was read from
+//! `QuickBooks` with a locally modified copy and sends only the fields that changed.
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use quickbooks_types::QBItem;
+use serde_json::{Map, Value};
+use ureq::http::Method;
+
+use crate::{
+    error::{APIError, APIErrorInner},
+    functions::{
+        conflict::{latest_sync_token, retry_on_conflict, with_sync_token},
+        qb_request,
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
+    APIResult, QBContext,
+};
+
+/// Trait for updating `QuickBooks` entities via the API.
+///
+/// This trait provides the `update` and `sparse_update` methods for sending
+/// modified entities back to `QuickBooks`. It validates that entities have the
+/// required ID and sync token before sending them to the API.
+///
+/// # Automatic Implementation
+///
+/// This trait is automatically implemented for all types that implement [`QBItem`].
+/// You don't need to implement it manually.
+///
+/// # Full vs. Sparse Updates
+///
+/// - **Full update** (`update`): Every writable field is replaced. Fields that are
+///   missing from the entity are cleared in `QuickBooks`.
+/// - **Sparse update** (`sparse_update`): Sent with `"sparse": true`, only the fields
+///   present on the entity are changed and everything else is left untouched.
+///
+/// # Requirements
+///
+/// Before updating, entities must have:
+/// - Valid ID (entity exists in `QuickBooks`)
+/// - Current sync token (for optimistic concurrency control)
+///
+/// These are automatically present when entities are read from `QuickBooks`.
+///
+/// # Conflict Recovery
+///
+/// If the context has a [`ConflictPolicy`](crate::functions::conflict::ConflictPolicy)
+/// set, updates sent with `update_with_merge` that are rejected because of a stale
+/// sync token are retried against the latest version of the entity, combined with
+/// the local changes by the given merge closure. `update` and `sparse_update`
+/// return the conflict instead, as resending the local copy would overwrite the
+/// changes made in the meantime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::{query::QBQuery, update::QBUpdate};
+/// use quickbooks_types::Customer;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// let mut customer = Customer::query_single(
+///     "WHERE DisplayName = 'Acme Corp'",
+///     &qb_context,
+/// ).unwrap().unwrap();
+///
+/// // Change a field and send a sparse update
+/// customer.display_name = Some("Acme Corporation".to_string());
+/// let updated = customer.sparse_update(&qb_context).unwrap();
+/// println!("New sync token: {:?}", updated.sync_token);
+/// ```
+///
+/// # Return Value
+///
+/// Returns the updated entity as stored by `QuickBooks`, including its new sync token.
+/// Use the returned entity for any subsequent updates or deletes.
+///
+/// # Errors
+///
+/// - `UpdateMissingItems`: Entity missing ID or sync token
+/// - `UreqError`: Network or HTTP errors during API call
+/// - `BadRequest`: `QuickBooks` API error (e.g., validation failure, stale sync token)
+/// - `ConflictRetriesExhausted`: Stale sync token conflict persisted after retrying
+/// - `JsonError`: Serialization or response parsing errors
+pub trait QBUpdate {
+    /// Sends a full update of the item
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn update(&self, qb: &QBContext) -> APIResult<Self>
+    where
+        Self: Sized;
+
+    /// Sends a sparse update of the item, only changing the fields set on it
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn sparse_update(&self, qb: &QBContext) -> APIResult<Self>
+    where
+        Self: Sized;
+
+    /// Sends a sparse update containing only the fields that differ from `original`,
+    /// returns `original` unchanged without making a request if nothing differs
+    ///
+    /// # Errors
+    ///
+    /// If `original` has no ID and sync token or if the request itself fails
+    fn update_changes(&self, original: &Self, qb: &QBContext) -> APIResult<Self>
+    where
+        Self: Sized;
+
+    /// Sends a full update of the item, calling `merge` with the local item and the
+    /// latest version read from `QuickBooks` whenever the context's conflict policy
+    /// retries a stale sync token conflict
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn update_with_merge<F>(&self, qb: &QBContext, merge: F) -> APIResult<Self>
+    where
+        Self: Sized,
+        F: FnMut(&Self, Self) -> Self;
+
+    /// Sends a full or sparse update of the item with the given request ID, or a new
+    /// one if `None`, so `QuickBooks` applies it only once however many times it is sent,
+    /// and returns the updated item along with the request ID
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn update_with_request_id(
+        &self,
+        sparse: bool,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>>
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem> QBUpdate for T {
+    fn update(&self, qb: &QBContext) -> APIResult<Self> {
+        qb_update(
+            self,
+            false,
+            RequestId::new()?,
+            qb,
+            None::<fn(&Self, Self) -> Self>,
+        )
+        .map(WithRequestId::into_value)
+    }
+
+    fn sparse_update(&self, qb: &QBContext) -> APIResult<Self> {
+        qb_update(
+            self,
+            true,
+            RequestId::new()?,
+            qb,
+            None::<fn(&Self, Self) -> Self>,
+        )
+        .map(WithRequestId::into_value)
+    }
+
+    fn update_changes(&self, original: &Self, qb: &QBContext) -> APIResult<Self> {
+        let diff = SparseDiff::new(original, self)?;
+        if diff.is_empty() {
+            return Ok(original.clone());
+        }
+        diff.send(qb)
+    }
+
+    fn update_with_merge<F>(&self, qb: &QBContext, merge: F) -> APIResult<Self>
+    where
+        F: FnMut(&Self, Self) -> Self,
+    {
+        qb_update(self, false, RequestId::new()?, qb, Some(merge)).map(WithRequestId::into_value)
+    }
+
+    fn update_with_request_id(
+        &self,
+        sparse: bool,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>> {
+        qb_update(
+            self,
+            sparse,
+            request_id.map_or_else(RequestId::new, Ok)?,
+            qb,
+            None::<fn(&Self, Self) -> Self>,
+        )
+    }
+}
+
+/// Updates the given item using the context given, but first
+/// checks that the item has an ID and sync token to update with.
+///
+/// On a stale sync token conflict the item is merged with the latest
+/// version using `merge`, if the context's conflict policy allows a retry.
+/// Without `merge` the conflict is returned, as resending the item with the
+/// latest sync token would overwrite the changes that caused it.
+fn qb_update<T, F>(
+    item: &T,
+    sparse: bool,
+    mut request_id: RequestId,
+    qb: &QBContext,
+    merge: Option<F>,
+) -> Result<WithRequestId<T>, APIError>
+where
+    T: QBItem,
+    F: FnMut(&T, T) -> T,
+{
+    let (Some(_), Some(id)) = (item.sync_token(), item.id()) else {
+        return Err(APIErrorInner::UpdateMissingItems.into());
+    };
+    let Some(mut merge) = merge else {
+        let value = qb_send_update(&update_body(item, sparse)?, &request_id, qb)?;
+        return Ok(WithRequestId { value, request_id });
+    };
+
+    let value = retry_on_conflict(id, qb, |latest: Option<T>| {
+        let to_send = match latest {
+            None => Cow::Borrowed(item),
+            Some(latest) => {
+                // A different request, which QuickBooks must not deduplicate
+                request_id = RequestId::new()?;
+                let sync_token = latest_sync_token(&latest)?;
+                Cow::Owned(with_sync_token(&merge(item, latest), &sync_token)?)
+            }
+        };
+
+        qb_send_update(&update_body(&*to_send, sparse)?, &request_id, qb)
+    })?;
+    Ok(WithRequestId { value, request_id })
+}
+
+/// Serializes an item for a full or sparse update
+fn update_body<T: QBItem>(item: &T, sparse: bool) -> APIResult<Value> {
+    let mut body = serde_json::to_value(item)?;
+    if let Value::Object(map) = &mut body {
+        map.insert("sparse".into(), Value::Bool(sparse));
+    }
+    Ok(body)
+}
+
+/// Posts an already serialized update body for an item of type `T`
+/// and returns the entity that `QuickBooks` sends back.
+pub(crate) fn qb_send_update<T: QBItem>(
+    body: &Value,
+    request_id: &RequestId,
+    qb: &QBContext,
+) -> Result<T, APIError> {
+    let response: QBResponse<T> = qb_request(
+        qb,
+        Method::POST,
+        &format!("company/{}/{}", qb.company_id, T::qb_id()),
+        Some(body),
+        None,
+        Some([(REQUEST_ID_PARAM, request_id.as_str())]),
+    )?;
+
+    #[cfg(feature = "logging")]
+    log::info!(
+        "Successfully updated {} with ID of '{:?}', new SyncToken '{:?}'",
+        T::name(),
+        response.object.id(),
+        response.object.sync_token()
+    );
+
+    Ok(response.object)
+}
+
+/// Fields that are never sent as part of a diff, either because they identify the
+/// entity (and are always sent) or because they are managed by `QuickBooks`.
+const DIFF_IGNORED_FIELDS: [&str; 4] = ["Id", "SyncToken", "MetaData", "sparse"];
+
+/// The set of top-level fields that changed between two versions of an entity.
+///
+/// A `SparseDiff` is computed by serializing the original entity (as read from
+/// `QuickBooks`) and the modified entity with serde and comparing their top-level
+/// fields. Sending it issues a sparse update containing only the changed fields
+/// plus the `Id` and `SyncToken` of the original, so fields edited by someone else
+/// in the meantime are left untouched.
+///
+/// Fields present on the original but missing from the modified entity are sent
+/// as `null`. Nested objects (addresses, line items, references) are compared and
+/// sent as a whole.
+///
+/// If the context has a conflict policy set, a stale sync token conflict resends
+/// the same changed fields with the latest sync token.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::{read::qb_get_single, update::SparseDiff};
+/// use quickbooks_types::Customer;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// let original: Customer = qb_get_single("123", &qb_context).unwrap();
+/// let mut modified = original.clone();
+/// modified.display_name = Some("Acme Corporation".to_string());
+///
+/// let diff = SparseDiff::new(&original, &modified).unwrap();
+/// // Inspect what would be sent without sending it
+/// println!("Changed fields: {:?}", diff.changed_fields().collect::<Vec<_>>());
+/// println!("Payload: {}", diff.payload());
+///
+/// let updated = diff.send(&qb_context).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SparseDiff<T> {
+    id: String,
+    sync_token: String,
+    changed: Map<String, Value>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: QBItem> SparseDiff<T> {
+    /// Computes the changed top-level fields between `original` and `modified`
+    ///
+    /// # Errors
+    ///
+    /// Returns `UpdateMissingItems` if `original` has no ID and sync token,
+    /// or `JsonError` if either entity cannot be serialized
+    pub fn new(original: &T, modified: &T) -> APIResult<Self> {
+        let (Some(id), Some(sync_token)) = (original.id(), original.sync_token()) else {
+            return Err(APIErrorInner::UpdateMissingItems.into());
+        };
+
+        let original_fields = to_fields(original)?;
+        let modified_fields = to_fields(modified)?;
+
+        let mut changed: Map<String, Value> = modified_fields
+            .iter()
+            .filter(|(key, value)| original_fields.get(key.as_str()) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        for key in original_fields.keys() {
+            if !modified_fields.contains_key(key) {
+                changed.insert(key.clone(), Value::Null);
+            }
+        }
+        changed.retain(|key, _| !DIFF_IGNORED_FIELDS.contains(&key.as_str()));
+
+        Ok(Self {
+            id: id.clone(),
+            sync_token: sync_token.clone(),
+            changed,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns `true` if no fields changed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+    }
+
+    /// Returns the names of the changed fields, as `QuickBooks` spells them
+    pub fn changed_fields(&self) -> impl Iterator<Item = &str> {
+        self.changed.keys().map(String::as_str)
+    }
+
+    /// Returns the JSON body that would be sent for this update
+    #[must_use]
+    pub fn payload(&self) -> Value {
+        let mut body = self.changed.clone();
+        body.insert("Id".into(), Value::String(self.id.clone()));
+        body.insert("SyncToken".into(), Value::String(self.sync_token.clone()));
+        body.insert("sparse".into(), Value::Bool(true));
+        Value::Object(body)
+    }
+
+    /// Sends the sparse update and returns the updated entity
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or `QuickBooks` rejects the update
+    pub fn send(&self, qb: &QBContext) -> APIResult<T> {
+        retry_on_conflict(&self.id, qb, |latest: Option<T>| {
+            let mut payload = self.payload();
+            if let (Some(latest), Value::Object(map)) = (latest, &mut payload) {
+                map.insert(
+                    "SyncToken".into(),
+                    Value::String(latest_sync_token(&latest)?),
+                );
+            }
+            qb_send_update(&payload, &RequestId::new()?, qb)
+        })
+    }
+}
+
+/// Serializes an entity into its map of top-level fields
+fn to_fields<T: QBItem>(item: &T) -> APIResult<Map<String, Value>> {
+    match serde_json::to_value(item)? {
+        Value::Object(map) => Ok(map),
+        _ => Err(<serde_json::Error as serde::ser::Error>::custom(format!(
+            "{} did not serialize to a JSON object",
+            T::name()
+        ))
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickbooks_types::Customer;
+    use serde_json::json;
+
+    use super::SparseDiff;
+
+    fn customer() -> Customer {
+        Customer {
+            id: Some("42".into()),
+            sync_token: Some("3".into()),
+            display_name: Some("Acme Corp".into()),
+            given_name: Some("Jane".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_only_changed_fields() {
+        let original = customer();
+        let mut modified = original.clone();
+        modified.display_name = Some("Acme Corporation".into());
+        modified.given_name = None;
+
+        let diff = SparseDiff::new(&original, &modified).unwrap();
+        let mut fields = diff.changed_fields().collect::<Vec<_>>();
+        fields.sort_unstable();
+        assert_eq!(fields, ["DisplayName", "GivenName"]);
+
+        let payload = diff.payload();
+        assert_eq!(payload["Id"], json!("42"));
+        assert_eq!(payload["SyncToken"], json!("3"));
+        assert_eq!(payload["sparse"], json!(true));
+        assert_eq!(payload["DisplayName"], json!("Acme Corporation"));
+        assert!(payload["GivenName"].is_null());
+    }
+
+    #[test]
+    fn test_diff_unchanged_is_empty() {
+        let original = customer();
+        let mut modified = original.clone();
+        modified.sync_token = Some("4".into());
+        let diff = SparseDiff::new(&original, &modified).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.payload()["SyncToken"], json!("3"));
+    }
+
+    #[test]
+    fn test_diff_requires_id_and_sync_token() {
+        let original = Customer::default();
+        assert!(SparseDiff::new(&original, &customer()).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Functions for voiding `QuickBooks` transactions via the API.
+//!
+//! Voiding keeps a transaction on the books but zeroes out its amounts, which is
+//! what `QuickBooks` expects for invoices, payments and receipts that were sent in
+//! error. Only some transaction types can be voided; see [`QBVoidable`].
+
+use quickbooks_types::{BillPayment, Invoice, Payment, QBItem, SalesReceipt};
+use serde::Serialize;
+use ureq::http::Method;
+
+use crate::{
+    error::{APIError, APIErrorInner},
+    functions::{
+        conflict::retry_on_conflict,
+        delete::{QBToDelete, QBToDeleteTrait},
+        qb_request,
+        request_id::{RequestId, WithRequestId, REQUEST_ID_PARAM},
+        QBResponse,
+    },
+    APIResult, QBContext,
+};
+
+/// How `QuickBooks` expects a void to be requested for an entity type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoidOperation {
+    /// `POST /{entity}?operation=void` with the ID and sync token
+    Void,
+    /// `POST /{entity}?operation=update&include=void` with the ID and sync token
+    /// sent as a sparse update
+    UpdateIncludeVoid,
+}
+
+impl VoidOperation {
+    fn query(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            VoidOperation::Void => &[("operation", "void")],
+            VoidOperation::UpdateIncludeVoid => &[("operation", "update"), ("include", "void")],
+        }
+    }
+}
+
+/// Marker trait for `QuickBooks` transaction types that support voiding.
+///
+/// Implemented for [`Invoice`], [`SalesReceipt`], [`Payment`] and [`BillPayment`].
+pub trait QBVoidable {
+    /// The request shape `QuickBooks` uses to void this entity type
+    const VOID_OPERATION: VoidOperation;
+}
+
+impl QBVoidable for Invoice {
+    const VOID_OPERATION: VoidOperation = VoidOperation::Void;
+}
+
+impl QBVoidable for SalesReceipt {
+    const VOID_OPERATION: VoidOperation = VoidOperation::Void;
+}
+
+impl QBVoidable for Payment {
+    const VOID_OPERATION: VoidOperation = VoidOperation::UpdateIncludeVoid;
+}
+
+impl QBVoidable for BillPayment {
+    const VOID_OPERATION: VoidOperation = VoidOperation::UpdateIncludeVoid;
+}
+
+/// Trait for voiding `QuickBooks` transactions via the API.
+///
+/// This trait provides the `void` method for voiding transactions in `QuickBooks`.
+/// It validates that entities have the required ID and sync token before attempting the void.
+///
+/// # Automatic Implementation
+///
+/// This trait is automatically implemented for all types that implement both
+/// [`QBItem`] and [`QBVoidable`]. You don't need to implement it manually.
+///
+/// # Requirements
+///
+/// Before voiding, entities must have:
+/// - Valid ID (entity exists in `QuickBooks`)
+/// - Current sync token (for optimistic concurrency control)
+///
+/// These are automatically present when entities are read from `QuickBooks`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::functions::query::QBQuery;
+/// use quick_oxibooks::functions::void::QBVoid;
+/// use quickbooks_types::Invoice;
+///
+/// let qb_context = QBContext::new(
+///     Environment::SANDBOX,
+///     "company_id".to_string(),
+///     "access_token".to_string(),
+/// ).unwrap();
+///
+/// let invoice = Invoice::query_single(
+///     "WHERE DocNumber = 'INV-0001'",
+///     &qb_context,
+/// ).unwrap().unwrap();
+///
+/// let voided = invoice.void(&qb_context).unwrap();
+/// println!("Voided invoice, new sync token: {:?}", voided.sync_token);
+/// ```
+///
+/// # Return Value
+///
+/// Returns the voided entity as stored by `QuickBooks`, with its amounts zeroed
+/// and a new sync token.
+///
+/// # Errors
+///
+/// - `VoidMissingItems`: Entity missing ID or sync token
+/// - `UreqError`: Network or HTTP errors during API call
+/// - `BadRequest`: `QuickBooks` API error (e.g., transaction already voided, sync conflict)
+/// - `ConflictRetriesExhausted`: Stale sync token conflict persisted after retrying
+/// - `JsonError`: Response parsing errors
+pub trait QBVoid {
+    /// Voids the item
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn void(&self, qb: &QBContext) -> APIResult<Self>
+    where
+        Self: Sized;
+
+    /// Voids the item with the given request ID, or a new one if `None`,
+    /// so `QuickBooks` voids it only once however many times it is sent,
+    /// and returns the voided item along with the request ID
+    ///
+    /// # Errors
+    ///
+    /// If the item has no ID and sync token available or if the request itself fails
+    fn void_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>>
+    where
+        Self: Sized;
+}
+
+impl<T: QBItem + QBVoidable> QBVoid for T {
+    fn void(&self, qb: &QBContext) -> APIResult<Self> {
+        qb_void(self, RequestId::new()?, qb).map(WithRequestId::into_value)
+    }
+
+    fn void_with_request_id(
+        &self,
+        request_id: Option<RequestId>,
+        qb: &QBContext,
+    ) -> APIResult<WithRequestId<Self>> {
+        qb_void(self, request_id.map_or_else(RequestId::new, Ok)?, qb)
+    }
+}
+
+/// Voids the given item using the ID
+/// returns an error if the item has no ID and sync token
+/// available or if the request itself fails
+fn qb_void<T: QBItem + QBVoidable>(
+    item: &T,
+    mut request_id: RequestId,
+    qb: &QBContext,
+) -> Result<WithRequestId<T>, APIError> {
+    let (Some(_), Some(id)) = (item.sync_token(), item.id()) else {
+        return Err(APIErrorInner::VoidMissingItems.into());
+    };
+
+    let value = retry_on_conflict(id, qb, |latest: Option<T>| {
+        if latest.is_some() {
+            // A different request, which QuickBooks must not deduplicate
+            request_id = RequestId::new()?;
+        }
+        let void_object = QBToVoid {
+            target: latest.as_ref().unwrap_or(item).to_delete(),
+            sparse: T::VOID_OPERATION == VoidOperation::UpdateIncludeVoid,
+        };
+
+        let response: QBResponse<T> = qb_request(
+            qb,
+            Method::POST,
+            &format!("company/{}/{}", qb.company_id, T::qb_id()),
+            Some(&void_object),
+            None,
+            Some(
+                T::VOID_OPERATION
+                    .query()
+                    .iter()
+                    .copied()
+                    .chain([(REQUEST_ID_PARAM, request_id.as_str())]),
+            ),
+        )?;
+
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Successfully voided {} with ID of {}",
+            T::name(),
+            void_object.target.id
+        );
+
+        Ok(response.object)
+    })?;
+    Ok(WithRequestId { value, request_id })
+}
+
+#[derive(Serialize, Debug)]
+struct QBToVoid<'a> {
+    #[serde(flatten)]
+    target: QBToDelete<'a>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    sparse: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use quickbooks_types::{BillPayment, Invoice, Payment};
+    use serde_json::json;
+    use ureq::http::Method;
+
+    use super::QBVoid;
+    use crate::functions::{
+        conflict::ConflictPolicy,
+        request_id::RequestId,
+        stub::{stale_object_fault, StubTransport},
+    };
+
+    #[test]
+    fn test_void_operation() {
+        let transport = StubTransport::new([(
+            200,
+            json!({ "Invoice": { "Id": "42", "SyncToken": "4", "TotalAmt": 0.0 } }),
+        )]);
+        let qb = transport.context();
+        let invoice = Invoice {
+            id: Some("42".into()),
+            sync_token: Some("3".into()),
+            total_amt: Some(100.0),
+            ..Default::default()
+        };
+
+        let voided = invoice
+            .void_with_request_id(Some(RequestId::from("void-42")), &qb)
+            .unwrap();
+        assert_eq!(voided.request_id.as_str(), "void-42");
+        assert_eq!(voided.value.sync_token.as_deref(), Some("4"));
+
+        let [sent] = &transport.take_sent()[..] else {
+            panic!("Expected a single request");
+        };
+        assert_eq!(sent.method, Method::POST);
+        assert_eq!(sent.path, "/v3/company/123/invoice");
+        assert_eq!(sent.param("operation"), Some("void"));
+        assert_eq!(sent.param("include"), None);
+        assert_eq!(sent.param("requestid"), Some("void-42"));
+        assert_eq!(sent.body, json!({ "Id": "42", "SyncToken": "3" }));
+    }
+
+    #[test]
+    fn test_void_update_include_void() {
+        let transport = StubTransport::new([
+            (200, json!({ "Payment": { "Id": "7", "SyncToken": "1" } })),
+            (
+                200,
+                json!({ "BillPayment": { "Id": "8", "SyncToken": "1" } }),
+            ),
+        ]);
+        let qb = transport.context();
+
+        let payment = Payment {
+            id: Some("7".into()),
+            sync_token: Some("0".into()),
+            ..Default::default()
+        };
+        assert_eq!(payment.void(&qb).unwrap().sync_token.as_deref(), Some("1"));
+        let bill_payment = BillPayment {
+            id: Some("8".into()),
+            sync_token: Some("0".into()),
+            ..Default::default()
+        };
+        let voided = bill_payment.void(&qb).unwrap();
+        assert_eq!(voided.id.as_deref(), Some("8"));
+
+        let sent = transport.take_sent();
+        let paths: Vec<_> = sent.iter().map(|sent| sent.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["/v3/company/123/payment", "/v3/company/123/billpayment"]
+        );
+        for (sent, id) in sent.iter().zip(["7", "8"]) {
+            assert_eq!(sent.param("operation"), Some("update"));
+            assert_eq!(sent.param("include"), Some("void"));
+            assert!(sent.param("requestid").is_some());
+            assert_eq!(
+                sent.body,
+                json!({ "Id": id, "SyncToken": "0", "sparse": true })
+            );
+        }
+    }
+
+    #[test]
+    fn test_void_retries_on_conflict() {
+        let transport = StubTransport::new([
+            stale_object_fault(),
+            (200, json!({ "Invoice": { "Id": "42", "SyncToken": "5" } })),
+            (200, json!({ "Invoice": { "Id": "42", "SyncToken": "6" } })),
+        ]);
+        let qb = transport
+            .context()
+            .with_conflict_policy(ConflictPolicy::new(1));
+        let invoice = Invoice {
+            id: Some("42".into()),
+            sync_token: Some("3".into()),
+            ..Default::default()
+        };
+
+        let voided = invoice
+            .void_with_request_id(Some(RequestId::from("void-42")), &qb)
+            .unwrap();
+        assert_eq!(voided.value.sync_token.as_deref(), Some("6"));
+        // The retry is a different request, with the latest sync token
+        assert_ne!(voided.request_id.as_str(), "void-42");
+
+        let sent = transport.take_sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[1].method, Method::GET);
+        assert_eq!(sent[1].path, "/v3/company/123/invoice/42");
+        assert_eq!(sent[2].body["SyncToken"], json!("5"));
+        assert_eq!(sent[2].param("requestid"), Some(voided.request_id.as_str()));
+    }
+}
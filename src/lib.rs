@@ -21,27 +21,24 @@
 //! use quick_oxibooks::{QBContext, Environment};
 //! use quickbooks_types::{Customer, Invoice};
 //! use quick_oxibooks::functions::{create::QBCreate, query::QBQuery, read::QBRead};
-//! use ureq::Agent;
 //!
 //! // Create a QuickBooks context
-//! let client = Agent::new_with_defaults();
 //! let qb_context = QBContext::new(
 //!     Environment::SANDBOX,
 //!     "your_company_id".to_string(),
 //!     "your_access_token".to_string(),
-//!     &client
 //! ).unwrap();
 //!
 //! // Create a new customer
 //! let mut customer = Customer::default();
 //! customer.display_name = Some("John Doe".to_string());
-//! let created_customer = customer.create(&qb_context, &client).unwrap();
+//! let created_customer = customer.create(&qb_context).unwrap();
 //!
 //! // Query for invoices
-//! let invoices = Invoice::query("WHERE TotalAmt > '100.00'", Some(10), &qb_context, &client).unwrap();
+//! let invoices = Invoice::query("WHERE TotalAmt > '100.00'", Some(10), &qb_context).unwrap();
 //!
 //! // Read a specific invoice by ID
-//! let invoice = Invoice::query_single("WHERE Id = '123'", &qb_context, &client).unwrap();
+//! let invoice = Invoice::query_single("WHERE Id = '123'", &qb_context).unwrap();
 //! ```
 //! ## Features
 //!
@@ -50,16 +47,23 @@
 //! - `macros`: Enables convenient query-building macros
 //! - `polars`: Enables integration with the `polars` `DataFrame` library for data analysis
 //! - `logging`: Enables detailed logging of API requests and responses
+//! - `webhooks`: Enables webhook notification parsing and signature verification
+//! - `oauth`: Enables the OAuth 2.0 authorization code flow for connecting companies
+//! - `async`: Enables the async API on `reqwest` in [`asynchronous`]
+//! - `testing`: Enables an in-process mock `QuickBooks` server in [`testing`]
 //!
 //! For more detailed usage examples, refer to the documentation for each module and type.
 #![warn(clippy::pedantic)]
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
 pub mod batch;
 pub mod client;
 pub use client::QBContext;
+use client::Transport;
 use error::APIError;
 use serde::{Deserialize, Serialize};
-use ureq::Agent;
+use ureq::http::Request;
 pub mod error;
 
 pub mod types {
@@ -68,7 +72,12 @@ pub mod types {
 }
 
 pub mod functions;
-pub(crate) mod limiter;
+pub mod limiter;
+pub mod mirror;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 
 use crate::error::APIErrorInner;
 #[cfg(feature = "attachments")]
@@ -87,11 +96,9 @@ pub mod macros;
 ///
 /// ```no_run
 /// use quick_oxibooks::{APIResult, QBContext, Environment};
-/// use ureq::Agent;
 ///
 /// fn get_context() -> APIResult<QBContext> {
-///     let client = Agent::new_with_defaults();
-///     QBContext::new_from_env(Environment::SANDBOX, &client)
+///     QBContext::new_from_env(Environment::SANDBOX)
 /// }
 /// ```
 pub type APIResult<T> = Result<T, APIError>;
@@ -202,6 +209,82 @@ impl Environment {
             Environment::SANDBOX => "https://sandbox-quickbooks.api.intuit.com/v3/",
         }
     }
+
+    /// Returns the [`Endpoints`] of the environment.
+    #[inline]
+    #[must_use]
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints {
+            api_url: self.endpoint_url().to_string(),
+            discovery_url: self.discovery_url().to_string(),
+            user_info_url: self.user_info_url().to_string(),
+            minor_version: DEFAULT_MINOR_VERSION,
+        }
+    }
+}
+
+/// The `minorversion` of the `QuickBooks` API requests are sent with by default
+pub const DEFAULT_MINOR_VERSION: u32 = 75;
+
+/// The URLs a [`QBContext`] sends its requests to, and the API minor version it uses.
+///
+/// Contexts use the endpoints of their [`Environment`] unless they are created with
+/// other ones through [`QBContextBuilder`](client::QBContextBuilder), e.g. to run
+/// against a local stub server instead of `QuickBooks`.
+///
+/// # Examples
+///
+/// ```rust
+/// use quick_oxibooks::{Endpoints, Environment, DEFAULT_MINOR_VERSION};
+///
+/// let endpoints = Endpoints::from_base_url("http://localhost:8080");
+/// assert_eq!(endpoints.api_url, "http://localhost:8080/v3/");
+/// assert_eq!(endpoints.minor_version, DEFAULT_MINOR_VERSION);
+///
+/// let sandbox = Environment::SANDBOX.endpoints();
+/// assert_eq!(sandbox.api_url, Environment::SANDBOX.endpoint_url());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoints {
+    /// Base URL of the accounting API, ending with `/v3/`
+    pub api_url: String,
+    /// URL the OAuth 2.0 discovery document is fetched from
+    pub discovery_url: String,
+    /// URL of the `OpenID` Connect userinfo endpoint
+    pub user_info_url: String,
+    /// The `minorversion` query parameter sent with API requests
+    pub minor_version: u32,
+}
+
+impl Endpoints {
+    /// Returns the endpoints of a server laid out like `QuickBooks` under `base_url`,
+    /// e.g. `http://localhost:8080`.
+    ///
+    /// The API is expected under `/v3/`, the discovery document at
+    /// `/.well-known/openid_configuration` and the userinfo endpoint at
+    /// `/v1/openid_connect/userinfo`.
+    #[must_use]
+    pub fn from_base_url(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        Self {
+            api_url: format!("{base_url}/v3/"),
+            discovery_url: format!("{base_url}/.well-known/openid_configuration"),
+            user_info_url: format!("{base_url}/v1/openid_connect/userinfo"),
+            minor_version: DEFAULT_MINOR_VERSION,
+        }
+    }
+}
+
+impl From<Environment> for Endpoints {
+    fn from(environment: Environment) -> Self {
+        environment.endpoints()
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Environment::default().endpoints()
+    }
 }
 
 /// OAuth 2.0 discovery document for `QuickBooks` API.
@@ -226,8 +309,7 @@ impl Environment {
 /// use quick_oxibooks::{DiscoveryDoc, Environment};
 /// use ureq::Agent;
 ///
-/// let client = Agent::new_with_defaults();
-/// let discovery = DiscoveryDoc::get(Environment::SANDBOX, &client).unwrap();
+/// let discovery = DiscoveryDoc::get(Environment::SANDBOX, &Agent::new_with_defaults()).unwrap();
 /// println!("Token endpoint: {}", discovery.token_endpoint);
 /// ```
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -256,7 +338,7 @@ impl DiscoveryDoc {
     /// # Parameters
     ///
     /// - `environment`: The `QuickBooks` environment (sandbox or production)
-    /// - `client`: HTTP client for making the request
+    /// - `transport`: Transport to send the request through
     ///
     /// # Returns
     ///
@@ -274,15 +356,63 @@ impl DiscoveryDoc {
     /// use quick_oxibooks::{DiscoveryDoc, Environment};
     /// use ureq::Agent;
     ///
-    /// let client = Agent::new_with_defaults();
-    /// let discovery = DiscoveryDoc::get(Environment::SANDBOX, &client).unwrap();
+    /// let discovery = DiscoveryDoc::get(Environment::SANDBOX, &Agent::new_with_defaults()).unwrap();
     /// ```
-    pub fn get(environment: Environment, client: &Agent) -> APIResult<Self> {
-        let url = environment.discovery_url();
-        let request = client.get(url).call()?;
-        if !request.status().is_success() {
-            return Err(APIErrorInner::BadRequest(request.into_body().read_json()?).into());
+    pub fn get(environment: Environment, transport: &dyn Transport) -> APIResult<Self> {
+        Self::get_from_url(environment.discovery_url(), transport)
+    }
+
+    /// Fetches the OAuth 2.0 discovery document from the given URL.
+    ///
+    /// See [`DiscoveryDoc::get`], used for contexts with custom [`Endpoints`].
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if the response format is invalid
+    /// - HTTP errors if the discovery endpoint returns an error response
+    pub fn get_from_url(url: &str, transport: &dyn Transport) -> APIResult<Self> {
+        let request = Request::get(url).body(Vec::new())?;
+        Self::from_response(&transport.send(request)?)
+    }
+
+    /// Parses the response of the discovery endpoint
+    pub(crate) fn from_response(response: &ureq::http::Response<Vec<u8>>) -> APIResult<Self> {
+        if !response.status().is_success() {
+            return Err(APIErrorInner::BadRequest(serde_json::from_slice(response.body())?).into());
+        }
+        Ok(serde_json::from_slice(response.body())?)
+    }
+
+    /// Fetches the OAuth 2.0 discovery document from `QuickBooks` through the async client.
+    ///
+    /// See [`DiscoveryDoc::get`].
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if the response format is invalid
+    /// - HTTP errors if the discovery endpoint returns an error response
+    #[cfg(feature = "async")]
+    pub async fn get_async(environment: Environment, client: &reqwest::Client) -> APIResult<Self> {
+        Self::get_from_url_async(environment.discovery_url(), client).await
+    }
+
+    /// Fetches the OAuth 2.0 discovery document from the given URL through the async client.
+    ///
+    /// See [`DiscoveryDoc::get_from_url`].
+    ///
+    /// # Errors
+    ///
+    /// - Network errors when fetching the discovery document
+    /// - JSON parsing errors if the response format is invalid
+    /// - HTTP errors if the discovery endpoint returns an error response
+    #[cfg(feature = "async")]
+    pub async fn get_from_url_async(url: &str, client: &reqwest::Client) -> APIResult<Self> {
+        let response = client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(APIErrorInner::BadRequest(response.json().await?).into());
         }
-        Ok(request.into_body().read_json()?)
+        Ok(response.json().await?)
     }
 }
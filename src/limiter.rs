@@ -1,103 +1,814 @@
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+//! Client side rate and concurrency limiting.
+//!
+//! `QuickBooks` limits every company to 500 requests per minute, 40 batch requests
+//! per minute and 10 requests in flight at once. Every [`QBContext`](crate::QBContext)
+//! enforces these limits for the requests sent through it, blocking until a request
+//! can be sent within them. The limits are configured with a [`LimitConfig`], see
+//! [`QBContext::set_realm_limits`](crate::QBContext::set_realm_limits).
+//!
+//! The limits apply to a company, not to a context: contexts for the same company
+//! share one budget through a [`LimiterRegistry`]. By default that is the registry
+//! returned by [`LimiterRegistry::global`], which keeps the request counts in memory.
+//! To share the budget with other processes on the same host, give every context a
+//! registry backed by a [`FileRateLimitBackend`] with
+//! [`QBContext::with_limiter_registry`](crate::QBContext::with_limiter_registry).
+//!
+//! [`QBContext::limit_status`](crate::QBContext::limit_status) reports how much of the
+//! budget is left, and [`QBContext::with_limit_timeout`](crate::QBContext::with_limit_timeout)
+//! makes requests fail with [`ThrottleLimitReached`](crate::error::APIErrorInner::ThrottleLimitReached)
+//! instead of waiting longer than a given time. Work sent outside the context can
+//! take from the same budget with [`QBContext::try_acquire`](crate::QBContext::try_acquire)
+//! and [`QBContext::acquire_timeout`](crate::QBContext::acquire_timeout).
 
-/// A simple rate limiter that allows a maximum number of requests
-/// within a specified duration.
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, OnceLock, PoisonError, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use fs4::fs_std::FileExt;
+
+use crate::{error::APIErrorInner, APIResult};
+
+/// The limits a [`QBContext`](crate::QBContext) keeps its requests within.
+///
+/// The defaults are the limits `QuickBooks` enforces for every company.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::limiter::LimitConfig;
+///
+/// // Leave room for another application connected to the same company
+/// let context = QBContext::new(
+///     Environment::PRODUCTION,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+/// ).unwrap();
+/// context.set_realm_limits(LimitConfig {
+///     requests_per_window: 300,
+///     max_concurrent: 5,
+///     ..LimitConfig::default()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitConfig {
+    /// Maximum number of regular requests within any `window`
+    pub requests_per_window: usize,
+    /// Maximum number of batch requests within any `window`
+    pub batches_per_window: usize,
+    /// The sliding window the request counts apply to
+    pub window: Duration,
+    /// Maximum number of requests in flight at once, batch requests included
+    pub max_concurrent: usize,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_window: 500,
+            batches_per_window: 40,
+            window: Duration::from_secs(60),
+            max_concurrent: 10,
+        }
+    }
+}
+
+/// Storage for the request counts rate limiting is based on.
+///
+/// Every key is a sliding window of the requests recorded under it. A backend
+/// shared between processes has to make [`RateLimitBackend::try_acquire`] atomic
+/// across them, like [`FileRateLimitBackend`] does with a lock file.
+/// Requests through the async client call the backend on Tokio's blocking thread
+/// pool, so its methods may block.
+///
+/// # Errors
 ///
-/// This rate limiter is designed to be used in a multi-threaded environment
-/// and ensures that no more than `max_requests` are allowed within the
-/// specified `duration`.
-#[derive(Clone, Debug)]
-pub(crate) struct RateLimiter {
-    inner: Arc<Mutex<InnerRateLimiter>>,
-    duration: Duration,
+/// Every method returns an error if the underlying storage fails.
+pub trait RateLimitBackend: Send + Sync {
+    /// Records a request under `key` if fewer than `max_requests` requests were
+    /// recorded under it within the last `window`
+    ///
+    /// Returns `None` if the request was recorded, or how long until it can be
+    /// otherwise, in which case nothing is recorded.
+    ///
+    /// # Errors
+    ///
+    /// If the requests can't be read or recorded
+    fn try_acquire(
+        &self,
+        key: &str,
+        max_requests: usize,
+        window: Duration,
+    ) -> APIResult<Option<Duration>>;
+
+    /// Returns how long ago each request recorded under `key` within the last
+    /// `window` was made, oldest first
+    ///
+    /// # Errors
+    ///
+    /// If the requests can't be read
+    fn requests_within(&self, key: &str, window: Duration) -> APIResult<Vec<Duration>>;
+}
+
+/// Forgets the requests in `sent` that were made `window` or longer before `now`.
+/// Times are offsets from any fixed point.
+fn prune(sent: &mut VecDeque<Duration>, now: Duration, window: Duration) {
+    while sent
+        .front()
+        .is_some_and(|t| now.saturating_sub(*t) >= window)
+    {
+        sent.pop_front();
+    }
+}
+
+/// Records a request made at `now` in the sliding window `sent`, see
+/// [`RateLimitBackend::try_acquire`]
+fn admit(
+    sent: &mut VecDeque<Duration>,
+    now: Duration,
     max_requests: usize,
+    window: Duration,
+) -> Option<Duration> {
+    prune(sent, now, window);
+    let max_requests = max_requests.max(1);
+    if sent.len() < max_requests {
+        sent.push_back(now);
+        return None;
+    }
+    // The limit may have been lowered, so more than the oldest one may have to leave
+    let blocking = sent[sent.len() - max_requests];
+    Some(window.saturating_sub(now.saturating_sub(blocking)))
 }
 
+/// [`RateLimitBackend`] that keeps the request counts in memory, for sharing
+/// between contexts in one process.
 #[derive(Debug)]
-struct InnerRateLimiter {
-    requests: usize,
-    window_start: Instant,
-}
-
-impl RateLimiter {
-    pub fn new(max_requests: usize, duration: Duration) -> Self {
-        let inner = Arc::new(Mutex::new(InnerRateLimiter {
-            requests: 0,
-            window_start: Instant::now(),
-        }));
-        RateLimiter {
-            inner,
-            duration,
+pub struct MemoryRateLimitBackend {
+    start: Instant,
+    sent: Mutex<HashMap<String, VecDeque<Duration>>>,
+}
+
+impl MemoryRateLimitBackend {
+    /// Creates a backend without any requests recorded
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for MemoryRateLimitBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimitBackend for MemoryRateLimitBackend {
+    fn try_acquire(
+        &self,
+        key: &str,
+        max_requests: usize,
+        window: Duration,
+    ) -> APIResult<Option<Duration>> {
+        let mut sent = self.sent.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = self.start.elapsed();
+        Ok(admit(
+            sent.entry(key.to_string()).or_default(),
+            now,
             max_requests,
+            window,
+        ))
+    }
+
+    fn requests_within(&self, key: &str, window: Duration) -> APIResult<Vec<Duration>> {
+        let mut sent = self.sent.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = self.start.elapsed();
+        let Some(sent) = sent.get_mut(key) else {
+            return Ok(Vec::new());
+        };
+        prune(sent, now, window);
+        Ok(sent.iter().map(|t| now.saturating_sub(*t)).collect())
+    }
+}
+
+/// [`RateLimitBackend`] that keeps the request counts of every key in one JSON file.
+///
+/// Every request holds an advisory lock on a `.lock` file next to it while it is
+/// counted, so several processes on the same host can share the budget of a company.
+/// Requests are timestamped with the system clock, which the processes have in common.
+#[derive(Debug, Clone)]
+pub struct FileRateLimitBackend {
+    path: PathBuf,
+}
+
+impl FileRateLimitBackend {
+    /// Creates a backend backed by the file at the given path,
+    /// the file is created on the first request
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The path of the JSON file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sibling_path(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    }
+
+    /// Opens the lock file, the lock is released when the file is dropped
+    fn lock_file(&self) -> APIResult<File> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.sibling_path(".lock"))?)
+    }
+
+    fn read_all(&self) -> APIResult<HashMap<String, VecDeque<Duration>>> {
+        match fs::read(&self.path) {
+            Ok(bytes) if bytes.is_empty() => Ok(HashMap::new()),
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// Acquires a permit from the rate limiter.
-    /// This method will block until a permit is available and returns a guard.
-    pub fn acquire(&self) -> RateLimiterGuard<'_> {
-        loop {
-            let Ok(mut guard) = self.inner.lock() else {
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    fn write_all(&self, sent: &HashMap<String, VecDeque<Duration>>) -> APIResult<()> {
+        let tmp_path = self.sibling_path(".tmp");
+        let mut file = File::create(&tmp_path)?;
+        serde_json::to_writer(&mut file, sent)?;
+        file.flush()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl RateLimitBackend for FileRateLimitBackend {
+    fn try_acquire(
+        &self,
+        key: &str,
+        max_requests: usize,
+        window: Duration,
+    ) -> APIResult<Option<Duration>> {
+        let lock = self.lock_file()?;
+        FileExt::lock_exclusive(&lock)?;
+        let mut sent = self.read_all()?;
+        let wait = admit(
+            sent.entry(key.to_string()).or_default(),
+            Self::now(),
+            max_requests,
+            window,
+        );
+        if wait.is_none() {
+            self.write_all(&sent)?;
+        }
+        Ok(wait)
+    }
+
+    fn requests_within(&self, key: &str, window: Duration) -> APIResult<Vec<Duration>> {
+        let lock = self.lock_file()?;
+        FileExt::lock_shared(&lock)?;
+        let now = Self::now();
+        Ok(self
+            .read_all()?
+            .remove(key)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| now.saturating_sub(t))
+            .filter(|age| *age < window)
+            .collect())
+    }
+}
+
+/// How much of the budget of a company is left, see
+/// [`QBContext::limit_status`](crate::QBContext::limit_status)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitStatus {
+    /// The limits in effect
+    pub limits: LimitConfig,
+    /// The budget of regular requests
+    pub requests: WindowStatus,
+    /// The budget of batch requests
+    pub batches: WindowStatus,
+    /// Number of requests in flight in this process
+    pub in_flight: usize,
+    /// Total time requests in this process have spent waiting for the limits
+    pub total_wait: Duration,
+}
+
+/// How much of a sliding window budget is left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowStatus {
+    /// Number of requests that can be sent right away
+    pub remaining: usize,
+    /// Time until the next request can be sent, zero if it can be sent right away
+    pub available_in: Duration,
+    /// Time until every request sent so far has left the window, and the full
+    /// budget is available again
+    pub resets_in: Duration,
+}
+
+impl WindowStatus {
+    /// The status of a window given how long ago each request in it was made, oldest first
+    fn new(ages: &[Duration], max_requests: usize, window: Duration) -> Self {
+        let max_requests = max_requests.max(1);
+        let expires_in = |age: &Duration| window.saturating_sub(*age);
+        Self {
+            remaining: max_requests.saturating_sub(ages.len()),
+            available_in: ages
+                .len()
+                .checked_sub(max_requests)
+                .map_or(Duration::ZERO, |blocking| expires_in(&ages[blocking])),
+            resets_in: ages.last().map_or(Duration::ZERO, expires_in),
+        }
+    }
+}
+
+/// Hands out the limiters of each company, so every context of a company shares
+/// the same budget.
+///
+/// The request counts are kept in the registry's [`RateLimitBackend`], the number of
+/// requests in flight is only limited within the process.
+///
+/// # Examples
+///
+/// ```no_run
+/// use quick_oxibooks::{QBContext, Environment};
+/// use quick_oxibooks::limiter::{FileRateLimitBackend, LimiterRegistry};
+///
+/// // Share the budget with every process using the same file
+/// let registry = LimiterRegistry::new(FileRateLimitBackend::new("/tmp/qbo_rate_limits.json"));
+///
+/// let context = QBContext::new(
+///     Environment::PRODUCTION,
+///     "company_123".to_string(),
+///     "access_token_xyz".to_string(),
+/// ).unwrap()
+/// .with_limiter_registry(&registry);
+/// ```
+pub struct LimiterRegistry {
+    backend: Arc<dyn RateLimitBackend>,
+    realms: Mutex<HashMap<String, RealmLimiter>>,
+}
+
+impl LimiterRegistry {
+    /// Creates a registry keeping the request counts in the given backend
+    pub fn new(backend: impl RateLimitBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            realms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The registry contexts use unless given another one,
+    /// which keeps the request counts in memory
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<LimiterRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(Self::default)
+    }
+
+    /// The limiter of the company, created with the default limits if it has none yet
+    pub(crate) fn realm(&self, realm_id: &str) -> RealmLimiter {
+        let mut realms = self.realms.lock().unwrap_or_else(PoisonError::into_inner);
+        realms
+            .entry(realm_id.to_string())
+            .or_insert_with(|| RealmLimiter::new(realm_id, self.backend.clone()))
+            .clone()
+    }
+}
+
+impl Default for LimiterRegistry {
+    fn default() -> Self {
+        Self::new(MemoryRateLimitBackend::new())
+    }
+}
+
+/// The rate and concurrency limits of one company, shared by all its contexts
+#[derive(Clone)]
+pub(crate) struct RealmLimiter {
+    key: String,
+    batch_key: String,
+    backend: Arc<dyn RateLimitBackend>,
+    limits: Arc<RwLock<LimitConfig>>,
+    /// Number of requests in flight, and a signal for when one finishes
+    in_flight: Arc<(Mutex<usize>, Condvar)>,
+    /// Signal for async requests waiting for a slot
+    #[cfg(feature = "async")]
+    slot_freed: Arc<tokio::sync::Notify>,
+    /// Total time spent waiting for the limits, in nanoseconds
+    waited: Arc<AtomicU64>,
+}
+
+impl RealmLimiter {
+    fn new(realm_id: &str, backend: Arc<dyn RateLimitBackend>) -> Self {
+        Self {
+            key: realm_id.to_string(),
+            batch_key: format!("{realm_id}:batch"),
+            backend,
+            limits: Arc::new(RwLock::new(LimitConfig::default())),
+            in_flight: Arc::new((Mutex::new(0), Condvar::new())),
+            #[cfg(feature = "async")]
+            slot_freed: Arc::new(tokio::sync::Notify::new()),
+            waited: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn limits(&self) -> LimitConfig {
+        *self.limits.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set_limits(&self, limits: LimitConfig) {
+        *self.limits.write().unwrap_or_else(PoisonError::into_inner) = limits;
+        // A higher concurrency limit may let waiting requests through
+        self.in_flight.1.notify_all();
+        #[cfg(feature = "async")]
+        self.slot_freed.notify_waiters();
+    }
+
+    pub fn status(&self) -> APIResult<LimitStatus> {
+        let limits = self.limits();
+        let requests = self.backend.requests_within(&self.key, limits.window)?;
+        let batches = self
+            .backend
+            .requests_within(&self.batch_key, limits.window)?;
+        Ok(LimitStatus {
+            limits,
+            requests: WindowStatus::new(&requests, limits.requests_per_window, limits.window),
+            batches: WindowStatus::new(&batches, limits.batches_per_window, limits.window),
+            in_flight: *self
+                .in_flight
+                .0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            total_wait: Duration::from_nanos(self.waited.load(Ordering::Relaxed)),
+        })
+    }
+
+    /// Acquires a slot for a request in flight, then a permit from the rate limit
+    /// of regular or batch requests, blocking until both are available.
+    /// The slot is released when the returned guard is dropped.
+    pub fn acquire(&self, batch: bool) -> APIResult<ConcurrencyGuard<'_>> {
+        self.timed(|| self.acquire_until(batch, None))
+    }
+
+    /// Like [`RealmLimiter::acquire`], but fails with `ThrottleLimitReached` when
+    /// it would have to wait longer than `timeout`. A zero timeout tries to acquire
+    /// both without waiting at all.
+    pub fn acquire_timeout(
+        &self,
+        batch: bool,
+        timeout: Duration,
+    ) -> APIResult<ConcurrencyGuard<'_>> {
+        self.timed(|| self.acquire_until(batch, Instant::now().checked_add(timeout)))
+    }
+
+    /// Like [`RealmLimiter::acquire`] or, with a timeout, [`RealmLimiter::acquire_timeout`],
+    /// but yields to the async runtime instead of blocking the thread while it waits.
+    #[cfg(feature = "async")]
+    pub async fn acquire_async(
+        &self,
+        batch: bool,
+        timeout: Option<Duration>,
+    ) -> APIResult<ConcurrencyGuard<'_>> {
+        let start = Instant::now();
+        let deadline = timeout.and_then(|timeout| start.checked_add(timeout));
+        let out = async {
+            let slot = self.acquire_slot_async(deadline).await?;
+            while let Some(wait) = self.try_permit_async(batch, deadline).await? {
+                tokio::time::sleep(wait).await;
+            }
+            Ok(slot)
+        }
+        .await;
+        self.add_waited(start.elapsed());
+        out
+    }
+
+    /// Adds the time `f` takes to the total time spent waiting
+    fn timed<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.add_waited(start.elapsed());
+        out
+    }
+
+    fn add_waited(&self, waited: Duration) {
+        let waited = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.waited.fetch_add(waited, Ordering::Relaxed);
+    }
+
+    fn acquire_until(
+        &self,
+        batch: bool,
+        deadline: Option<Instant>,
+    ) -> APIResult<ConcurrencyGuard<'_>> {
+        let slot = self.acquire_slot(deadline)?;
+        while let Some(wait) = self.try_permit(batch, deadline)? {
+            std::thread::sleep(wait);
+        }
+        Ok(slot)
+    }
+
+    /// Tries to take a permit from the rate limit of regular or batch requests,
+    /// returns how long to wait for one if there is none
+    fn try_permit(&self, batch: bool, deadline: Option<Instant>) -> APIResult<Option<Duration>> {
+        let limits = self.limits();
+        let (key, max_requests) = if batch {
+            (&self.batch_key, limits.batches_per_window)
+        } else {
+            (&self.key, limits.requests_per_window)
+        };
+        match self.backend.try_acquire(key, max_requests, limits.window)? {
+            // No point in waiting if the permit won't be available in time
+            Some(wait) if deadline.is_some_and(|deadline| Instant::now() + wait > deadline) => {
+                Err(APIErrorInner::ThrottleLimitReached {
+                    retry_after: Some(wait),
+                }
+                .into())
+            }
+            wait => Ok(wait),
+        }
+    }
+
+    /// [`RealmLimiter::try_permit`] on Tokio's blocking thread pool, as backends
+    /// like [`FileRateLimitBackend`] block the thread while they wait for a lock
+    #[cfg(feature = "async")]
+    async fn try_permit_async(
+        &self,
+        batch: bool,
+        deadline: Option<Instant>,
+    ) -> APIResult<Option<Duration>> {
+        let limiter = self.clone();
+        tokio::task::spawn_blocking(move || limiter.try_permit(batch, deadline))
+            .await
+            .map_err(std::io::Error::from)?
+    }
+
+    fn acquire_slot(&self, deadline: Option<Instant>) -> APIResult<ConcurrencyGuard<'_>> {
+        let (lock, freed) = &*self.in_flight;
+        let mut in_flight = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        while *in_flight >= self.limits().max_concurrent.max(1) {
+            let Some(deadline) = deadline else {
+                in_flight = freed
+                    .wait(in_flight)
+                    .unwrap_or_else(PoisonError::into_inner);
                 continue;
             };
-            let now = Instant::now();
-            if now.duration_since(guard.window_start) >= self.duration {
-                guard.window_start = now;
-                guard.requests = 0;
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                // When a slot frees up depends on the requests in flight
+                return Err(APIErrorInner::ThrottleLimitReached { retry_after: None }.into());
+            }
+            in_flight = freed
+                .wait_timeout(in_flight, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        *in_flight += 1;
+        Ok(ConcurrencyGuard { limiter: self })
+    }
+
+    #[cfg(feature = "async")]
+    async fn acquire_slot_async(
+        &self,
+        deadline: Option<Instant>,
+    ) -> APIResult<ConcurrencyGuard<'_>> {
+        loop {
+            let mut freed = std::pin::pin!(self.slot_freed.notified());
+            // Registered before checking, so a slot freed in between still wakes it
+            freed.as_mut().enable();
+            {
+                let mut in_flight = self
+                    .in_flight
+                    .0
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                if *in_flight < self.limits().max_concurrent.max(1) {
+                    *in_flight += 1;
+                    return Ok(ConcurrencyGuard { limiter: self });
+                }
             }
-            if guard.requests < self.max_requests {
-                guard.requests += 1;
-                let window_start = guard.window_start;
-                return RateLimiterGuard {
-                    limiter: self,
-                    window_start,
-                };
+            match deadline {
+                None => freed.await,
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), freed)
+                    .await
+                    .map_err(|_| APIErrorInner::ThrottleLimitReached { retry_after: None })?,
             }
-            let wait = self.duration - now.duration_since(guard.window_start);
-            drop(guard);
-            std::thread::sleep(wait);
         }
     }
 }
 
-pub struct RateLimiterGuard<'a> {
-    limiter: &'a RateLimiter,
-    window_start: Instant,
+/// A request slot and rate limit permit of a company, taken with
+/// [`QBContext::try_acquire`](crate::QBContext::try_acquire) or
+/// [`QBContext::acquire_timeout`](crate::QBContext::acquire_timeout).
+///
+/// The permit counts against the rate limit for its whole window, the slot is
+/// released when the guard is dropped.
+#[must_use = "the request slot is released as soon as the guard is dropped"]
+pub struct ConcurrencyGuard<'a> {
+    limiter: &'a RealmLimiter,
 }
 
-impl Drop for RateLimiterGuard<'_> {
+impl Drop for ConcurrencyGuard<'_> {
     fn drop(&mut self) {
-        let Ok(mut guard) = self.limiter.inner.lock() else {
-            return;
-        };
-        // Only decrement if still in the same window
-        if guard.window_start + self.limiter.duration <= self.window_start && guard.requests > 0 {
-            guard.requests -= 1;
-        }
+        let (lock, freed) = &*self.limiter.in_flight;
+        let mut in_flight = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        *in_flight -= 1;
+        freed.notify_one();
+        #[cfg(feature = "async")]
+        self.limiter.slot_freed.notify_waiters();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::thread;
 
     #[test]
     fn test_rate_limiter() {
-        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+        let registry = LimiterRegistry::default();
+        registry.realm("123").set_limits(LimitConfig {
+            requests_per_window: 5,
+            window: Duration::from_millis(200),
+            ..LimitConfig::default()
+        });
+        let start = Instant::now();
         let mut handles = vec![];
 
         for _ in 0..10 {
-            let limiter_clone = limiter.clone();
-            let handle = thread::spawn(move || {
-                let guard = limiter_clone.acquire();
-                assert!(guard.limiter.max_requests > 0);
-            });
+            // Every thread gets its own handle on the shared limiter
+            let limiter = registry.realm("123");
+            let handle = thread::spawn(move || drop(limiter.acquire(false).unwrap()));
             handles.push(handle);
         }
 
         for handle in handles {
             handle.join().unwrap();
         }
+        // The second five have to wait for the first five to leave the window
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_concurrency_limiter() {
+        let registry = LimiterRegistry::default();
+        let limiter = registry.realm("123");
+        limiter.set_limits(LimitConfig {
+            max_concurrent: 3,
+            ..LimitConfig::default()
+        });
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_seen = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let in_flight = in_flight.clone();
+                let max_seen = max_seen.clone();
+                thread::spawn(move || {
+                    let _guard = limiter.acquire(false).unwrap();
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_seen.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(max_seen.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_file_rate_limit_backend() {
+        let backend = FileRateLimitBackend::new(std::env::temp_dir().join(format!(
+            "quick_oxibooks_rate_limits_test_{}.json",
+            std::process::id()
+        )));
+        // A second handle on the file stands in for another process
+        let other = backend.clone();
+        let window = Duration::from_secs(60);
+
+        assert!(backend.try_acquire("123", 2, window).unwrap().is_none());
+        assert!(other.try_acquire("123", 2, window).unwrap().is_none());
+        let wait = backend.try_acquire("123", 2, window).unwrap().unwrap();
+        assert!(wait > Duration::from_secs(59) && wait <= window);
+        assert!(other.try_acquire("456", 2, window).unwrap().is_none());
+
+        let ages = other.requests_within("123", window).unwrap();
+        assert_eq!(ages.len(), 2);
+        assert!(ages[0] >= ages[1]);
+
+        fs::remove_file(backend.path()).unwrap();
+        fs::remove_file(backend.sibling_path(".lock")).unwrap();
+    }
+
+    #[test]
+    fn test_acquire_timeout_and_status() {
+        let registry = LimiterRegistry::default();
+        let limiter = registry.realm("123");
+        limiter.set_limits(LimitConfig {
+            batches_per_window: 2,
+            max_concurrent: 2,
+            ..LimitConfig::default()
+        });
+
+        let first = limiter.acquire_timeout(true, Duration::ZERO).unwrap();
+        drop(limiter.acquire_timeout(true, Duration::ZERO).unwrap());
+        let err = limiter
+            .acquire_timeout(true, Duration::ZERO)
+            .map(drop)
+            .unwrap_err();
+        let APIErrorInner::ThrottleLimitReached {
+            retry_after: Some(retry_after),
+        } = &*err
+        else {
+            panic!("Expected ThrottleLimitReached with a retry time, got {err}");
+        };
+        assert!(*retry_after > Duration::from_secs(59));
+
+        let second = limiter.acquire(false).unwrap();
+        assert!(matches!(
+            &*limiter
+                .acquire_timeout(false, Duration::from_millis(10))
+                .map(drop)
+                .unwrap_err(),
+            APIErrorInner::ThrottleLimitReached { retry_after: None }
+        ));
+
+        let status = limiter.status().unwrap();
+        assert_eq!(status.in_flight, 2);
+        assert_eq!(status.batches.remaining, 0);
+        assert!(status.batches.available_in > Duration::from_secs(59));
+        assert_eq!(status.requests.remaining, 499);
+        assert_eq!(status.requests.available_in, Duration::ZERO);
+        assert!(status.total_wait >= Duration::from_millis(10));
+        drop((first, second));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_acquire_async() {
+        let registry = LimiterRegistry::default();
+        let limiter = registry.realm("123");
+        limiter.set_limits(LimitConfig {
+            requests_per_window: 2,
+            window: Duration::from_millis(200),
+            max_concurrent: 1,
+            ..LimitConfig::default()
+        });
+
+        let first = limiter.acquire_async(false, None).await.unwrap();
+        assert!(matches!(
+            &*limiter
+                .acquire_async(false, Some(Duration::from_millis(10)))
+                .await
+                .map(drop)
+                .unwrap_err(),
+            APIErrorInner::ThrottleLimitReached { retry_after: None }
+        ));
+
+        // Waits for the slot without blocking the runtime
+        let waiting = async {
+            let start = Instant::now();
+            drop(limiter.acquire_async(false, None).await.unwrap());
+            start.elapsed()
+        };
+        let release = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(first);
+        };
+        let (waited, ()) = tokio::join!(waiting, release);
+        assert!(waited >= Duration::from_millis(50));
+
+        // Both requests of the window are used up
+        let start = Instant::now();
+        drop(limiter.acquire_async(false, None).await.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(100));
     }
 }
@@ -191,13 +191,12 @@ macro_rules! qb_where_clause {
 /// # Syntax
 ///
 /// ```ignore
-/// qb_query!(qb_context, http_client, EntityType | field1 op value1, field2 op value2 [; additional_clauses...])
+/// qb_query!(qb_context, EntityType | field1 op value1, field2 op value2 [; additional_clauses...])
 /// ```
 ///
 /// # Parameters
 ///
 /// - `qb_context`: Reference to [`QBContext`] with authentication and configuration
-/// - `http_client`: Reference to HTTP client (usually `&ureq::Agent`)
 /// - `EntityType`: QuickBooks entity type (e.g., Customer, Invoice, Item)
 /// - `field op value`: Query conditions using supported operators
 /// - `additional_clauses`: Optional raw SQL clauses after `;` separator
@@ -208,12 +207,9 @@ macro_rules! qb_where_clause {
 /// ```rust
 /// # use quick_oxibooks::{qb_query, QBContext, Environment};
 /// # use quickbooks_types::Customer;
-/// # use ureq::Agent;
-/// # let client = Agent::new_with_defaults();
-/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string(), &client).unwrap();
+/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string()).unwrap();
 /// let customer = qb_query!(
 ///     &qb_context,
-///     &client,
 ///     Customer | given_name = "John", family_name = "Doe"
 /// )?;
 /// // Executes: SELECT * FROM Customer WHERE GivenName = 'John' AND FamilyName = 'Doe' MAXRESULTS 1
@@ -223,14 +219,11 @@ macro_rules! qb_where_clause {
 /// ```rust
 /// # use quick_oxibooks::{qb_query, QBContext, Environment};
 /// # use quickbooks_types::Customer;
-/// # use ureq::Agent;
-/// # let client = Agent::new_with_defaults();
-/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string(), &client).unwrap();
+/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string()).unwrap();
 /// let name = "John".to_string();
 /// let active = true;
 /// let customer = qb_query!(
 ///     &qb_context,
-///     &client,
 ///     Customer | given_name = name, active = active
 /// )?;
 /// // Uses runtime values in the query
@@ -240,13 +233,10 @@ macro_rules! qb_where_clause {
 /// ```rust
 /// # use quick_oxibooks::{qb_query, QBContext, Environment};
 /// # use quickbooks_types::Invoice;
-/// # use ureq::Agent;
-/// # let client = Agent::new_with_defaults();
-/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string(), &client).unwrap();
+/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string()).unwrap();
 /// let today = "2024-01-01";
 /// let invoice = qb_query!(
 ///     &qb_context,
-///     &client,
 ///     Invoice | doc_number = "INV-001" ; "ORDER BY MetaData.CreateTime DESC"
 /// )?;
 /// // Adds ordering to the query
@@ -256,12 +246,9 @@ macro_rules! qb_where_clause {
 /// ```rust
 /// # use quick_oxibooks::{qb_query, QBContext, Environment};
 /// # use quickbooks_types::Customer;
-/// # use ureq::Agent;
-/// # let client = Agent::new_with_defaults();
-/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string(), &client).unwrap();
+/// # let qb_context = QBContext::new(Environment::SANDBOX, "123".to_string(), "token".to_string()).unwrap();
 /// let customer = qb_query!(
 ///     &qb_context,
-///     &client,
 ///     Customer | display_name like "%Corp%"
 /// )?;
 /// // Pattern matching query
@@ -288,19 +275,17 @@ macro_rules! qb_where_clause {
 /// - `JsonError`: Response parsing errors
 #[macro_export]
 macro_rules! qb_query {
-  ($qb:expr, $client:expr, $struct_name:ident | $($field:ident $op:tt $value:expr),+) => {
+  ($qb:expr, $struct_name:ident | $($field:ident $op:tt $value:expr),+) => {
     <$struct_name as $crate::functions::query::QBQuery>::query_single(
       &$crate::qb_where_clause!($struct_name | $($field $op $value),+),
-      $qb,
-      $client
+      $qb
     )
   };
 
-  ($qb:expr, $client:expr, $struct_name:ident | $($field:ident $op:tt $value:expr),+ ; $($addon:literal),+) => {
+  ($qb:expr, $struct_name:ident | $($field:ident $op:tt $value:expr),+ ; $($addon:literal),+) => {
     <$struct_name as $crate::functions::query::QBQuery>::query_single(
       &$crate::qb_where_clause!($struct_name | $($field $op $value),+ ; $($addon),+),
-      $qb,
-      $client
+      $qb
     )
   };
 }
@@ -311,16 +296,9 @@ mod test {
     use quickbooks_types::Customer;
 
     fn test_macro_works() -> Result<(), String> {
-        let client = ureq::Agent::new_with_defaults();
-        let qb = QBContext::new_from_env(crate::Environment::SANDBOX, &client)
+        let qb = QBContext::new_from_env(crate::Environment::SANDBOX).map_err(|e| e.to_string())?;
+        let cust = qb_query!(&qb, Customer | given_name = "Tom", family_name = "Hanks")
             .map_err(|e| e.to_string())?;
-        let cust = qb_query!(
-            &qb,
-            &client,
-            Customer | given_name = "Tom",
-            family_name = "Hanks"
-        )
-        .map_err(|e| e.to_string())?;
         Ok(())
     }
 }
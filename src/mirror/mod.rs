@@ -0,0 +1,375 @@
+//! # Incremental Local Mirror
+//!
+//! This module keeps a local copy of `QuickBooks` entities in sync using
+//! Change Data Capture (see [`crate::functions::cdc`]).
+//!
+//! A [`Mirror`] is configured with the entity types to keep and a [`MirrorStore`]
+//! to keep them in. Each call to [`Mirror::sync`]:
+//!
+//! 1. Fully loads, through a paginated [`QBQuery::query_all`] ordered by `Id`, every
+//!    entity type that has never been synced or whose watermark is too old for CDC
+//!    (30 days). Inactive customers, vendors, items, accounts and employees are
+//!    loaded too, as CDC reports them like any other entity.
+//! 2. Requests the changes to every other entity type since its watermark with one
+//!    CDC request, upserting changed entities and removing deleted ones. When the
+//!    response was truncated at 1000 objects, which applies to all entity types
+//!    together, every entity type in it is fully reloaded instead.
+//! 3. Advances the watermarks and flushes the store.
+//!
+//! Watermarks live in the store, so a restarted process using a persistent store
+//! such as [`JsonFileStore`] picks up where the last sync left off.
+//!
+//! ## Usage Example
+//!
+//! ```no_run
+//! use quick_oxibooks::{QBContext, Environment};
+//! use quick_oxibooks::mirror::{JsonFileStore, Mirror};
+//! use quickbooks_types::{Customer, Invoice, Item, Payment};
+//!
+//! let qb_context = QBContext::new(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//! ).unwrap();
+//!
+//! let store = JsonFileStore::open("mirror.json").unwrap();
+//! let mut mirror = Mirror::new(store)
+//!     .entity::<Customer>()
+//!     .entity::<Invoice>()
+//!     .entity::<Payment>()
+//!     .entity::<Item>();
+//!
+//! let report = mirror.sync(&qb_context).unwrap();
+//! println!("Upserted {}, removed {}", report.upserted, report.removed);
+//!
+//! let invoices: Vec<Invoice> = mirror.all().unwrap();
+//! ```
+
+use chrono::{DateTime, Duration, Utc};
+use quickbooks_types::QBItem;
+use serde_json::Value;
+
+use crate::{
+    functions::{
+        cdc::{CdcRequest, CdcResponse, CDC_MAX_LOOKBACK_DAYS},
+        query::QBQuery,
+    },
+    APIResult, QBContext,
+};
+
+mod store;
+pub use store::{JsonFileStore, MemoryStore, MirrorStore};
+
+/// An entity type kept by the mirror, with the type-specific operations erased
+/// so entity types can be mixed in one mirror
+struct MirrorEntity {
+    name: &'static str,
+    add_to_cdc: fn(CdcRequest) -> CdcRequest,
+    load: LoadFn,
+}
+
+/// Loads every entity of one type, paired with its ID
+type LoadFn = fn(&QBContext) -> APIResult<Vec<(String, Value)>>;
+
+/// Summary of a single [`Mirror::sync`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Entity types that were fully loaded instead of synced incrementally
+    pub full_loads: Vec<&'static str>,
+    /// Number of entities inserted or updated
+    pub upserted: usize,
+    /// Number of entities removed because they were deleted in `QuickBooks`
+    pub removed: usize,
+}
+
+/// Local copy of `QuickBooks` entities, kept in sync through Change Data Capture.
+///
+/// See the [module documentation](self) for how syncing works.
+pub struct Mirror<S> {
+    store: S,
+    entities: Vec<MirrorEntity>,
+}
+
+impl<S: MirrorStore> Mirror<S> {
+    /// Creates a mirror that keeps its entities in the given store
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            entities: Vec::new(),
+        }
+    }
+
+    /// Adds the entity type `T` to the mirror
+    #[must_use]
+    pub fn entity<T: QBItem>(mut self) -> Self {
+        if self.entities.iter().all(|e| e.name != T::name()) {
+            self.entities.push(MirrorEntity {
+                name: T::name(),
+                add_to_cdc: CdcRequest::entity::<T>,
+                load: load_all::<T>,
+            });
+        }
+        self
+    }
+
+    /// The underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the mirror, returning the underlying store
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Returns the stored entity of type `T` with the given ID
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails or the entity can't be deserialized into `T`
+    pub fn get<T: QBItem>(&self, id: &str) -> APIResult<Option<T>> {
+        self.store
+            .get(T::name(), id)?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Returns every stored entity of type `T`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails or an entity can't be deserialized into `T`
+    pub fn all<T: QBItem>(&self) -> APIResult<Vec<T>> {
+        self.store
+            .all(T::name())?
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(Into::into))
+            .collect()
+    }
+
+    /// Brings the store up to date with `QuickBooks`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request or the store fails. Watermarks are only
+    /// advanced for the entity types that were synced before the failure, and the
+    /// store is not flushed, so the next sync retries from the last flushed state.
+    pub fn sync(&mut self, qb: &QBContext) -> APIResult<SyncReport> {
+        let started = Utc::now();
+        // Leave some room so the watermark is still inside the lookback
+        // window by the time the CDC request is sent
+        let cdc_cutoff = started - Duration::days(CDC_MAX_LOOKBACK_DAYS) + Duration::hours(1);
+
+        let mut report = SyncReport::default();
+        let mut full_loads = Vec::new();
+        let mut incremental = Vec::new();
+        for entity in &self.entities {
+            match self.store.watermark(entity.name)? {
+                Some(watermark) if watermark > cdc_cutoff => incremental.push((entity, watermark)),
+                _ => full_loads.push(entity),
+            }
+        }
+
+        if let Some(changed_since) = incremental.iter().map(|(_, w)| *w).min() {
+            let request = incremental
+                .iter()
+                .fold(CdcRequest::new(changed_since), |request, (entity, _)| {
+                    (entity.add_to_cdc)(request)
+                });
+            let response = request.send(qb)?;
+            let incremental = incremental.into_iter().map(|(entity, _)| entity);
+            full_loads.extend(apply_cdc(
+                &mut self.store,
+                &response,
+                response.time().unwrap_or(started),
+                incremental,
+                &mut report,
+            )?);
+        }
+
+        for entity in full_loads {
+            #[cfg(feature = "logging")]
+            log::info!("Fully loading {} into the mirror", entity.name);
+
+            let items = (entity.load)(qb)?;
+            self.store.clear(entity.name)?;
+            report.upserted += items.len();
+            for (id, value) in items {
+                self.store.upsert(entity.name, &id, value)?;
+            }
+            self.store.set_watermark(entity.name, started)?;
+            report.full_loads.push(entity.name);
+        }
+
+        self.store.flush()?;
+
+        #[cfg(feature = "logging")]
+        log::info!(
+            "Synced mirror: {} upserted, {} removed, full loads: {:?}",
+            report.upserted,
+            report.removed,
+            report.full_loads
+        );
+
+        Ok(report)
+    }
+
+    /// The oldest watermark across the mirrored entity types,
+    /// `None` if any of them has never been synced
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store fails
+    pub fn watermark(&self) -> APIResult<Option<DateTime<Utc>>> {
+        let mut oldest: Option<DateTime<Utc>> = None;
+        for entity in &self.entities {
+            let Some(watermark) = self.store.watermark(entity.name)? else {
+                return Ok(None);
+            };
+            oldest = Some(oldest.map_or(watermark, |o| o.min(watermark)));
+        }
+        Ok(oldest)
+    }
+}
+
+/// Applies the changes in a CDC response to the store and advances the watermarks of
+/// the given entity types, returning them instead if the response was truncated, as
+/// changes to any of them may be missing
+fn apply_cdc<'a, S: MirrorStore>(
+    store: &mut S,
+    response: &CdcResponse,
+    watermark: DateTime<Utc>,
+    entities: impl IntoIterator<Item = &'a MirrorEntity>,
+    report: &mut SyncReport,
+) -> APIResult<Vec<&'a MirrorEntity>> {
+    if response.is_truncated() {
+        return Ok(entities.into_iter().collect());
+    }
+    for entity in entities {
+        if let Some(changes) = response.get(entity.name) {
+            for value in changes.changed_raw() {
+                let Some(id) = value.get("Id").and_then(Value::as_str) else {
+                    continue;
+                };
+                store.upsert(entity.name, id, value.clone())?;
+                report.upserted += 1;
+            }
+            for deleted in changes.deleted() {
+                store.remove(entity.name, &deleted.id)?;
+                report.removed += 1;
+            }
+        }
+        store.set_watermark(entity.name, watermark)?;
+    }
+    Ok(Vec::new())
+}
+
+/// Entity types that can be made inactive, which queries leave out unless asked for
+const DEACTIVATABLE: &[&str] = &["Account", "Customer", "Employee", "Item", "Vendor"];
+
+/// The query for every entity of the type with the given name, ordered so the
+/// pages of a paginated query don't overlap or skip any
+fn load_query(name: &str) -> &'static str {
+    if DEACTIVATABLE.contains(&name) {
+        "WHERE Active IN (true, false) ORDERBY Id"
+    } else {
+        "ORDERBY Id"
+    }
+}
+
+/// Loads every entity of type `T`, including inactive ones, with a paginated query
+fn load_all<T: QBItem>(qb: &QBContext) -> APIResult<Vec<(String, Value)>> {
+    let mut items = Vec::new();
+    for item in T::query_all(load_query(T::name()), qb) {
+        let item = item?;
+        let Some(id) = item.id() else {
+            continue;
+        };
+        items.push((id.clone(), serde_json::to_value(&item)?));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use quickbooks_types::{Customer, Invoice, Vendor};
+    use serde_json::json;
+
+    use super::{apply_cdc, load_query, MemoryStore, Mirror, MirrorStore, SyncReport};
+    use crate::functions::cdc::CdcResponse;
+
+    fn cdc_response(customers: usize, invoices: usize) -> CdcResponse {
+        let items = |count: usize| {
+            (0..count)
+                .map(|i| json!({ "Id": i.to_string(), "SyncToken": "0" }))
+                .collect::<Vec<_>>()
+        };
+        serde_json::from_value(json!({
+            "CDCResponse": [{
+                "QueryResponse": [
+                    { "Customer": items(customers) },
+                    { "Invoice": items(invoices) },
+                ]
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_cdc() {
+        let mut mirror = Mirror::new(MemoryStore::new())
+            .entity::<Customer>()
+            .entity::<Invoice>()
+            .entity::<Vendor>();
+        let watermark = Utc::now();
+        let mut report = SyncReport::default();
+
+        let full_loads = apply_cdc(
+            &mut mirror.store,
+            &cdc_response(2, 1),
+            watermark,
+            &mirror.entities,
+            &mut report,
+        )
+        .unwrap();
+        assert!(full_loads.is_empty());
+        assert_eq!(report.upserted, 3);
+        assert_eq!(mirror.store.all("Customer").unwrap().len(), 2);
+        assert_eq!(mirror.store.watermark("Vendor").unwrap(), Some(watermark));
+    }
+
+    #[test]
+    fn test_apply_truncated_cdc() {
+        let mut mirror = Mirror::new(MemoryStore::new())
+            .entity::<Customer>()
+            .entity::<Invoice>()
+            .entity::<Vendor>();
+        let mut report = SyncReport::default();
+
+        // 1000 objects across two types, changes to any of the three may be missing
+        let full_loads = apply_cdc(
+            &mut mirror.store,
+            &cdc_response(600, 400),
+            Utc::now(),
+            &mirror.entities,
+            &mut report,
+        )
+        .unwrap();
+        let names: Vec<_> = full_loads.iter().map(|entity| entity.name).collect();
+        assert_eq!(names, ["Customer", "Invoice", "Vendor"]);
+        assert_eq!(report, SyncReport::default());
+        assert!(mirror.store.all("Customer").unwrap().is_empty());
+        assert_eq!(mirror.store.watermark("Customer").unwrap(), None);
+    }
+
+    #[test]
+    fn test_load_query() {
+        assert_eq!(
+            load_query("Customer"),
+            "WHERE Active IN (true, false) ORDERBY Id"
+        );
+        assert_eq!(load_query("Invoice"), "ORDERBY Id");
+    }
+}
@@ -0,0 +1,258 @@
+//! Storage backends for the local mirror.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::APIResult;
+
+/// Storage for the entities kept by a [`Mirror`](super::Mirror).
+///
+/// Entities are stored as raw JSON, keyed by entity type name (e.g. `Customer`)
+/// and ID. Alongside them the store keeps a sync watermark per entity type, the
+/// point in time the next incremental sync picks up from.
+///
+/// The mirror calls [`MirrorStore::flush`] once at the end of every sync, so a
+/// store that persists its state should make the entities and watermarks
+/// written since the last flush durable together.
+pub trait MirrorStore {
+    /// Returns the entity of the given type and ID, if stored
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn get(&self, entity: &str, id: &str) -> APIResult<Option<Value>>;
+
+    /// Returns every stored entity of the given type
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn all(&self, entity: &str) -> APIResult<Vec<Value>>;
+
+    /// Inserts or replaces the entity of the given type and ID
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn upsert(&mut self, entity: &str, id: &str, value: Value) -> APIResult<()>;
+
+    /// Removes the entity of the given type and ID, if stored
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn remove(&mut self, entity: &str, id: &str) -> APIResult<()>;
+
+    /// Removes every stored entity of the given type
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn clear(&mut self, entity: &str) -> APIResult<()>;
+
+    /// Returns the sync watermark of the given entity type
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn watermark(&self, entity: &str) -> APIResult<Option<DateTime<Utc>>>;
+
+    /// Sets the sync watermark of the given entity type
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn set_watermark(&mut self, entity: &str, watermark: DateTime<Utc>) -> APIResult<()>;
+
+    /// Persists the changes made since the last flush
+    ///
+    /// # Errors
+    ///
+    /// If the underlying storage fails
+    fn flush(&mut self) -> APIResult<()> {
+        Ok(())
+    }
+}
+
+/// [`MirrorStore`] that keeps everything in memory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryStore {
+    entities: BTreeMap<String, BTreeMap<String, Value>>,
+    watermarks: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MirrorStore for MemoryStore {
+    fn get(&self, entity: &str, id: &str) -> APIResult<Option<Value>> {
+        Ok(self
+            .entities
+            .get(entity)
+            .and_then(|items| items.get(id))
+            .cloned())
+    }
+
+    fn all(&self, entity: &str) -> APIResult<Vec<Value>> {
+        Ok(self
+            .entities
+            .get(entity)
+            .map(|items| items.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn upsert(&mut self, entity: &str, id: &str, value: Value) -> APIResult<()> {
+        self.entities
+            .entry(entity.to_string())
+            .or_default()
+            .insert(id.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&mut self, entity: &str, id: &str) -> APIResult<()> {
+        if let Some(items) = self.entities.get_mut(entity) {
+            items.remove(id);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, entity: &str) -> APIResult<()> {
+        self.entities.remove(entity);
+        Ok(())
+    }
+
+    fn watermark(&self, entity: &str) -> APIResult<Option<DateTime<Utc>>> {
+        Ok(self.watermarks.get(entity).copied())
+    }
+
+    fn set_watermark(&mut self, entity: &str, watermark: DateTime<Utc>) -> APIResult<()> {
+        self.watermarks.insert(entity.to_string(), watermark);
+        Ok(())
+    }
+}
+
+/// [`MirrorStore`] that keeps everything in memory and writes it to a JSON file on flush.
+///
+/// The file is replaced atomically by writing to a temporary file next to it and
+/// renaming it over the old one, so a crash mid-write leaves the previous sync intact.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    path: PathBuf,
+    data: MemoryStore,
+}
+
+impl JsonFileStore {
+    /// Opens the store at the given path, loading its contents if the file exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but can't be read or parsed
+    pub fn open(path: impl AsRef<Path>) -> APIResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let data = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MemoryStore::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, data })
+    }
+
+    /// The path of the JSON file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl MirrorStore for JsonFileStore {
+    fn get(&self, entity: &str, id: &str) -> APIResult<Option<Value>> {
+        self.data.get(entity, id)
+    }
+
+    fn all(&self, entity: &str) -> APIResult<Vec<Value>> {
+        self.data.all(entity)
+    }
+
+    fn upsert(&mut self, entity: &str, id: &str, value: Value) -> APIResult<()> {
+        self.data.upsert(entity, id, value)
+    }
+
+    fn remove(&mut self, entity: &str, id: &str) -> APIResult<()> {
+        self.data.remove(entity, id)
+    }
+
+    fn clear(&mut self, entity: &str) -> APIResult<()> {
+        self.data.clear(entity)
+    }
+
+    fn watermark(&self, entity: &str) -> APIResult<Option<DateTime<Utc>>> {
+        self.data.watermark(entity)
+    }
+
+    fn set_watermark(&mut self, entity: &str, watermark: DateTime<Utc>) -> APIResult<()> {
+        self.data.set_watermark(entity, watermark)
+    }
+
+    fn flush(&mut self) -> APIResult<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut file = fs::File::create(&tmp_path)?;
+        serde_json::to_writer(&mut file, &self.data)?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::{JsonFileStore, MirrorStore};
+
+    #[test]
+    fn test_json_file_store_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "quick_oxibooks_mirror_test_{}.json",
+            std::process::id()
+        ));
+        let watermark = Utc::now();
+
+        let mut store = JsonFileStore::open(&path).unwrap();
+        store
+            .upsert("Customer", "1", json!({"Id": "1", "DisplayName": "Acme"}))
+            .unwrap();
+        store.upsert("Customer", "2", json!({"Id": "2"})).unwrap();
+        store.remove("Customer", "2").unwrap();
+        store.set_watermark("Customer", watermark).unwrap();
+        store.flush().unwrap();
+
+        let store = JsonFileStore::open(&path).unwrap();
+        assert_eq!(store.all("Customer").unwrap().len(), 1);
+        assert_eq!(
+            store.get("Customer", "1").unwrap().unwrap()["DisplayName"],
+            "Acme"
+        );
+        assert_eq!(store.watermark("Customer").unwrap(), Some(watermark));
+        assert_eq!(store.watermark("Invoice").unwrap(), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
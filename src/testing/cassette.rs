@@ -0,0 +1,514 @@
+//! Recording the requests a context sends and replaying their responses offline.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ureq::http::{header::CONTENT_TYPE, Request, Response};
+
+use super::query_pairs;
+use crate::{client::Transport, error::APIErrorInner, APIResult};
+
+/// What redacted values are replaced with
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Body fields holding tokens, which are never written to a cassette, like the
+/// refresh token in the body of a revocation
+const TOKEN_FIELDS: [&str; 4] = ["access_token", "refresh_token", "id_token", "token"];
+
+/// Form parameters holding tokens or authorization codes, which are never written to
+/// a cassette. `code` is only redacted in forms, in JSON it is the code of a fault.
+const FORM_TOKEN_FIELDS: [&str; 6] = [
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "token",
+    "code",
+    "code_verifier",
+];
+
+/// Query parameters left out of recorded requests, as they differ on every run
+const IGNORED_PARAMS: [&str; 1] = [crate::functions::request_id::REQUEST_ID_PARAM];
+
+/// Response headers kept in a cassette, the others are dropped
+const RECORDED_HEADERS: [&str; 2] = ["content-type", "retry-after"];
+
+/// A [`Transport`] that records the requests sent through it to a cassette file, or
+/// answers them from one.
+///
+/// In record mode, every request is sent through another transport and written to the
+/// cassette along with its response: the method, path, query and body of the request,
+/// and the status, body and `Content-Type` and `Retry-After` headers of the response.
+/// Headers of the request, including the `Authorization` header carrying the access
+/// token, aren't recorded. Tokens in bodies are replaced with [`REDACTED`], and the
+/// `requestid` is left out of the query, as it's generated anew for every request.
+///
+/// In replay mode, requests are answered with the recorded response of the first
+/// interaction with the same method, path, query and body that wasn't replayed yet,
+/// without sending anything. A request no interaction matches fails with
+/// [`APIErrorInner::UnmatchedCassetteRequest`].
+///
+/// Discovery and token requests are recorded too, so a context built through a
+/// cassette can be replayed entirely offline. The async API sends its requests
+/// through the cassette as well, instead of through its `reqwest::Client`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use quick_oxibooks::{client::Transport, testing::Cassette, Environment, QBContext};
+/// use quick_oxibooks::functions::query::QBQuery;
+/// use quickbooks_types::Customer;
+///
+/// let cassette = if std::env::var("RECORD").is_ok() {
+///     Cassette::record(
+///         "test/cassettes/customers.json",
+///         Arc::new(ureq::Agent::new_with_defaults()),
+///     )
+/// } else {
+///     Cassette::replay("test/cassettes/customers.json").unwrap()
+/// };
+///
+/// let qb = QBContext::new_with_transport(
+///     Environment::SANDBOX,
+///     std::env::var("QB_COMPANY_ID").unwrap_or_default(),
+///     std::env::var("QB_ACCESS_TOKEN").unwrap_or_default(),
+///     Arc::new(cassette),
+/// )
+/// .unwrap();
+/// let customers = Customer::query("WHERE Active = true", None, &qb).unwrap();
+/// ```
+pub struct Cassette {
+    path: PathBuf,
+    /// The transport requests are recorded from, `None` when replaying
+    recording: Option<Arc<dyn Transport>>,
+    state: Mutex<CassetteState>,
+}
+
+#[derive(Default)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    replayed: Vec<bool>,
+}
+
+/// The contents of a cassette file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+/// A request and the response it got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// A recorded request, without its headers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The path of the URL, e.g. `/v3/company/123/query`
+    pub path: String,
+    /// The decoded query parameters, in the order they were sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub query: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+/// A recorded response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+/// A request or response body, kept as JSON when it is JSON so cassettes can be
+/// read and edited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    Json(Value),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Cassette {
+    /// Creates a cassette that sends requests through the given transport and records
+    /// them to the file at the given path, replacing what it held
+    pub fn record(path: impl Into<PathBuf>, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            path: path.into(),
+            recording: Some(transport),
+            state: Mutex::new(CassetteState::default()),
+        }
+    }
+
+    /// Loads the cassette at the given path to answer requests from
+    ///
+    /// # Errors
+    ///
+    /// - I/O errors when reading the file
+    /// - JSON parsing errors if the file isn't a cassette
+    pub fn replay(path: impl Into<PathBuf>) -> APIResult<Self> {
+        let path = path.into();
+        let file: CassetteFile = serde_json::from_slice(&fs::read(&path)?)?;
+        Ok(Self {
+            path,
+            recording: None,
+            state: Mutex::new(CassetteState {
+                replayed: vec![false; file.interactions.len()],
+                interactions: file.interactions,
+            }),
+        })
+    }
+
+    /// The path of the cassette file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `true` if requests are sent and recorded, `false` if they are replayed
+    #[must_use]
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// The interactions recorded so far, or loaded to be replayed
+    #[must_use]
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.state().interactions.clone()
+    }
+
+    /// Returns `true` if every loaded interaction was replayed
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.state().replayed.iter().all(|replayed| *replayed)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CassetteState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> APIResult<()> {
+        let file = CassetteFile {
+            interactions: interactions.to_vec(),
+        };
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+impl Transport for Cassette {
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+        let recorded = RecordedRequest::new(&request);
+        let Some(transport) = &self.recording else {
+            let mut state = self.state();
+            let CassetteState {
+                interactions,
+                replayed,
+            } = &mut *state;
+            let Some((interaction, replayed)) = interactions
+                .iter()
+                .zip(replayed.iter_mut())
+                .find(|(interaction, replayed)| !**replayed && interaction.request == recorded)
+            else {
+                return Err(APIErrorInner::UnmatchedCassetteRequest(format!(
+                    "{} {}",
+                    recorded.method, recorded.path
+                ))
+                .into());
+            };
+            *replayed = true;
+            return interaction.response.to_response();
+        };
+
+        let response = transport.send(request)?;
+        let mut state = self.state();
+        state.interactions.push(Interaction {
+            request: recorded,
+            response: RecordedResponse::new(&response),
+        });
+        state.replayed.push(true);
+        self.save(&state.interactions)?;
+        Ok(response)
+    }
+
+    fn sends_async_requests(&self) -> bool {
+        true
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &Request<Vec<u8>>) -> Self {
+        let uri = request.uri();
+        let query = uri
+            .query()
+            .map(|query| {
+                query_pairs(query)
+                    .filter(|(k, _)| !IGNORED_PARAMS.contains(&k.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            method: request.method().to_string(),
+            path: uri.path().to_string(),
+            query,
+            body: RecordedBody::new(request.body()),
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn new(response: &Response<Vec<u8>>) -> Self {
+        let headers = RECORDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers().get(*name)?.to_str().ok()?;
+                Some(((*name).to_string(), value.to_string()))
+            })
+            .collect();
+        Self {
+            status: response.status().as_u16(),
+            headers,
+            body: RecordedBody::new(response.body()),
+        }
+    }
+
+    fn to_response(&self) -> APIResult<Response<Vec<u8>>> {
+        let mut response = Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(name.as_str(), value.as_str());
+        }
+        if !self.headers.contains_key(CONTENT_TYPE.as_str())
+            && matches!(self.body, Some(RecordedBody::Json(_)))
+        {
+            response = response.header(CONTENT_TYPE, "application/json");
+        }
+        let body = self
+            .body
+            .as_ref()
+            .map(RecordedBody::to_bytes)
+            .unwrap_or_default();
+        Ok(response.body(body)?)
+    }
+}
+
+impl RecordedBody {
+    /// Keeps the body as JSON or text if it is, with its tokens redacted
+    fn new(body: &[u8]) -> Option<Self> {
+        if body.is_empty() {
+            return None;
+        }
+        if let Ok(mut json) = serde_json::from_slice::<Value>(body) {
+            redact_json(&mut json);
+            return Some(Self::Json(json));
+        }
+        Some(match std::str::from_utf8(body) {
+            Ok(text) => Self::Text(redact_form(text)),
+            Err(_) => Self::Bytes(body.to_vec()),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Json(json) => serde_json::to_vec(json).unwrap_or_default(),
+            Self::Text(text) => text.clone().into_bytes(),
+            Self::Bytes(bytes) => bytes.clone(),
+        }
+    }
+}
+
+/// Replaces the tokens anywhere in a JSON value
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if TOKEN_FIELDS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Replaces the tokens in a form encoded body, like the one of a token request
+fn redact_form(body: &str) -> String {
+    body.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if FORM_TOKEN_FIELDS.contains(&key) => format!("{key}={REDACTED}"),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use quickbooks_types::Customer;
+    use ureq::http::{Request, Response};
+
+    use super::{Cassette, RecordedBody, REDACTED};
+    use crate::{
+        client::Transport,
+        error::APIErrorInner,
+        functions::{create::QBCreate, query::QBQuery},
+        testing::MockRealm,
+        APIResult, Environment, QBContext,
+    };
+
+    /// A realm that also accepts revocations, which [`MockRealm`] doesn't serve
+    struct RevocableRealm(MockRealm);
+
+    impl Transport for RevocableRealm {
+        fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+            if request.uri().path().ends_with("/tokens/revoke") {
+                return Ok(Response::new(Vec::new()));
+            }
+            self.0.send(request)
+        }
+    }
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "quick_oxibooks_cassette_{name}_{}.json",
+            std::process::id()
+        ))
+    }
+
+    fn context(cassette: Cassette) -> QBContext {
+        QBContext::builder(
+            Environment::SANDBOX,
+            "123".to_string(),
+            "access_token_xyz".to_string(),
+        )
+        .endpoints(MockRealm::endpoints())
+        .transport(Arc::new(cassette) as Arc<dyn Transport>)
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let path = cassette_path("test");
+        let customer = Customer {
+            display_name: Some("Acme".to_string()),
+            ..Default::default()
+        };
+
+        let qb = context(Cassette::record(&path, Arc::new(MockRealm::new("123"))));
+        let created = customer.create(&qb).unwrap();
+        let found = Customer::query("WHERE DisplayName = 'Acme'", None, &qb).unwrap();
+        drop(qb);
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(!recorded.contains("access_token_xyz"));
+        assert!(!recorded.contains("requestid"));
+
+        let qb = context(Cassette::replay(&path).unwrap());
+        assert_eq!(customer.create(&qb).unwrap().id, created.id);
+        let replayed = Customer::query("WHERE DisplayName = 'Acme'", None, &qb).unwrap();
+        assert_eq!(replayed.len(), found.len());
+        // Every recorded interaction was replayed already
+        let err = customer.create(&qb).unwrap_err();
+        assert!(matches!(&*err, APIErrorInner::UnmatchedCassetteRequest(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redact() {
+        let body = br#"{"token_type":"bearer","access_token":"a","x":{"refresh_token":"r"}}"#;
+        let Some(RecordedBody::Json(json)) = RecordedBody::new(body) else {
+            panic!("Body should be kept as JSON");
+        };
+        assert_eq!(json["access_token"], REDACTED);
+        assert_eq!(json["x"]["refresh_token"], REDACTED);
+        assert_eq!(json["token_type"], "bearer");
+        assert_eq!(
+            RecordedBody::new(b"grant_type=refresh_token&refresh_token=r"),
+            Some(RecordedBody::Text(format!(
+                "grant_type=refresh_token&refresh_token={REDACTED}"
+            )))
+        );
+        assert_eq!(
+            RecordedBody::new(b"grant_type=authorization_code&code=c&code_verifier=v"),
+            Some(RecordedBody::Text(format!(
+                "grant_type=authorization_code&code={REDACTED}&code_verifier={REDACTED}"
+            )))
+        );
+
+        let body = br#"{"Fault":{"Error":[{"Message":"Stale Object Error","code":"5010"}]}}"#;
+        let Some(RecordedBody::Json(json)) = RecordedBody::new(body) else {
+            panic!("Body should be kept as JSON");
+        };
+        assert_eq!(json["Fault"]["Error"][0]["code"], "5010");
+    }
+
+    #[test]
+    fn test_record_revoke() {
+        let path = cassette_path("revoke");
+        let realm = RevocableRealm(MockRealm::new("123"));
+        let qb = context(Cassette::record(&path, Arc::new(realm)))
+            .with_refresh("refresh_secret".to_string());
+        qb.revoke("client_id", "client_secret").unwrap();
+        drop(qb);
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(!recorded.contains("refresh_secret"));
+        assert!(recorded.contains(&format!(r#""token": "{REDACTED}""#)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_record_replay_async() {
+        use crate::asynchronous::{
+            create::QBCreate as QBCreateAsync, query::QBQuery as QBQueryAsync,
+        };
+
+        let path = cassette_path("async");
+        let client = reqwest::Client::new();
+        let customer = Customer {
+            display_name: Some("Acme".to_string()),
+            ..Default::default()
+        };
+
+        let qb = context(Cassette::record(&path, Arc::new(MockRealm::new("123"))));
+        let created = QBCreateAsync::create(&customer, &qb, &client)
+            .await
+            .unwrap();
+        drop(qb);
+
+        let qb = context(Cassette::replay(&path).unwrap());
+        let replayed = QBCreateAsync::create(&customer, &qb, &client)
+            .await
+            .unwrap();
+        assert_eq!(replayed.id, created.id);
+        // Nothing was recorded for the query, and the client never reaches the network
+        let err =
+            <Customer as QBQueryAsync>::query("WHERE DisplayName = 'Acme'", None, &qb, &client)
+                .await
+                .unwrap_err();
+        assert!(matches!(&*err, APIErrorInner::UnmatchedCassetteRequest(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,889 @@
+//! # Mock `QuickBooks` Server
+//!
+//! An in-process stand-in for the `QuickBooks` Online API, for tests that shouldn't
+//! reach the network.
+//!
+//! A [`MockRealm`] keeps the entities of one company in memory and answers requests
+//! the way `QuickBooks` does: creates assign an `Id` and a `SyncToken`, updates and
+//! deletes with an outdated `SyncToken` fail with a stale object fault, and errors come
+//! back as the same `Fault` payloads. It serves creates, reads, updates, deletes, voids,
+//! queries, batches and reports for the entity types in [`MOCK_ENTITIES`], and writes
+//! sent again with the same `requestid` get the first response back.
+//!
+//! The realm is a [`Transport`], so a [`QBContext`] can send its requests straight to
+//! it with [`MockRealm::context`]. [`MockServer`] serves it on a local TCP listener
+//! instead, for clients that need a real HTTP server, like the async API.
+//!
+//! A [`Cassette`] records the requests a context sends and their responses to a file,
+//! with tokens redacted, and replays them offline, for tests against the real API
+//! that shouldn't depend on it once recorded.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use quick_oxibooks::functions::{create::QBCreate, query::QBQuery};
+//! use quick_oxibooks::testing::MockRealm;
+//! use quickbooks_types::Customer;
+//!
+//! let realm = Arc::new(MockRealm::new("123"));
+//! let qb = realm.context("access_token").unwrap();
+//!
+//! let mut customer = Customer::default();
+//! customer.display_name = Some("Acme Corp".to_string());
+//! let created = customer.create(&qb).unwrap();
+//! assert_eq!(created.sync_token.as_deref(), Some("0"));
+//!
+//! let found = Customer::query("WHERE DisplayName = 'Acme Corp'", None, &qb).unwrap();
+//! assert_eq!(found.len(), 1);
+//! assert_eq!(Customer::count("", &qb).unwrap(), 1);
+//! ```
+
+mod cassette;
+mod query;
+mod server;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use ureq::http::{header::CONTENT_TYPE, HeaderValue, Method, Request, Response, StatusCode};
+use urlencoding::decode;
+
+use self::query::{Projection, Query};
+pub use self::{
+    cassette::{Cassette, Interaction, RecordedBody, RecordedRequest, RecordedResponse, REDACTED},
+    server::MockServer,
+};
+use crate::{
+    client::Transport, limiter::LimiterRegistry, APIResult, DiscoveryDoc, Endpoints, Environment,
+    QBContext,
+};
+
+/// The base URL of a [`MockRealm`] used in memory, its requests never leave the process
+pub const MOCK_BASE_URL: &str = "http://quickbooks.mock";
+
+/// The entity types a [`MockRealm`] serves
+pub const MOCK_ENTITIES: [&str; 12] = [
+    "Account",
+    "Attachable",
+    "Bill",
+    "CompanyInfo",
+    "Customer",
+    "Employee",
+    "Estimate",
+    "Invoice",
+    "Item",
+    "Payment",
+    "SalesReceipt",
+    "Vendor",
+];
+
+/// Entities whose `DisplayName` has to be unique
+const NAMED_ENTITIES: [&str; 3] = ["Customer", "Employee", "Vendor"];
+
+/// The entities of one company, answering requests like `QuickBooks` Online.
+///
+/// See the [module documentation](self).
+pub struct MockRealm {
+    realm_id: String,
+    state: Mutex<RealmState>,
+}
+
+#[derive(Default)]
+struct RealmState {
+    entities: HashMap<&'static str, BTreeMap<u64, Value>>,
+    last_id: u64,
+    reports: HashMap<String, Value>,
+    /// Responses to writes by their `requestid`, sent again for a repeated write
+    responses: HashMap<String, (StatusCode, Value)>,
+}
+
+impl MockRealm {
+    /// Creates an empty realm for the company with the given ID
+    #[must_use]
+    pub fn new(realm_id: impl Into<String>) -> Self {
+        Self {
+            realm_id: realm_id.into(),
+            state: Mutex::new(RealmState::default()),
+        }
+    }
+
+    /// The ID of the company
+    #[must_use]
+    pub fn realm_id(&self) -> &str {
+        &self.realm_id
+    }
+
+    /// The endpoints of the realm when used in memory
+    #[must_use]
+    pub fn endpoints() -> Endpoints {
+        Endpoints::from_base_url(MOCK_BASE_URL)
+    }
+
+    /// Creates a context for the company that sends its requests to the realm in memory
+    ///
+    /// The context has a [`LimiterRegistry`] of its own, so its requests don't count
+    /// against the budget of other contexts, mock or real, for the same company.
+    ///
+    /// # Errors
+    ///
+    /// Doesn't fail, as the discovery document is served without a request, but
+    /// returns the result of [`QBContextBuilder::build`](crate::client::QBContextBuilder::build)
+    pub fn context(self: &Arc<Self>, access_token: impl Into<String>) -> APIResult<QBContext> {
+        QBContext::builder(
+            Environment::SANDBOX,
+            self.realm_id.clone(),
+            access_token.into(),
+        )
+        .endpoints(Self::endpoints())
+        .discovery_doc(discovery_doc(MOCK_BASE_URL))
+        .transport(Arc::clone(self) as Arc<dyn Transport>)
+        .build()
+        .map(|qb| qb.with_limiter_registry(&LimiterRegistry::default()))
+    }
+
+    /// Returns the stored entity of the given type, e.g. `Invoice`, with the given ID
+    #[must_use]
+    pub fn entity(&self, entity: &str, id: &str) -> Option<Value> {
+        let entity = entity_name(entity)?;
+        let id = id.parse().ok()?;
+        self.state().entities.get(entity)?.get(&id).cloned()
+    }
+
+    /// Returns all stored entities of the given type, ordered by ID
+    #[must_use]
+    pub fn entities(&self, entity: &str) -> Vec<Value> {
+        entity_name(entity)
+            .and_then(|entity| {
+                Some(
+                    self.state()
+                        .entities
+                        .get(entity)?
+                        .values()
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sets the report returned for the given report name, e.g. `ProfitAndLoss`
+    ///
+    /// Reports without one are returned with a header and no rows.
+    pub fn set_report(&self, name: impl Into<String>, report: Value) {
+        self.state().reports.insert(name.into(), report);
+    }
+
+    /// Answers a request like `QuickBooks` would
+    #[must_use]
+    pub fn handle(&self, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+        let (status, body) = self.route(request);
+        let mut response = Response::new(serde_json::to_vec(&body).unwrap_or_default());
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, RealmState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn route(&self, request: &Request<Vec<u8>>) -> (StatusCode, Value) {
+        let uri = request.uri();
+        let path = uri.path();
+        let params: HashMap<String, String> =
+            query_pairs(uri.query().unwrap_or_default()).collect();
+
+        if path.ends_with("/.well-known/openid_configuration") {
+            let base_url = match (uri.scheme_str(), uri.authority()) {
+                (Some(scheme), Some(authority)) => format!("{scheme}://{authority}"),
+                _ => MOCK_BASE_URL.to_string(),
+            };
+            return (StatusCode::OK, json!(discovery_doc(&base_url)));
+        }
+        if path.ends_with("/v1/openid_connect/userinfo") {
+            return (StatusCode::OK, json!({ "sub": self.realm_id }));
+        }
+
+        let Some(rest) = path
+            .split_once("/v3/company/")
+            .map(|(_, rest)| rest.trim_end_matches('/'))
+        else {
+            return fault_response(&MockFault::unsupported(path));
+        };
+        let (realm_id, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        if realm_id != self.realm_id {
+            return fault_response(&MockFault::authorization());
+        }
+
+        let request_id = params
+            .get("requestid")
+            .filter(|_| request.method() == Method::POST);
+        let mut state = self.state();
+        if let Some(response) = request_id.and_then(|id| state.responses.get(id)) {
+            return response.clone();
+        }
+        let segments: Vec<&str> = rest.split('/').collect();
+        let response = match (request.method(), segments.as_slice()) {
+            (&Method::GET | &Method::POST, ["query"]) => {
+                let query = params
+                    .get("query")
+                    .cloned()
+                    .unwrap_or_else(|| String::from_utf8_lossy(request.body()).into_owned());
+                state
+                    .query(&query)
+                    .map(|response| json!({ "QueryResponse": response }))
+            }
+            (&Method::POST, ["batch"]) => state.batch(request.body()),
+            (&Method::GET, ["reports", name]) => Ok(state.report(name)),
+            (&Method::GET, [entity, id]) => match entity_name(entity) {
+                Some(entity) => state
+                    .read(entity, id)
+                    .map(|object| json!({ entity: object })),
+                None => Err(MockFault::unsupported(path)),
+            },
+            (&Method::POST, [entity]) => match entity_name(entity) {
+                Some(entity) => state.write(entity, &params, request.body()),
+                None => Err(MockFault::unsupported(path)),
+            },
+            _ => Err(MockFault::unsupported(path)),
+        };
+        let response = match response {
+            Ok(mut body) => {
+                body["time"] = json!(timestamp());
+                (StatusCode::OK, body)
+            }
+            Err(fault) => fault_response(&fault),
+        };
+        if let Some(request_id) = request_id {
+            state.responses.insert(request_id.clone(), response.clone());
+        }
+        response
+    }
+}
+
+impl Transport for MockRealm {
+    fn send(&self, request: Request<Vec<u8>>) -> APIResult<Response<Vec<u8>>> {
+        Ok(self.handle(&request))
+    }
+}
+
+impl RealmState {
+    fn read(&self, entity: &'static str, id: &str) -> Result<Value, MockFault> {
+        id.parse()
+            .ok()
+            .and_then(|id: u64| self.entities.get(entity)?.get(&id).cloned())
+            .ok_or_else(MockFault::not_found)
+    }
+
+    fn write(
+        &mut self,
+        entity: &'static str,
+        params: &HashMap<String, String>,
+        body: &[u8],
+    ) -> Result<Value, MockFault> {
+        let object: Map<String, Value> =
+            serde_json::from_slice(body).map_err(|e| MockFault::invalid_body(&e))?;
+        // Updates are sent without an operation, voids as an update including `void`
+        let operation = match (
+            params.get("operation").map(String::as_str),
+            params.get("include").map(String::as_str),
+        ) {
+            (Some("update"), Some("void")) => "void",
+            (None | Some("create"), _) if object.contains_key("Id") => "update",
+            (operation, _) => operation.unwrap_or("create"),
+        };
+        let result = match operation {
+            "create" => self.create(entity, object),
+            "update" => self.update(entity, object),
+            "delete" => self.delete(entity, &object),
+            "void" => self.void(entity, &object),
+            operation => Err(MockFault::unsupported(operation)),
+        };
+        result.map(|object| json!({ entity: object }))
+    }
+
+    fn create(
+        &mut self,
+        entity: &'static str,
+        mut object: Map<String, Value>,
+    ) -> Result<Value, MockFault> {
+        self.check_unique_name(entity, &object, None)?;
+        self.last_id += 1;
+        let now = timestamp();
+        object.insert("Id".into(), json!(self.last_id.to_string()));
+        object.insert("SyncToken".into(), json!("0"));
+        object.insert("domain".into(), json!("QBO"));
+        object.insert("sparse".into(), json!(false));
+        if DEACTIVATABLE.contains(&entity) {
+            object.entry("Active").or_insert(json!(true));
+        }
+        object.insert(
+            "MetaData".into(),
+            json!({ "CreateTime": now, "LastUpdatedTime": now }),
+        );
+        let object = Value::Object(object);
+        self.entities
+            .entry(entity)
+            .or_default()
+            .insert(self.last_id, object.clone());
+        Ok(object)
+    }
+
+    fn update(
+        &mut self,
+        entity: &'static str,
+        mut object: Map<String, Value>,
+    ) -> Result<Value, MockFault> {
+        let id = self.check_current(entity, &object)?;
+        self.check_unique_name(entity, &object, Some(id))?;
+        let stored = self.stored_mut(entity, id);
+        let sparse = object.remove("sparse") == Some(Value::Bool(true));
+        if sparse {
+            stored.extend(object);
+        } else {
+            let meta_data = stored.remove("MetaData");
+            *stored = object;
+            stored.extend(meta_data.map(|m| ("MetaData".to_string(), m)));
+        }
+        Ok(bump(stored))
+    }
+
+    fn void(
+        &mut self,
+        entity: &'static str,
+        object: &Map<String, Value>,
+    ) -> Result<Value, MockFault> {
+        let id = self.check_current(entity, object)?;
+        let stored = self.stored_mut(entity, id);
+        for amount in ["TotalAmt", "Balance"] {
+            if stored.contains_key(amount) {
+                stored.insert(amount.into(), json!(0));
+            }
+        }
+        stored.insert("PrivateNote".into(), json!("Voided"));
+        Ok(bump(stored))
+    }
+
+    fn delete(
+        &mut self,
+        entity: &'static str,
+        object: &Map<String, Value>,
+    ) -> Result<Value, MockFault> {
+        let id = self.check_current(entity, object)?;
+        if let Some(entities) = self.entities.get_mut(entity) {
+            entities.remove(&id);
+        }
+        Ok(json!({ "status": "Deleted", "domain": "QBO", "Id": id.to_string() }))
+    }
+
+    /// Checks that the object has an ID and the current sync token of the stored entity
+    fn check_current(
+        &self,
+        entity: &'static str,
+        object: &Map<String, Value>,
+    ) -> Result<u64, MockFault> {
+        let Some(id) = object.get("Id").and_then(Value::as_str) else {
+            return Err(MockFault::missing("Id"));
+        };
+        let Some(sync_token) = object.get("SyncToken").and_then(Value::as_str) else {
+            return Err(MockFault::missing("SyncToken"));
+        };
+        let id = id.parse().map_err(|_| MockFault::not_found())?;
+        let stored = self
+            .entities
+            .get(entity)
+            .and_then(|entities| entities.get(&id))
+            .ok_or_else(MockFault::not_found)?;
+        if stored["SyncToken"].as_str() != Some(sync_token) {
+            return Err(MockFault::stale_object());
+        }
+        Ok(id)
+    }
+
+    fn stored_mut(&mut self, entity: &'static str, id: u64) -> &mut Map<String, Value> {
+        self.entities
+            .get_mut(entity)
+            .and_then(|entities| entities.get_mut(&id))
+            .and_then(Value::as_object_mut)
+            .expect("Checked entity should be stored as an object")
+    }
+
+    fn check_unique_name(
+        &self,
+        entity: &'static str,
+        object: &Map<String, Value>,
+        id: Option<u64>,
+    ) -> Result<(), MockFault> {
+        let Some(name) = object.get("DisplayName").and_then(Value::as_str) else {
+            return Ok(());
+        };
+        if !NAMED_ENTITIES.contains(&entity) {
+            return Ok(());
+        }
+        let taken = NAMED_ENTITIES
+            .iter()
+            .filter_map(|entity| self.entities.get(entity))
+            .flatten()
+            .any(|(other_id, other)| {
+                Some(*other_id) != id
+                    && other["DisplayName"]
+                        .as_str()
+                        .is_some_and(|other| other.eq_ignore_ascii_case(name))
+            });
+        if taken {
+            return Err(MockFault::duplicate_name());
+        }
+        Ok(())
+    }
+
+    /// Runs a query, returning the contents of its `QueryResponse`
+    fn query(&self, query: &str) -> Result<Value, MockFault> {
+        let query = Query::parse(query).map_err(|reason| MockFault::query_parser(&reason))?;
+        let Some(entity) = entity_name(&query.entity) else {
+            return Err(MockFault::query_parser(&format!(
+                "Invalid context declaration : {}",
+                query.entity
+            )));
+        };
+        // Inactive entities are left out unless the query asks about them
+        let active_only = DEACTIVATABLE.contains(&entity) && !query.filters("Active");
+        let mut matching: Vec<Value> = self
+            .entities
+            .get(entity)
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .filter(|object| !active_only || object["Active"] != json!(false))
+            .filter(|object| query.matches(object))
+            .cloned()
+            .collect();
+        if query.projection == Projection::Count {
+            return Ok(json!({ "totalCount": matching.len() }));
+        }
+        query.sort(&mut matching);
+        let page: Vec<Value> = matching
+            .into_iter()
+            .skip(query.start_position - 1)
+            .take(query.max_results)
+            .map(|object| query.project(object))
+            .collect();
+        if page.is_empty() {
+            return Ok(json!({}));
+        }
+        Ok(json!({
+            entity: page,
+            "startPosition": query.start_position,
+            "maxResults": page.len(),
+        }))
+    }
+
+    fn batch(&mut self, body: &[u8]) -> Result<Value, MockFault> {
+        let request: Value =
+            serde_json::from_slice(body).map_err(|e| MockFault::invalid_body(&e))?;
+        let Some(items) = request["BatchItemRequest"].as_array() else {
+            return Err(MockFault::missing("BatchItemRequest"));
+        };
+        let responses = items
+            .iter()
+            .map(|item| {
+                let mut response = match self.batch_item(item) {
+                    Ok(response) => response,
+                    Err(fault) => json!({ "Fault": fault.to_value() }),
+                };
+                response["bId"] = item["bId"].clone();
+                response
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "BatchItemResponse": responses }))
+    }
+
+    fn batch_item(&mut self, item: &Value) -> Result<Value, MockFault> {
+        if let Some(query) = item["Query"].as_str() {
+            return self
+                .query(query)
+                .map(|response| json!({ "QueryResponse": response }));
+        }
+        let Some((entity, object)) = MOCK_ENTITIES
+            .iter()
+            .find_map(|entity| Some((*entity, item.get(*entity)?)))
+        else {
+            return Err(MockFault::unsupported("batch item without an entity"));
+        };
+        let mut params = HashMap::new();
+        if let Some(operation) = item["operation"].as_str().filter(|op| *op != "create") {
+            params.insert("operation".to_string(), operation.to_string());
+        }
+        let body = serde_json::to_vec(object).map_err(|e| MockFault::invalid_body(&e))?;
+        self.write(entity, &params, &body)
+    }
+
+    fn report(&self, name: &str) -> Value {
+        self.reports.get(name).cloned().unwrap_or_else(|| {
+            json!({
+                "Header": {
+                    "Time": timestamp(),
+                    "ReportName": name,
+                    "Currency": "USD",
+                    "Option": [{ "Name": "NoReportData", "Value": "true" }],
+                },
+                "Columns": { "Column": [] },
+                "Rows": { "Row": [] },
+            })
+        })
+    }
+}
+
+/// Increments the sync token of a stored entity after a change, returning the entity
+fn bump(stored: &mut Map<String, Value>) -> Value {
+    let sync_token = stored
+        .get("SyncToken")
+        .and_then(Value::as_str)
+        .and_then(|token| token.parse::<u64>().ok())
+        .unwrap_or_default()
+        + 1;
+    stored.insert("SyncToken".into(), json!(sync_token.to_string()));
+    stored.insert("sparse".into(), json!(false));
+    if let Some(meta_data) = stored.get_mut("MetaData").and_then(Value::as_object_mut) {
+        meta_data.insert("LastUpdatedTime".into(), json!(timestamp()));
+    }
+    Value::Object(stored.clone())
+}
+
+/// Finds the entity type a name refers to, ignoring case like `QuickBooks` URLs do
+fn entity_name(name: &str) -> Option<&'static str> {
+    MOCK_ENTITIES
+        .iter()
+        .find(|entity| entity.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// Decodes the parameters of a query string
+fn query_pairs(query: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| {
+            let unescape = |s: &str| {
+                decode(&s.replace('+', " "))
+                    .map_or_else(|_| s.to_string(), std::borrow::Cow::into_owned)
+            };
+            (unescape(k), unescape(v))
+        })
+}
+
+fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+/// The discovery document of a mock server at the given base URL
+fn discovery_doc(base_url: &str) -> DiscoveryDoc {
+    let strings = |values: &[&str]| values.iter().map(ToString::to_string).collect();
+    DiscoveryDoc {
+        issuer: base_url.to_string(),
+        authorization_endpoint: format!("{base_url}/connect/oauth2"),
+        token_endpoint: format!("{base_url}/oauth2/v1/tokens/bearer"),
+        userinfo_endpoint: format!("{base_url}/v1/openid_connect/userinfo"),
+        revocation_endpoint: format!("{base_url}/oauth2/v1/tokens/revoke"),
+        jwks_uri: format!("{base_url}/oauth2/v1/keys"),
+        response_types_supported: strings(&["code"]),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: strings(&["RS256"]),
+        scopes_supported: strings(&["openid", "email", "profile", "address", "phone"]),
+        token_endpoint_auth_methods_supported: strings(&[
+            "client_secret_post",
+            "client_secret_basic",
+        ]),
+        claims_supported: strings(&["aud", "exp", "iat", "iss", "realmid", "sub"]),
+    }
+}
+
+/// A fault the realm answers with, shaped like the ones `QuickBooks` sends
+struct MockFault {
+    status: StatusCode,
+    kind: &'static str,
+    code: &'static str,
+    message: &'static str,
+    detail: String,
+}
+
+impl MockFault {
+    fn validation(code: &'static str, message: &'static str, detail: String) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            kind: "ValidationFault",
+            code,
+            message,
+            detail,
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::validation(
+            "610",
+            "Object Not Found",
+            "Object Not Found : Something you're trying to use has been made inactive. Check \
+             the fields with accounts, customers, items, vendors or employees."
+                .to_string(),
+        )
+    }
+
+    fn stale_object() -> Self {
+        Self::validation(
+            crate::functions::conflict::STALE_OBJECT_FAULT_CODE,
+            "Stale Object Error",
+            "Stale Object Error : You and another user were working on this at the same \
+             time. The other user finished before you did, so your work was not saved."
+                .to_string(),
+        )
+    }
+
+    fn missing(param: &str) -> Self {
+        Self::validation(
+            "2020",
+            "Required param missing, need to supply the required value for the API",
+            format!("Required parameter {param} is missing in the request"),
+        )
+    }
+
+    fn duplicate_name() -> Self {
+        Self::validation(
+            "6240",
+            "Duplicate Name Exists Error",
+            "The name supplied already exists. : Another customer, vendor or employee is \
+             already using this name. Please use a different name."
+                .to_string(),
+        )
+    }
+
+    fn invalid_body(error: &serde_json::Error) -> Self {
+        Self::validation(
+            "2010",
+            "Request has invalid or unsupported property",
+            format!("Request has invalid or unsupported property : {error}"),
+        )
+    }
+
+    fn query_parser(reason: &str) -> Self {
+        Self::validation(
+            "4000",
+            "Error parsing query",
+            format!("QueryParserError: {reason}"),
+        )
+    }
+
+    fn unsupported(operation: &str) -> Self {
+        Self::validation(
+            "500",
+            "Unsupported Operation",
+            format!("Operation {operation} is not supported."),
+        )
+    }
+
+    fn authorization() -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            kind: "AUTHENTICATION",
+            code: "003100",
+            message: "message=ApplicationAuthorizationFailed; errorCode=003100; statusCode=403",
+            detail: String::new(),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "Error": [{
+                "Message": self.message,
+                "Detail": self.detail,
+                "code": self.code,
+                "element": "",
+            }],
+            "type": self.kind,
+        })
+    }
+}
+
+/// Entity types `QuickBooks` makes active when created, unless told otherwise
+const DEACTIVATABLE: &[&str] = &["Account", "Customer", "Employee", "Item", "Vendor"];
+
+fn fault_response(fault: &MockFault) -> (StatusCode, Value) {
+    (
+        fault.status,
+        json!({ "Fault": fault.to_value(), "time": timestamp() }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quickbooks_types::{Attachable, Customer, Vendor};
+
+    use super::{MockRealm, MockServer};
+    use crate::{
+        batch::{BatchIterator, QBBatchOperation, QBBatchResponseData, QBResource},
+        functions::{
+            conflict::ConflictPolicy, create::QBCreate, delete::QBDelete, query::QBQuery,
+            read::qb_get_single, update::QBUpdate,
+        },
+        mirror::{MemoryStore, Mirror},
+    };
+
+    fn customer(name: &str) -> Customer {
+        Customer {
+            display_name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_crud() {
+        let realm = Arc::new(MockRealm::new("crud"));
+        let qb = realm.context("token").unwrap();
+
+        let created = customer("Acme").create(&qb).unwrap();
+        let id = created.id.clone().unwrap();
+        assert_eq!(created.sync_token.as_deref(), Some("0"));
+
+        let mut changed = created.clone();
+        changed.display_name = Some("Acme Corp".to_string());
+        let updated = changed.sparse_update(&qb).unwrap();
+        assert_eq!(updated.sync_token.as_deref(), Some("1"));
+
+        let read: Customer = qb_get_single(&id, &qb).unwrap();
+        assert_eq!(read.display_name.as_deref(), Some("Acme Corp"));
+
+        // The first version's sync token is outdated now
+        let err = created.update(&qb).unwrap_err();
+        assert!(err.is_stale_object());
+
+        // Customers can't be deleted, only made inactive
+        let attachable = Attachable {
+            note: Some("Receipt".to_string()),
+            ..Default::default()
+        }
+        .create(&qb)
+        .unwrap();
+        let id = attachable.id.clone().unwrap();
+        let deleted = attachable.delete(&qb).unwrap();
+        assert_eq!(deleted.status, "Deleted");
+        assert!(realm.entity("Attachable", &id).is_none());
+        assert!(qb_get_single::<Attachable>(&id, &qb).is_err());
+    }
+
+    #[test]
+    fn test_update_conflict() {
+        let realm = Arc::new(MockRealm::new("conflict"));
+        let qb = realm
+            .context("token")
+            .unwrap()
+            .with_conflict_policy(ConflictPolicy::new(1));
+
+        let created = customer("Acme").create(&qb).unwrap();
+        let mut theirs = created.clone();
+        theirs.display_name = Some("Acme Corp".to_string());
+        theirs.update(&qb).unwrap();
+
+        // Resending our copy would overwrite their change
+        let mut ours = created;
+        ours.display_name = Some("Acme Inc".to_string());
+        assert!(ours.update(&qb).unwrap_err().is_stale_object());
+        assert!(ours.sparse_update(&qb).unwrap_err().is_stale_object());
+
+        let merged = ours
+            .update_with_merge(&qb, |ours, mut latest| {
+                latest.display_name.clone_from(&ours.display_name);
+                latest
+            })
+            .unwrap();
+        assert_eq!(merged.display_name.as_deref(), Some("Acme Inc"));
+        assert_eq!(merged.sync_token.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_query() {
+        let realm = Arc::new(MockRealm::new("query"));
+        let qb = realm.context("token").unwrap();
+        for name in ["Alpha", "Beta", "Gamma"] {
+            customer(name).create(&qb).unwrap();
+        }
+        assert!(customer("alpha").create(&qb).is_err());
+
+        let found = Customer::query(
+            "WHERE DisplayName LIKE '%a' ORDERBY DisplayName DESC",
+            None,
+            &qb,
+        )
+        .unwrap();
+        let names: Vec<_> = found
+            .iter()
+            .filter_map(|c| c.display_name.as_deref())
+            .collect();
+        assert_eq!(names, ["Gamma", "Beta", "Alpha"]);
+        assert_eq!(Customer::count("WHERE Id IN ('1', '3')", &qb).unwrap(), 2);
+        assert_eq!(Customer::query_all("", &qb).count(), 3);
+        assert!(Customer::query("WHERE", None, &qb).is_err());
+    }
+
+    #[test]
+    fn test_mirror_loads_inactive() {
+        let realm = Arc::new(MockRealm::new("mirror"));
+        let qb = realm.context("token").unwrap();
+        for name in ["Acme", "Globex", "Initech"] {
+            customer(name).create(&qb).unwrap();
+        }
+        let created = customer("Umbrella").create(&qb).unwrap();
+        let mut inactive = created.clone();
+        inactive.active = Some(false);
+        inactive.sparse_update(&qb).unwrap();
+        assert_eq!(Customer::query_all("", &qb).count(), 3);
+
+        let mut mirror = Mirror::new(MemoryStore::new()).entity::<Customer>();
+        mirror.sync(&qb).unwrap();
+        let mut ids: Vec<_> = mirror
+            .all::<Customer>()
+            .unwrap()
+            .into_iter()
+            .filter_map(|customer| customer.id)
+            .collect();
+        ids.sort_unstable();
+        assert_eq!(ids, ["1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_batch() {
+        let realm = Arc::new(MockRealm::new("batch"));
+        let qb = realm.context("token").unwrap();
+        let vendor = Vendor {
+            display_name: Some("Smith Family Store".to_string()),
+            ..Default::default()
+        };
+        let results = [
+            QBBatchOperation::create(QBResource::Vendor(vendor)),
+            QBBatchOperation::query("select * from Invoice where Id = '42'"),
+        ]
+        .batch(&qb)
+        .unwrap();
+        assert!(matches!(results[0].1, QBBatchResponseData::Item(_)));
+        assert!(matches!(
+            results[1].1,
+            QBBatchResponseData::QueryResponse(_)
+        ));
+        assert_eq!(realm.entities("Vendor").len(), 1);
+    }
+
+    #[test]
+    fn test_server() {
+        let server = MockServer::start(Arc::new(MockRealm::new("server"))).unwrap();
+        let qb = server.context("token").unwrap();
+        assert!(qb.check_authorized().unwrap());
+        let created = customer("Acme").create(&qb).unwrap();
+        let read: Customer = qb_get_single(created.id.as_deref().unwrap(), &qb).unwrap();
+        assert_eq!(read.display_name.as_deref(), Some("Acme"));
+    }
+}
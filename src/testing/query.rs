@@ -0,0 +1,444 @@
+//! The subset of the `QuickBooks` query language [`MockRealm`](super::MockRealm) understands.
+//!
+//! `SELECT * | COUNT(*) | fields FROM Entity`, followed by `WHERE` conditions joined with
+//! `AND` (`=`, `!=`, `<`, `<=`, `>`, `>=`, `IN` and `LIKE`), `ORDERBY`, `STARTPOSITION`
+//! and `MAXRESULTS`, all case insensitive.
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// The number of entities a query returns without `MAXRESULTS`
+const DEFAULT_MAX_RESULTS: usize = 100;
+
+/// The most entities a query can return
+const MAX_MAX_RESULTS: usize = 1000;
+
+/// What a query selects
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Projection {
+    All,
+    Count,
+    Fields(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Compare(String, Op, Literal),
+    In(String, Vec<Literal>),
+    Like(String, String),
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Query {
+    pub(crate) entity: String,
+    pub(crate) projection: Projection,
+    conditions: Vec<Condition>,
+    order_by: Vec<(String, bool)>,
+    pub(crate) start_position: usize,
+    pub(crate) max_results: usize,
+}
+
+impl Query {
+    /// Parses a query, returning the reason it can't be parsed on failure
+    pub(crate) fn parse(query: &str) -> Result<Self, String> {
+        Parser {
+            tokens: tokenize(query)?,
+            pos: 0,
+        }
+        .query()
+    }
+
+    /// Returns `true` if the entity satisfies every condition of the query
+    pub(crate) fn matches(&self, entity: &Value) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Compare(field, op, literal) => {
+                field_value(entity, field).is_some_and(|value| compare(value, *op, literal))
+            }
+            Condition::In(field, literals) => field_value(entity, field)
+                .is_some_and(|value| literals.iter().any(|l| compare(value, Op::Eq, l))),
+            Condition::Like(field, pattern) => field_value(entity, field)
+                .and_then(value_string)
+                .is_some_and(|value| like(&value.to_lowercase(), &pattern.to_lowercase())),
+        })
+    }
+
+    /// Returns `true` if one of the conditions of the query is on the field
+    pub(crate) fn filters(&self, field: &str) -> bool {
+        self.conditions.iter().any(|condition| {
+            let (Condition::Compare(name, ..) | Condition::In(name, _) | Condition::Like(name, _)) =
+                condition;
+            name.eq_ignore_ascii_case(field)
+        })
+    }
+
+    /// Sorts the entities by the `ORDERBY` fields of the query
+    pub(crate) fn sort(&self, entities: &mut [Value]) {
+        if self.order_by.is_empty() {
+            return;
+        }
+        entities.sort_by(|a, b| {
+            for (field, descending) in &self.order_by {
+                let ordering = order(field_value(a, field), field_value(b, field));
+                let ordering = if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+
+    /// Keeps only the selected fields of the entity, along with its `Id`
+    pub(crate) fn project(&self, entity: Value) -> Value {
+        let Projection::Fields(fields) = &self.projection else {
+            return entity;
+        };
+        let Value::Object(mut map) = entity else {
+            return entity;
+        };
+        map.retain(|key, _| key == "Id" || fields.iter().any(|f| f.eq_ignore_ascii_case(key)));
+        Value::Object(map)
+    }
+}
+
+/// Looks up a field of an entity, following dots into nested objects
+fn field_value<'a>(entity: &'a Value, field: &str) -> Option<&'a Value> {
+    field.split('.').try_fold(entity, |value, key| {
+        value
+            .as_object()?
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    })
+}
+
+fn value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn value_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    let ordering = match literal {
+        Literal::Bool(b) => {
+            let Some(value) = value.as_bool().or_else(|| value.as_str()?.parse().ok()) else {
+                return false;
+            };
+            value.cmp(b)
+        }
+        Literal::Number(n) => match value_number(value).and_then(|v| v.partial_cmp(n)) {
+            Some(ordering) => ordering,
+            None => return false,
+        },
+        Literal::String(s) => {
+            let Some(value_str) = value_string(value) else {
+                return false;
+            };
+            // Amounts are usually compared against quoted numbers
+            match (value_number(value), s.parse::<f64>()) {
+                (Some(v), Ok(n)) => match v.partial_cmp(&n) {
+                    Some(ordering) => ordering,
+                    None => return false,
+                },
+                _ => value_str.cmp(s),
+            }
+        }
+    };
+    match op {
+        Op::Eq => ordering == Ordering::Equal,
+        Op::Ne => ordering != Ordering::Equal,
+        Op::Lt => ordering == Ordering::Less,
+        Op::Le => ordering != Ordering::Greater,
+        Op::Gt => ordering == Ordering::Greater,
+        Op::Ge => ordering != Ordering::Less,
+    }
+}
+
+/// Orders two field values, numerically if both are numbers, missing values first
+fn order(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => match (value_number(a), value_number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ => value_string(a).cmp(&value_string(b)),
+        },
+    }
+}
+
+/// Matches a value against a `LIKE` pattern, where `%` matches any characters
+fn like(value: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('%');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(Op),
+    Comma,
+    Open,
+    Close,
+    Star,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            ',' => tokens.push(Token::Comma),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '*' => tokens.push(Token::Star),
+            '=' => tokens.push(Token::Op(Op::Eq)),
+            '!' if chars.next_if_eq(&'=').is_some() => tokens.push(Token::Op(Op::Ne)),
+            '<' if chars.next_if_eq(&'=').is_some() => tokens.push(Token::Op(Op::Le)),
+            '<' if chars.next_if_eq(&'>').is_some() => tokens.push(Token::Op(Op::Ne)),
+            '<' => tokens.push(Token::Op(Op::Lt)),
+            '>' if chars.next_if_eq(&'=').is_some() => tokens.push(Token::Op(Op::Ge)),
+            '>' => tokens.push(Token::Op(Op::Gt)),
+            '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => s.extend(chars.next()),
+                        Some('\'') => break,
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | ':') => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | ':'))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => return Err(format!("unexpected character '{c}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", keyword.to_uppercase()))
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), String> {
+        if self.bump().as_ref() == Some(token) {
+            Ok(())
+        } else {
+            Err(format!("expected {token:?}"))
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.bump() {
+            Some(Token::Word(w)) => Ok(w),
+            other => Err(format!("expected a name, found {other:?}")),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        self.word()?
+            .parse()
+            .map_err(|_| "expected a number".to_string())
+    }
+
+    fn query(mut self) -> Result<Query, String> {
+        self.expect_keyword("select")?;
+        let projection = self.projection()?;
+        self.expect_keyword("from")?;
+        let entity = self.word()?;
+
+        let mut conditions = Vec::new();
+        if self.eat_keyword("where") {
+            conditions.push(self.condition()?);
+            while self.eat_keyword("and") {
+                conditions.push(self.condition()?);
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("orderby") || (self.eat_keyword("order") && self.eat_keyword("by")) {
+            loop {
+                let field = self.word()?;
+                let descending = self.eat_keyword("desc");
+                if !descending {
+                    self.eat_keyword("asc");
+                }
+                order_by.push((field, descending));
+                if self.tokens.get(self.pos) != Some(&Token::Comma) {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+
+        let mut start_position = 1;
+        let mut max_results = DEFAULT_MAX_RESULTS;
+        loop {
+            if self.eat_keyword("startposition") {
+                start_position = self.number()?.max(1);
+            } else if self.eat_keyword("maxresults") {
+                max_results = self.number()?.min(MAX_MAX_RESULTS);
+            } else {
+                break;
+            }
+        }
+
+        if let Some(token) = self.tokens.get(self.pos) {
+            return Err(format!("unexpected {token:?}"));
+        }
+        Ok(Query {
+            entity,
+            projection,
+            conditions,
+            order_by,
+            start_position,
+            max_results,
+        })
+    }
+
+    fn projection(&mut self) -> Result<Projection, String> {
+        if self.tokens.get(self.pos) == Some(&Token::Star) {
+            self.pos += 1;
+            return Ok(Projection::All);
+        }
+        if self.peek_keyword("count") && self.tokens.get(self.pos + 1) == Some(&Token::Open) {
+            self.pos += 2;
+            self.expect(&Token::Star)?;
+            self.expect(&Token::Close)?;
+            return Ok(Projection::Count);
+        }
+        let mut fields = vec![self.word()?];
+        while self.tokens.get(self.pos) == Some(&Token::Comma) {
+            self.pos += 1;
+            fields.push(self.word()?);
+        }
+        Ok(Projection::Fields(fields))
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let field = self.word()?;
+        if self.eat_keyword("in") {
+            self.expect(&Token::Open)?;
+            let mut literals = vec![self.literal()?];
+            while self.tokens.get(self.pos) == Some(&Token::Comma) {
+                self.pos += 1;
+                literals.push(self.literal()?);
+            }
+            self.expect(&Token::Close)?;
+            return Ok(Condition::In(field, literals));
+        }
+        if self.eat_keyword("like") {
+            return match self.bump() {
+                Some(Token::Str(pattern)) => Ok(Condition::Like(field, pattern)),
+                _ => Err("expected a quoted LIKE pattern".to_string()),
+            };
+        }
+        match self.bump() {
+            Some(Token::Op(op)) => Ok(Condition::Compare(field, op, self.literal()?)),
+            other => Err(format!(
+                "expected an operator after {field}, found {other:?}"
+            )),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal, String> {
+        match self.bump() {
+            Some(Token::Str(s)) => Ok(Literal::String(s)),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("true") => Ok(Literal::Bool(true)),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("false") => Ok(Literal::Bool(false)),
+            Some(Token::Word(w)) => w
+                .parse()
+                .map(Literal::Number)
+                .map_err(|_| format!("expected a value, found {w}")),
+            other => Err(format!("expected a value, found {other:?}")),
+        }
+    }
+}
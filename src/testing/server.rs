@@ -0,0 +1,175 @@
+//! Serving a [`MockRealm`] over HTTP on a local TCP listener.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use ureq::http::{Method, Request};
+
+use super::MockRealm;
+use crate::{limiter::LimiterRegistry, APIResult, Endpoints, Environment, QBContext};
+
+/// Serves a [`MockRealm`] over HTTP on `127.0.0.1` until dropped.
+///
+/// Each connection is answered on its own thread and closed after one request.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use quick_oxibooks::testing::{MockRealm, MockServer};
+///
+/// let server = MockServer::start(Arc::new(MockRealm::new("123"))).unwrap();
+/// let qb = server.context("access_token").unwrap();
+/// assert!(qb.check_authorized().unwrap());
+/// ```
+pub struct MockServer {
+    realm: Arc<MockRealm>,
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts serving the realm on a free port
+    ///
+    /// # Errors
+    ///
+    /// I/O errors when binding the listener
+    pub fn start(realm: Arc<MockRealm>) -> APIResult<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let realm = Arc::clone(&realm);
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let realm = Arc::clone(&realm);
+                    std::thread::spawn(move || {
+                        if let Err(_e) = serve(&realm, stream) {
+                            #[cfg(feature = "logging")]
+                            log::warn!("Mock server failed to answer a request: {_e}");
+                        }
+                    });
+                }
+            })
+        };
+        Ok(Self {
+            realm,
+            addr,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// The URL the server is reachable at, e.g. `http://127.0.0.1:54321`
+    #[must_use]
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The endpoints of the server
+    #[must_use]
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::from_base_url(&self.base_url())
+    }
+
+    /// The realm the server answers with
+    #[must_use]
+    pub fn realm(&self) -> &Arc<MockRealm> {
+        &self.realm
+    }
+
+    /// Creates a context for the realm's company that sends its requests to the
+    /// server, fetching the discovery document from it
+    ///
+    /// Like [`MockRealm::context`], the context has a [`LimiterRegistry`] of its own.
+    ///
+    /// # Errors
+    ///
+    /// Network errors when fetching the discovery document
+    pub fn context(&self, access_token: impl Into<String>) -> APIResult<QBContext> {
+        QBContext::builder(
+            Environment::SANDBOX,
+            self.realm.realm_id().to_string(),
+            access_token.into(),
+        )
+        .endpoints(self.endpoints())
+        .build()
+        .map(|qb| qb.with_limiter_registry(&LimiterRegistry::default()))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // Wakes the listener so it sees it should stop
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reads one HTTP/1.1 request from the stream and writes the realm's response
+fn serve(realm: &MockRealm, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(());
+    };
+    let method = Method::from_bytes(method.as_bytes()).map_err(std::io::Error::other)?;
+    // The line is reused for the headers
+    let target = target.to_string();
+
+    let mut request = Request::builder().method(method);
+    let mut host = String::from("127.0.0.1");
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("host") {
+            host = value.to_string();
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().map_err(std::io::Error::other)?;
+        }
+        request = request.header(name, value);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let request = request
+        .uri(format!("http://{host}{target}"))
+        .body(body)
+        .map_err(std::io::Error::other)?;
+    let response = realm.handle(&request);
+    let status = response.status();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or_default(),
+        response.body().len(),
+    )?;
+    stream.write_all(response.body())?;
+    stream.flush()
+}
@@ -0,0 +1,207 @@
+//! # `QuickBooks` Webhook Notifications
+//!
+//! This module parses the webhook notifications `QuickBooks` sends when entities
+//! change, and verifies the `intuit-signature` header they are signed with.
+//!
+//! The signature is the base64 encoded HMAC-SHA256 of the raw request body, keyed
+//! with the verifier token shown for the app on the Intuit developer portal. Always
+//! verify against the raw body bytes, before they are parsed or re-serialized.
+//!
+//! ## Usage Example
+//!
+//! ```no_run
+//! use quick_oxibooks::{QBContext, Environment};
+//! use quick_oxibooks::webhooks::{WebhookNotification, SIGNATURE_HEADER};
+//! use quickbooks_types::Customer;
+//!
+//! let qb_context = QBContext::new(
+//!     Environment::SANDBOX,
+//!     "company_id".to_string(),
+//!     "access_token".to_string(),
+//! ).unwrap();
+//!
+//! // The raw body and the `intuit-signature` header of the webhook request
+//! let body: &[u8] = b"...";
+//! let signature = "...";
+//!
+//! let notification = WebhookNotification::from_signed(body, signature, "verifier_token").unwrap();
+//! for customer in notification.fetch_changed::<Customer>(&qb_context).unwrap() {
+//!     println!("Customer changed: {:?}", customer.display_name);
+//! }
+//! ```
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use quickbooks_types::QBItem;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{error::APIErrorInner, functions::read::qb_get_single, APIResult, QBContext};
+
+/// Name of the header `QuickBooks` puts the webhook signature in
+pub const SIGNATURE_HEADER: &str = "intuit-signature";
+
+/// Verifies the `intuit-signature` of a webhook request
+///
+/// `payload` must be the raw request body, `signature` the value of the
+/// [`SIGNATURE_HEADER`] header and `verifier_token` the app's webhook verifier token.
+/// The comparison runs in constant time.
+#[must_use]
+pub fn verify_signature(payload: &[u8], signature: &str, verifier_token: &str) -> bool {
+    let Ok(signature) = base64::engine::general_purpose::STANDARD.decode(signature.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(verifier_token.as_bytes()) else {
+        return false;
+    };
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// The body of a webhook request
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookNotification {
+    pub event_notifications: Vec<WebhookEvent>,
+}
+
+/// The changes to the entities of a single company
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookEvent {
+    /// ID of the company the changes were made in
+    pub realm_id: String,
+    pub data_change_event: DataChangeEvent,
+}
+
+/// The list of changed entities in a [`WebhookEvent`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataChangeEvent {
+    pub entities: Vec<EntityChange>,
+}
+
+/// A single changed entity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityChange {
+    /// Name of the entity type, e.g. `Customer`
+    pub name: String,
+    /// ID of the changed entity
+    pub id: String,
+    pub operation: WebhookOperation,
+    /// When the change was made, as sent by `QuickBooks`
+    #[serde(default)]
+    pub last_updated: Option<String>,
+    /// ID of the entity that was merged into this one, for `Merge` operations
+    #[serde(default)]
+    pub deleted_id: Option<String>,
+}
+
+impl EntityChange {
+    /// Returns `true` if the change is for the entity type `T`
+    #[must_use]
+    pub fn is<T: QBItem>(&self) -> bool {
+        self.name == T::name()
+    }
+
+    /// Returns `true` if the entity no longer exists after the change
+    #[must_use]
+    pub fn is_deletion(&self) -> bool {
+        self.operation == WebhookOperation::Delete
+    }
+
+    /// Parses [`EntityChange::last_updated`], which `QuickBooks` sends
+    /// both with and without a colon in the UTC offset
+    #[must_use]
+    pub fn last_updated(&self) -> Option<DateTime<Utc>> {
+        let time = self.last_updated.as_deref()?;
+        DateTime::parse_from_rfc3339(time)
+            .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f%z"))
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// The operation that changed an entity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookOperation {
+    Create,
+    Update,
+    Delete,
+    Merge,
+    Void,
+    Emailed,
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl WebhookNotification {
+    /// Parses a webhook request body after verifying its signature
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidWebhookSignature`: The signature doesn't match the body
+    /// - `JsonError`: The body is not a valid webhook notification
+    pub fn from_signed(payload: &[u8], signature: &str, verifier_token: &str) -> APIResult<Self> {
+        if !verify_signature(payload, signature, verifier_token) {
+            return Err(APIErrorInner::InvalidWebhookSignature.into());
+        }
+        Ok(serde_json::from_slice(payload)?)
+    }
+
+    /// Iterates over the changes made in the company of the given realm ID
+    pub fn changes_for<'a>(&'a self, realm_id: &'a str) -> impl Iterator<Item = &'a EntityChange> {
+        self.event_notifications
+            .iter()
+            .filter(move |event| event.realm_id == realm_id)
+            .flat_map(|event| &event.data_change_event.entities)
+    }
+
+    /// Reads the entities of type `T` that were changed in the context's company
+    /// with [`qb_get_single`], skipping deleted ones
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the reads fail
+    pub fn fetch_changed<T: QBItem>(&self, qb: &QBContext) -> APIResult<Vec<T>> {
+        let mut ids = Vec::new();
+        for change in self.changes_for(&qb.company_id) {
+            if change.is::<T>() && !change.is_deletion() && !ids.contains(&change.id.as_str()) {
+                ids.push(change.id.as_str());
+            }
+        }
+        ids.into_iter().map(|id| qb_get_single(id, qb)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickbooks_types::Customer;
+
+    use super::{verify_signature, WebhookNotification, WebhookOperation};
+
+    const PAYLOAD: &[u8] = br#"{"eventNotifications":[{"realmId":"123","dataChangeEvent":{"entities":[{"name":"Customer","id":"1","operation":"Create","lastUpdated":"2015-10-05T14:42:19-0700"}]}}]}"#;
+    const SIGNATURE: &str = "gMuoJfB+UH/XlNLI5XYUqX6Sgc2BFu2G0Un3xDIlBek=";
+
+    #[test]
+    fn test_verify_signature() {
+        assert!(verify_signature(PAYLOAD, SIGNATURE, "verifier-token"));
+        assert!(!verify_signature(PAYLOAD, SIGNATURE, "other-token"));
+        assert!(!verify_signature(b"{}", SIGNATURE, "verifier-token"));
+        assert!(!verify_signature(PAYLOAD, "not base64!", "verifier-token"));
+    }
+
+    #[test]
+    fn test_parse_notification() {
+        let notification =
+            WebhookNotification::from_signed(PAYLOAD, SIGNATURE, "verifier-token").unwrap();
+        let changes: Vec<_> = notification.changes_for("123").collect();
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is::<Customer>());
+        assert_eq!(changes[0].operation, WebhookOperation::Create);
+        assert!(changes[0].last_updated().is_some());
+        assert_eq!(notification.changes_for("456").count(), 0);
+    }
+}